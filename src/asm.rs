@@ -0,0 +1,271 @@
+use std::collections::HashMap;
+
+use crate::opcodes::{self, Mode};
+
+#[cfg(test)]
+mod asm_test;
+
+// A two pass assembler for the dialect used by Easy6502:
+//
+//   define  sysRandom  $fe   ; constants
+//   loop:                    ; labels, always assembled as 16 bit addresses
+//     lda #<table            ; `<` / `>` take the low / high byte
+//     sta ($00),y
+//     bne loop
+//     dcb $01, $02, 3        ; raw bytes
+//
+// Operands that only use numbers and defines and fit in a byte pick the zero
+// page form, just like the original.
+pub fn assemble(source: &str, origin: u16) -> Result<Vec<u8>, String> {
+    let lines = parse(source)?;
+
+    let mut defines = HashMap::new();
+    for line in &lines {
+        if let Statement::Define(name, expr) = &line.statement {
+            let value = eval(expr, &defines, &HashMap::new(), line.number)?;
+            defines.insert(name.to_ascii_lowercase(), value.value);
+        }
+    }
+
+    // first pass lays out the labels, the second one emits with all of them known
+    let (labels, _) = emit(&lines, origin, &defines, None)?;
+    let (_, code) = emit(&lines, origin, &defines, Some(&labels))?;
+    Ok(code)
+}
+
+enum Statement<'a> {
+    Empty,
+    Define(&'a str, &'a str),
+    Bytes(Vec<&'a str>),
+    Instruction(&'a str, &'a str),
+}
+
+struct Line<'a> {
+    number: usize,
+    label: Option<&'a str>,
+    statement: Statement<'a>,
+}
+
+fn parse(source: &str) -> Result<Vec<Line<'_>>, String> {
+    let mut lines = Vec::new();
+    for (index, raw) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = raw.split(';').next().unwrap().trim();
+
+        let mut label = None;
+        if let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+            if !is_identifier(name) {
+                return Err(format!("line {}: invalid label `{}`", number, name));
+            }
+            label = Some(name);
+            text = text[colon + 1..].trim();
+        }
+
+        let (word, rest) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], text[split..].trim()),
+            None => (text, ""),
+        };
+        let statement = if word.is_empty() {
+            Statement::Empty
+        } else if word.eq_ignore_ascii_case("define") {
+            let mut parts = rest.splitn(2, char::is_whitespace);
+            match (parts.next(), parts.next()) {
+                (Some(name), Some(value)) if is_identifier(name) => {
+                    Statement::Define(name, value.trim())
+                }
+                _ => return Err(format!("line {}: expected `define <name> <value>`", number)),
+            }
+        } else if word.eq_ignore_ascii_case("dcb") {
+            Statement::Bytes(rest.split(',').map(str::trim).collect())
+        } else if opcodes::is_mnemonic(word) {
+            Statement::Instruction(word, rest)
+        } else {
+            return Err(format!("line {}: unknown instruction `{}`", number, word));
+        };
+        lines.push(Line {
+            number,
+            label,
+            statement,
+        });
+    }
+    Ok(lines)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// `labels` is None during the first pass, when they're still being collected
+fn emit(
+    lines: &[Line],
+    origin: u16,
+    defines: &HashMap<String, i64>,
+    labels: Option<&HashMap<String, i64>>,
+) -> Result<(HashMap<String, i64>, Vec<u8>), String> {
+    let mut found = HashMap::new();
+    let mut code = Vec::new();
+    let no_labels = HashMap::new();
+
+    for line in lines {
+        let pc = origin as i64 + code.len() as i64;
+        if let Some(label) = line.label {
+            if found.insert(label.to_ascii_lowercase(), pc).is_some() {
+                return Err(format!("line {}: label `{}` defined twice", line.number, label));
+            }
+        }
+        let resolve = |expr: &str| -> Result<Value, String> {
+            match labels {
+                Some(labels) => eval(expr, defines, labels, line.number),
+                None => Ok(eval(expr, defines, &no_labels, line.number)
+                    .unwrap_or(Value { value: 0, uses_label: true })),
+            }
+        };
+
+        match &line.statement {
+            Statement::Empty | Statement::Define(..) => {}
+            Statement::Bytes(values) => {
+                for value in values {
+                    code.push(resolve(value)?.value as u8);
+                }
+            }
+            Statement::Instruction(mnemonic, operand) => {
+                let (mode, expr) = operand_mode(mnemonic, operand, &resolve, line.number)?;
+                let op = opcodes::find(mnemonic, mode).ok_or_else(|| {
+                    format!("line {}: {} does not support {:?} addressing", line.number, mnemonic, mode)
+                })?;
+                code.push(op.code);
+                let value = match expr {
+                    Some(expr) => resolve(expr)?.value,
+                    None => 0,
+                };
+                match mode.size() {
+                    2 if mode == Mode::Relative => {
+                        let offset = value - (pc + 2);
+                        if labels.is_some() && !(-128..=127).contains(&offset) {
+                            return Err(format!("line {}: branch target out of range", line.number));
+                        }
+                        code.push(offset as u8);
+                    }
+                    2 => code.push(value as u8),
+                    3 => code.extend_from_slice(&(value as u16).to_le_bytes()),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok((found, code))
+}
+
+fn operand_mode<'a>(
+    mnemonic: &str,
+    operand: &'a str,
+    resolve: &dyn Fn(&str) -> Result<Value, String>,
+    number: usize,
+) -> Result<(Mode, Option<&'a str>), String> {
+    let has = |mode| opcodes::find(mnemonic, mode).is_some();
+    let upper = operand.to_ascii_uppercase().replace(' ', "");
+
+    if operand.is_empty() {
+        let mode = if has(Mode::Accumulator) { Mode::Accumulator } else { Mode::Implied };
+        return Ok((mode, None));
+    }
+    if upper == "A" && has(Mode::Accumulator) {
+        return Ok((Mode::Accumulator, None));
+    }
+    if let Some(expr) = operand.strip_prefix('#') {
+        return Ok((Mode::Immediate, Some(expr.trim())));
+    }
+    if has(Mode::Relative) {
+        return Ok((Mode::Relative, Some(operand)));
+    }
+    if upper.starts_with('(') {
+        let inner = operand.trim_start_matches('(').trim();
+        if upper.ends_with(",X)") {
+            let expr = inner[..inner.rfind(',').unwrap()].trim();
+            return Ok((Mode::IndirectX, Some(expr)));
+        }
+        if upper.ends_with("),Y") {
+            let expr = inner[..inner.find(')').unwrap()].trim();
+            return Ok((Mode::IndirectY, Some(expr)));
+        }
+        if upper.ends_with(')') {
+            return Ok((Mode::Indirect, Some(inner.trim_end_matches(')').trim())));
+        }
+        return Err(format!("line {}: invalid operand `{}`", number, operand));
+    }
+
+    let (expr, zero_page, absolute) = if upper.ends_with(",X") {
+        (&operand[..operand.rfind(',').unwrap()], Mode::ZeroPageX, Mode::AbsoluteX)
+    } else if upper.ends_with(",Y") {
+        (&operand[..operand.rfind(',').unwrap()], Mode::ZeroPageY, Mode::AbsoluteY)
+    } else {
+        (operand, Mode::ZeroPage, Mode::Absolute)
+    };
+    let expr = expr.trim();
+    let value = resolve(expr)?;
+    let fits = !value.uses_label && (0..0x100).contains(&value.value);
+    if (fits && has(zero_page)) || !has(absolute) {
+        Ok((zero_page, Some(expr)))
+    } else {
+        Ok((absolute, Some(expr)))
+    }
+}
+
+struct Value {
+    value: i64,
+    uses_label: bool,
+}
+
+// numbers (`$ff`, `%1010`, `255`, `'a'`), names, `<` / `>` byte selectors and `+` / `-`
+fn eval(
+    expr: &str,
+    defines: &HashMap<String, i64>,
+    labels: &HashMap<String, i64>,
+    number: usize,
+) -> Result<Value, String> {
+    let expr = expr.trim();
+    if let Some(rest) = expr.strip_prefix('<') {
+        let inner = eval(rest, defines, labels, number)?;
+        return Ok(Value { value: inner.value & 0xff, uses_label: false });
+    }
+    if let Some(rest) = expr.strip_prefix('>') {
+        let inner = eval(rest, defines, labels, number)?;
+        return Ok(Value { value: (inner.value >> 8) & 0xff, uses_label: false });
+    }
+    if let Some(split) = expr.rfind(['+', '-']).filter(|&i| i > 0) {
+        let left = eval(&expr[..split], defines, labels, number)?;
+        let right = eval(&expr[split + 1..], defines, labels, number)?;
+        let value = if expr.as_bytes()[split] == b'+' {
+            left.value + right.value
+        } else {
+            left.value - right.value
+        };
+        return Ok(Value { value, uses_label: left.uses_label || right.uses_label });
+    }
+
+    let invalid = || format!("line {}: invalid value `{}`", number, expr);
+    let constant = |value| Ok(Value { value, uses_label: false });
+    if let Some(hex) = expr.strip_prefix('$') {
+        return constant(i64::from_str_radix(hex, 16).map_err(|_| invalid())?);
+    }
+    if let Some(bin) = expr.strip_prefix('%') {
+        return constant(i64::from_str_radix(bin, 2).map_err(|_| invalid())?);
+    }
+    if expr.len() == 3 && expr.starts_with('\'') && expr.ends_with('\'') {
+        return constant(expr.as_bytes()[1] as i64);
+    }
+    if expr.starts_with(|c: char| c.is_ascii_digit()) {
+        return constant(expr.parse::<i64>().map_err(|_| invalid())?);
+    }
+    let name = expr.to_ascii_lowercase();
+    if let Some(value) = defines.get(&name) {
+        return constant(*value);
+    }
+    match labels.get(&name) {
+        Some(value) => Ok(Value { value: *value, uses_label: true }),
+        None => Err(format!("line {}: unknown symbol `{}`", number, expr)),
+    }
+}
@@ -0,0 +1,107 @@
+use super::*;
+
+#[test]
+fn test_snake_matches_the_original() {
+    let expected: Vec<u8> = vec![
+        0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
+        0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
+        0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
+        0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
+        0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
+        0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
+        0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
+        0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
+        0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
+        0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
+        0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
+        0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
+        0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
+        0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
+        0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
+        0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
+        0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
+        0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
+        0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
+        0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
+        0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
+    ];
+    let code = assemble(crate::easy6502::SNAKE, 0x0600).unwrap();
+    assert_eq!(code, expected);
+}
+
+#[test]
+fn test_addressing_modes() {
+    let source = "
+        lda #$10
+        lda $10
+        lda $10,x
+        lda $1234
+        lda $1234,x
+        lda $1234,y
+        lda ($10,x)
+        lda ($10),y
+        ldx $10,y
+        jmp ($1234)
+        asl
+        asl a
+        rol $10
+        nop
+    ";
+    let code = assemble(source, 0x0600).unwrap();
+    assert_eq!(
+        code,
+        vec![
+            0xa9, 0x10, 0xa5, 0x10, 0xb5, 0x10, 0xad, 0x34, 0x12, 0xbd, 0x34, 0x12, 0xb9, 0x34,
+            0x12, 0xa1, 0x10, 0xb1, 0x10, 0xb6, 0x10, 0x6c, 0x34, 0x12, 0x0a, 0x0a, 0x26, 0x10,
+            0xea,
+        ]
+    );
+}
+
+#[test]
+fn test_labels_and_branches() {
+    let source = "
+        start:
+          ldx #3
+        again: dex
+          bne again
+          beq done
+          jmp start
+        done:
+          lda #<table
+          ldy #>table
+          brk
+        table:
+          dcb $01, 2, %11, 'a'
+    ";
+    let code = assemble(source, 0x0600).unwrap();
+    assert_eq!(
+        code,
+        vec![
+            0xa2, 0x03, 0xca, 0xd0, 0xfd, 0xf0, 0x03, 0x4c, 0x00, 0x06, 0xa9, 0x0f, 0xa0, 0x06,
+            0x00, 0x01, 0x02, 0x03, 0x61,
+        ]
+    );
+}
+
+#[test]
+fn test_labels_are_always_absolute() {
+    let code = assemble("lda zero\nzero: brk", 0x0000).unwrap();
+    assert_eq!(code, vec![0xad, 0x03, 0x00, 0x00]);
+}
+
+#[test]
+fn test_defines_can_be_used_before_they_are_declared() {
+    let code = assemble("sta screen+1\ndefine screen $10", 0x0600).unwrap();
+    assert_eq!(code, vec![0x85, 0x11]);
+}
+
+#[test]
+fn test_errors_carry_the_line_number() {
+    let err = assemble("nop\nfoo $10", 0x0600).unwrap_err();
+    assert!(err.starts_with("line 2:"), "{}", err);
+    let err = assemble("nop\nlda missing", 0x0600).unwrap_err();
+    assert!(err.contains("unknown symbol"), "{}", err);
+    let err = assemble("stx $1234,x", 0x0600).unwrap_err();
+    assert!(err.contains("does not support"), "{}", err);
+}
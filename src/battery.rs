@@ -0,0 +1,60 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::cpu::CPU;
+
+#[cfg(test)]
+mod battery_test;
+
+// frames between writes of a changed save RAM, about five seconds
+pub const FLUSH_INTERVAL: usize = 300;
+
+// keeps the $6000-$7fff PRG-RAM of battery-backed carts in a `.sav` next to the ROM
+pub struct BatterySave {
+    path: PathBuf,
+    frames: usize,
+}
+
+impl BatterySave {
+    // loads an existing save, returns None when the cart has no battery
+    pub fn open(program: &Path, cpu: &mut CPU) -> Result<Option<BatterySave>, String> {
+        if !cpu.bus.has_battery() {
+            return Ok(None);
+        }
+        let path = program.with_extension("sav");
+        match fs::read(&path) {
+            Ok(data) => cpu
+                .bus
+                .load_prg_ram(&data)
+                .map_err(|e| format!("can't load {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("can't read {}: {}", path.display(), e)),
+        }
+        Ok(Some(BatterySave { path, frames: 0 }))
+    }
+
+    // call once per frame, writes the save every FLUSH_INTERVAL frames if it changed
+    pub fn tick(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        self.frames += 1;
+        if self.frames < FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.frames = 0;
+        self.flush(cpu)
+    }
+
+    pub fn flush(&mut self, cpu: &mut CPU) -> Result<(), String> {
+        if !cpu.bus.prg_ram_dirty() {
+            return Ok(());
+        }
+        // write then rename, so a crash mid-write can't eat the old save;
+        // until that worked the RAM stays dirty and the next flush retries
+        let tmp = self.path.with_extension("sav.tmp");
+        fs::write(&tmp, cpu.bus.prg_ram())
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|e| format!("can't write {}: {}", self.path.display(), e))?;
+        cpu.bus.mark_prg_ram_clean();
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use super::*;
+use crate::testutil::Ines;
+
+fn battery_cart() -> CPU {
+    Ines::new(&[]).battery().cpu()
+}
+
+#[test]
+fn test_save_ram_survives_restart() {
+    let dir = std::env::temp_dir().join(format!("nes-battery-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("game.nes");
+
+    let mut cpu = battery_cart();
+    let mut battery = BatterySave::open(&program, &mut cpu).unwrap().unwrap();
+    cpu.mem_write(0x6000, 0x12);
+    cpu.mem_write(0x7fff, 0x34);
+    battery.flush(&mut cpu).unwrap();
+    assert!(dir.join("game.sav").exists());
+
+    let mut cpu = battery_cart();
+    BatterySave::open(&program, &mut cpu).unwrap().unwrap();
+    assert_eq!(cpu.mem_read(0x6000), 0x12);
+    assert_eq!(cpu.mem_read(0x7fff), 0x34);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_flush_skips_unchanged_ram() {
+    let dir = std::env::temp_dir().join(format!("nes-battery-clean-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("game.nes");
+
+    let mut cpu = battery_cart();
+    let mut battery = BatterySave::open(&program, &mut cpu).unwrap().unwrap();
+    battery.flush(&mut cpu).unwrap();
+    assert!(!dir.join("game.sav").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_failed_flush_is_retried() {
+    let dir = std::env::temp_dir().join(format!("nes-battery-retry-{}", std::process::id()));
+    let program = dir.join("game.nes");
+
+    let mut cpu = battery_cart();
+    let mut battery = BatterySave::open(&program, &mut cpu).unwrap().unwrap();
+    cpu.mem_write(0x6000, 0x56);
+    // the directory isn't there yet
+    assert!(battery.flush(&mut cpu).is_err());
+    fs::create_dir_all(&dir).unwrap();
+    battery.flush(&mut cpu).unwrap();
+    assert_eq!(fs::read(dir.join("game.sav")).unwrap()[0], 0x56);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_carts_without_battery_have_no_save() {
+    let mut cpu = CPU::new();
+    assert!(BatterySave::open(Path::new("snake"), &mut cpu).unwrap().is_none());
+}
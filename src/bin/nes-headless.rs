@@ -0,0 +1,21 @@
+use nes::cli::{self, Args};
+use nes::launch::Launch;
+
+fn exit_with_usage(error: String) -> ! {
+    eprintln!("{}\n\n{}", error, cli::USAGE);
+    std::process::exit(2);
+}
+
+// same options as `nes --headless`, without linking SDL
+fn main() {
+    let mut args = vec!["--headless".to_string()];
+    args.extend(std::env::args().skip(1));
+    let args = Args::parse(&args).unwrap_or_else(|e| exit_with_usage(e));
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    let mut launch = Launch::from_args(&args).unwrap_or_else(|e| exit_with_usage(e));
+    let status = launch.run_windowless(&args).expect("the arguments ask for a headless run");
+    std::process::exit(status);
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+
+use crate::machine::Machine;
+
+#[cfg(test)]
+mod blargg_test;
+
+// blargg's test ROMs report through PRG-RAM once $6001-$6003 hold the
+// signature: $6000 is $80 while running, $81 when the ROM wants the reset
+// button pressed, and the result code (0 for success) when done. $6004 on
+// holds a NUL terminated message, the same text the ROM prints on screen.
+//
+// Only ROMs the core can run get anywhere: mapper 0, and no PPU yet, so the
+// ones waiting for vblank or NMIs time out.
+const STATUS: u16 = 0x6000;
+const SIGNATURE: u16 = 0x6001;
+const MESSAGE: u16 = 0x6004;
+const MESSAGE_END: u16 = 0x7fff;
+
+const VALID: [u8; 3] = [0xde, 0xb0, 0x61];
+const RUNNING: u8 = 0x80;
+const RESET_REQUEST: u8 = 0x81;
+
+// the ROMs ask for at least 100ms between the request and the reset
+pub const RESET_DELAY: usize = 60_000;
+pub const MAX_STEPS: usize = 200_000_000;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed { message: String },
+    Failed { code: u8, message: String },
+    // `message` is whatever the ROM had written so far
+    TimedOut { message: String },
+}
+
+impl Outcome {
+    // for #[test]s: Ok with the message on success, Err with it otherwise
+    pub fn into_result(self) -> Result<String, String> {
+        match self {
+            Outcome::Passed { message } => Ok(message),
+            outcome => Err(outcome.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed { message } => write!(f, "passed\n{}", message),
+            Outcome::Failed { code, message } => write!(f, "failed with code {}\n{}", code, message),
+            Outcome::TimedOut { message } => write!(f, "timed out\n{}", message),
+        }
+    }
+}
+
+fn message(machine: &Machine) -> String {
+    let cpu = machine.cpu();
+    let bytes: Vec<u8> = (MESSAGE..=MESSAGE_END)
+        .map(|addr| cpu.peek(addr))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+
+fn status(machine: &Machine) -> Option<u8> {
+    let cpu = machine.cpu();
+    let signature = [cpu.peek(SIGNATURE), cpu.peek(SIGNATURE + 1), cpu.peek(SIGNATURE + 2)];
+    if signature == VALID {
+        Some(cpu.peek(STATUS))
+    } else {
+        None
+    }
+}
+
+pub fn run(rom: &[u8], max_steps: usize) -> Result<Outcome, String> {
+    let mut machine = Machine::nes(rom)?;
+    // set once the request is seen, cleared when the ROM is running again
+    let mut reset_at = None;
+    let mut reset_done = false;
+
+    for step in 0..max_steps {
+        machine.step().map_err(|e| e.to_string())?;
+        match status(&machine) {
+            None | Some(RUNNING) => reset_done = false,
+            Some(RESET_REQUEST) if !reset_done => {
+                let at = *reset_at.get_or_insert(step + RESET_DELAY);
+                if step >= at {
+                    machine.cpu_mut().soft_reset();
+                    reset_at = None;
+                    reset_done = true;
+                }
+            }
+            Some(RESET_REQUEST) => {}
+            Some(0) => return Ok(Outcome::Passed { message: message(&machine) }),
+            Some(code) => return Ok(Outcome::Failed { code, message: message(&machine) }),
+        }
+    }
+    Ok(Outcome::TimedOut { message: message(&machine) })
+}
@@ -0,0 +1,80 @@
+use std::env;
+use std::fs;
+
+use super::*;
+use crate::testutil::{Ines, prg_at_c000};
+
+// a mapper 0 cartridge with 16K of PRG, the code starting at $c000
+fn ines(source: &str) -> Vec<u8> {
+    Ines::new(&prg_at_c000(source)).image()
+}
+
+const REPORT: &str = "
+    define status $6000
+    sta status
+    lda #$de
+    sta $6001
+    lda #$b0
+    sta $6002
+    lda #$61
+    sta $6003
+    ldx #0
+copy:
+    lda text,x
+    sta $6004,x
+    beq done
+    inx
+    jmp copy
+done:
+    sty status
+hang:
+    jmp hang
+";
+
+#[test]
+fn test_passing_rom() {
+    let source = format!("lda #$80\nldy #0\n{}\ntext: dcb 'o', 'k', $0a, 0", REPORT);
+    let outcome = run(&ines(&source), 10_000).unwrap();
+    assert_eq!(outcome, Outcome::Passed { message: "ok".to_string() });
+}
+
+#[test]
+fn test_failing_rom_after_reset() {
+    // asks for a reset first, then fails with code 3
+    let source = format!(
+        "lda $6000\ncmp #$81\nbeq again\nlda #$81\nldy #$81\njmp report\nagain: lda #$80\nldy #3\nreport:\n{}\ntext: dcb 'b', 'a', 'd', 0",
+        REPORT
+    );
+    let outcome = run(&ines(&source), RESET_DELAY * 2).unwrap();
+    assert_eq!(outcome, Outcome::Failed { code: 3, message: "bad".to_string() });
+    assert_eq!(outcome.into_result(), Err("failed with code 3\nbad".to_string()));
+}
+
+#[test]
+fn test_silent_rom_times_out() {
+    let outcome = run(&ines("loop: jmp loop"), 1000).unwrap();
+    assert_eq!(outcome, Outcome::TimedOut { message: String::new() });
+}
+
+// Runs every .nes file in NES_BLARGG_ROMS, e.g. a checkout of instr_test-v5/rom_singles,
+// when the ignored tests are run
+#[test]
+#[ignore = "needs NES_BLARGG_ROMS"]
+fn test_blargg_roms() {
+    let dir = env::var("NES_BLARGG_ROMS").expect("NES_BLARGG_ROMS isn't set");
+    let mut failures = Vec::new();
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_none_or(|ext| ext != "nes") {
+            continue;
+        }
+        let result = fs::read(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|rom| run(&rom, MAX_STEPS))
+            .and_then(Outcome::into_result);
+        if let Err(e) = result {
+            failures.push(format!("{}: {}", path.display(), e));
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n\n"));
+}
@@ -0,0 +1,216 @@
+use crate::cartridge::Rom;
+use crate::hooks::{Access, Hooks};
+use crate::joypad::Joypad;
+use crate::savestate::{StateReader, StateWriter, JOYPAD_CHUNK, PRG_RAM_CHUNK, RAM_CHUNK};
+
+//  _______________ $10000  _______________
+// | PRG-ROM       |       |               |
+// |               |       |               |
+// |_______________| $8000 |_______________|
+// | PRG-RAM       |       |               |
+// |_______________| $6000 |_______________|
+// | I/O registers |       |               |
+// |_______________| $2000 |_______________|
+// | RAM           |       | RAM (mirrors) |
+// |_______________| $0000 |_______________|
+const RAM: u16 = 0x0000;
+const RAM_MIRRORS_END: u16 = 0x1fff;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const PRG_RAM: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7fff;
+const PRG_ROM: u16 = 0x8000;
+
+#[derive(Clone)]
+pub struct Bus {
+    // the whole 64KiB address space without a cartridge, the 2KiB of work RAM with one
+    ram: Vec<u8>,
+    prg_ram: Vec<u8>,
+    // PRG-RAM changed since the battery save was last written
+    prg_ram_dirty: bool,
+    rom: Option<Rom>,
+    // at $4016 and $4017 with a cartridge; the Easy6502 machine has a key
+    // port instead and keeps the buttons it last latched in the first
+    pub joypads: [Joypad; 2],
+    // run on every CPU access, `peek` and `poke` go around them
+    pub hooks: Hooks,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    // flat memory, as used by the Easy6502 programs
+    pub fn new() -> Self {
+        Bus {
+            ram: vec![0; 0x10000],
+            prg_ram: Vec::new(),
+            prg_ram_dirty: false,
+            rom: None,
+            joypads: Default::default(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    pub fn with_rom(rom: Rom) -> Self {
+        Bus {
+            ram: vec![0; 0x800],
+            prg_ram: vec![0; 0x2000],
+            prg_ram_dirty: false,
+            rom: Some(rom),
+            joypads: Default::default(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    // where a CPU address falls in PRG-ROM, for bank aware symbols and tools
+    pub fn prg_offset(&self, addr: u16) -> Option<u32> {
+        let rom = self.rom.as_ref()?;
+        if addr < PRG_ROM {
+            return None;
+        }
+        Some(((addr - PRG_ROM) as usize % rom.prg_rom.len()) as u32)
+    }
+
+    // the inverse of `prg_offset`, picking the last mirror, which holds the vectors
+    pub fn prg_address(&self, offset: u32) -> Option<u16> {
+        let len = self.rom.as_ref()?.prg_rom.len() as u32;
+        if offset >= len || len > 0x8000 {
+            return None;
+        }
+        Some((0x10000 - len + offset) as u16)
+    }
+
+    pub fn rom(&self) -> Option<&Rom> {
+        self.rom.as_ref()
+    }
+
+    pub fn rom_hash(&self) -> u64 {
+        self.rom.as_ref().map_or(0, |rom| rom.hash())
+    }
+
+    pub fn has_battery(&self) -> bool {
+        self.rom.as_ref().is_some_and(|rom| rom.battery)
+    }
+
+    pub fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    pub fn load_prg_ram(&mut self, data: &[u8]) -> Result<(), String> {
+        if data.len() != self.prg_ram.len() {
+            return Err(format!(
+                "expected {} bytes of PRG-RAM, got {}",
+                self.prg_ram.len(),
+                data.len()
+            ));
+        }
+        self.prg_ram.copy_from_slice(data);
+        self.prg_ram_dirty = false;
+        Ok(())
+    }
+
+    // whether PRG-RAM changed since it was loaded or last marked clean
+    pub fn prg_ram_dirty(&self) -> bool {
+        self.prg_ram_dirty
+    }
+
+    pub fn mark_prg_ram_clean(&mut self) {
+        self.prg_ram_dirty = false;
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.chunk(RAM_CHUNK, &self.ram);
+        if self.rom.is_some() {
+            state.chunk(PRG_RAM_CHUNK, &self.prg_ram);
+        }
+        state.chunk(JOYPAD_CHUNK, &self.joypads.iter().flat_map(Joypad::save).collect::<Vec<u8>>());
+    }
+
+    pub fn load_state(&mut self, state: &StateReader) -> Result<(), String> {
+        let ram = state.require(RAM_CHUNK, self.ram.len())?;
+        let prg_ram = match self.rom {
+            Some(_) => state.require(PRG_RAM_CHUNK, self.prg_ram.len())?,
+            None => &[],
+        };
+        let (ram_len, prg_ram_len) = (self.ram.len(), self.prg_ram.len());
+        self.ram.copy_from_slice(&ram[..ram_len]);
+        self.prg_ram.copy_from_slice(&prg_ram[..prg_ram_len]);
+        self.prg_ram_dirty = true;
+        // states from before the joypads were saved leave them as they are
+        if let Some(joypads) = state.chunk(JOYPAD_CHUNK).filter(|joypads| joypads.len() >= 6) {
+            for (joypad, saved) in self.joypads.iter_mut().zip(joypads.chunks_exact(3)) {
+                joypad.restore(saved.try_into().unwrap());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn mem_read(&self, addr: u16) -> u8 {
+        let value = match addr {
+            JOYPAD1 | JOYPAD2 if self.rom.is_some() => self.joypads[(addr - JOYPAD1) as usize].read(),
+            _ => self.peek(addr),
+        };
+        self.hooked(Access::READ, addr, value)
+    }
+
+    // the opcode fetch, which execute hooks see instead of read hooks
+    pub fn fetch(&self, addr: u16) -> u8 {
+        self.hooked(Access::EXECUTE, addr, self.peek(addr))
+    }
+
+    pub fn mem_write(&mut self, addr: u16, data: u8) {
+        let data = self.hooked(Access::WRITE, addr, data);
+        self.poke(addr, data);
+    }
+
+    fn hooked(&self, access: Access, addr: u16, value: u8) -> u8 {
+        if self.hooks.is_empty() {
+            value
+        } else {
+            self.hooks.run(access, addr, value)
+        }
+    }
+
+    // reads without side effects, for debuggers and frontends
+    pub fn peek(&self, addr: u16) -> u8 {
+        let rom = match &self.rom {
+            Some(rom) => rom,
+            None => return self.ram[addr as usize],
+        };
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.ram[(addr & 0x07ff) as usize],
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            JOYPAD1 | JOYPAD2 => self.joypads[(addr - JOYPAD1) as usize].peek(),
+            PRG_ROM..=0xffff => {
+                // 16KiB carts are mirrored into $c000-$ffff
+                let offset = (addr - PRG_ROM) as usize % rom.prg_rom.len();
+                rom.prg_rom[offset]
+            }
+            _ => {
+                // there is no PPU/APU yet, I/O registers read as open bus
+                0
+            }
+        }
+    }
+
+    pub fn poke(&mut self, addr: u16, data: u8) {
+        if self.rom.is_none() {
+            self.ram[addr as usize] = data;
+            return;
+        }
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.ram[(addr & 0x07ff) as usize] = data,
+            PRG_RAM..=PRG_RAM_END => {
+                self.prg_ram[(addr - PRG_RAM) as usize] = data;
+                self.prg_ram_dirty = true;
+            }
+            // the strobe goes to both controllers
+            JOYPAD1 => self.joypads.iter_mut().for_each(|joypad| joypad.write(data)),
+            _ => { /* I/O registers and ROM ignore writes for now */ }
+        }
+    }
+}
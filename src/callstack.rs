@@ -0,0 +1,92 @@
+use crate::cpu::Interrupt;
+
+#[cfg(test)]
+mod callstack_test;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entry {
+    Call,
+    // NMI, IRQ or a BRK going through $fffe
+    Interrupt(Interrupt),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub entry: Entry,
+    // the JSR or BRK, or the instruction an interrupt came before
+    pub from: u16,
+    // the routine or handler entered
+    pub target: u16,
+    // where the matching RTS or RTI should land
+    pub return_to: u16,
+    // the stack pointer before the call pushed anything
+    pub sp: u8,
+}
+
+// An RTS or RTI that didn't return to the innermost frame: an RTS used as a
+// jump, or a routine that changed its return address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mismatch {
+    pub pc: u16,
+    pub expected: Option<u16>,
+    pub landed: u16,
+}
+
+// The calls the CPU is in, kept beside the real stack from JSR/RTS and
+// interrupt entry/RTI. Frames the stack pointer has moved back past are
+// dropped, so manual stack handling can't make it grow or drift for long.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<Frame>,
+    pub mismatches: u64,
+    pub last_mismatch: Option<Mismatch>,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack::default()
+    }
+
+    // outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub fn enter(&mut self, frame: Frame) {
+        self.unwind(frame.sp);
+        self.frames.push(frame);
+    }
+
+    // after an RTS or RTI at `pc` moved the stack pointer to `sp` and jumped
+    // to `landed`, returns whether that was the innermost frame returning
+    pub fn leave(&mut self, pc: u16, sp: u8, landed: u16) -> bool {
+        let innermost = self.frames.last().copied();
+        if innermost.is_some_and(|frame| frame.sp == sp && frame.return_to == landed) {
+            self.frames.pop();
+            return true;
+        }
+        self.mismatches += 1;
+        self.last_mismatch = Some(Mismatch {
+            pc,
+            expected: innermost.map(|frame| frame.return_to),
+            landed,
+        });
+        self.unwind(sp);
+        false
+    }
+
+    // frames entered with the stack at or below `sp` have been left
+    fn unwind(&mut self, sp: u8) {
+        while self.frames.last().is_some_and(|frame| frame.sp <= sp) {
+            self.frames.pop();
+        }
+    }
+}
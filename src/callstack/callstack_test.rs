@@ -0,0 +1,91 @@
+use super::*;
+use crate::testutil::cpu_with;
+use crate::cpu::{CPU, NMI_VECTOR};
+use crate::debugger::Debugger;
+
+fn steps(cpu: &mut CPU, count: usize) {
+    for _ in 0..count {
+        assert!(cpu.step().unwrap());
+    }
+}
+
+#[test]
+fn test_calls_and_interrupts() {
+    let source = "
+        nop
+        nop
+        brk
+    nmi:
+        jsr update_sprites
+        rti
+    update_sprites:
+        jsr draw_snake
+        rts
+    draw_snake:
+        nop
+        rts
+    ";
+    let mut cpu = cpu_with(source);
+    cpu.mem_write_u16(NMI_VECTOR, 0x0603);
+    cpu.nmi_pending = true;
+    steps(&mut cpu, 3);
+
+    let frames = cpu.call_stack.frames();
+    let nmi = Frame { entry: Entry::Interrupt(Interrupt::Nmi), from: 0x0600, target: 0x0603, return_to: 0x0600, sp: 0xff };
+    assert_eq!(frames[0], nmi);
+    let call = Frame { entry: Entry::Call, from: 0x0603, target: 0x0607, return_to: 0x0606, sp: 0xfc };
+    assert_eq!(frames[1], call);
+    assert_eq!(frames[2].target, 0x060b);
+
+    let mut debugger = Debugger::new();
+    debugger.symbols.parse_nl("$0607#update_sprites#\n$060B#draw_snake#\n", None).unwrap();
+    assert_eq!(debugger.backtrace(&cpu), "NMI → update_sprites → draw_snake");
+
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.call_stack.depth(), 2);
+    steps(&mut cpu, 2);
+    assert_eq!(cpu.call_stack.depth(), 0);
+    assert_eq!(cpu.program_counter, 0x0600);
+    assert_eq!(cpu.call_stack.mismatches, 0);
+    assert_eq!(debugger.backtrace(&cpu), "");
+}
+
+#[test]
+fn test_mismatched_returns() {
+    // RTS used as a jump to $060C, then the real return
+    let source = "
+        jsr sub
+        brk
+    sub:
+        lda #$06
+        pha
+        lda #$0b
+        pha
+        rts
+        nop
+        rts
+    ";
+    let mut cpu = cpu_with(source);
+    steps(&mut cpu, 6);
+    assert_eq!(cpu.call_stack.mismatches, 1);
+    let mismatch = Mismatch { pc: 0x060a, expected: Some(0x0603), landed: 0x060c };
+    assert_eq!(cpu.call_stack.last_mismatch, Some(mismatch));
+    // the stack pointer is still inside `sub`
+    assert_eq!(cpu.call_stack.depth(), 1);
+
+    steps(&mut cpu, 1);
+    assert_eq!(cpu.call_stack.depth(), 0);
+    assert_eq!(cpu.call_stack.mismatches, 1);
+}
+
+#[test]
+fn test_unwound_frames_are_dropped() {
+    // the stack is reset without returning, then a new call comes in
+    let mut cpu = cpu_with("jsr sub\nbrk\nsub:\nldx #$ff\ntxs\njsr sub2\nsub2:\nnop");
+    steps(&mut cpu, 4);
+    assert_eq!(cpu.call_stack.depth(), 1);
+    assert_eq!(cpu.call_stack.frames()[0].target, 0x060a);
+
+    cpu.reset();
+    assert_eq!(cpu.call_stack.depth(), 0);
+}
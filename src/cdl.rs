@@ -0,0 +1,185 @@
+use std::cell::{Ref, RefCell};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::rc::Rc;
+
+use bitflags::bitflags;
+
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::hooks::{Access, HookId};
+use crate::opcodes::{Mode, OPCODES_MAP};
+
+#[cfg(test)]
+mod cdl_test;
+
+const PRG_ROM: u16 = 0x8000;
+
+bitflags! {
+    // one byte per PRG-ROM byte, as FCEUX writes them
+    pub struct Prg: u8 {
+        const CODE = 0x01;
+        const DATA = 0x02;
+        // bits 2-3: the 8KiB window the byte was last seen in, 0 for $8000
+        // up to 3 for $E000
+        const WINDOW = 0x0c;
+        // code reached through JMP ($xxxx)
+        const INDIRECT_CODE = 0x10;
+        // data read through ($xx,X) or ($xx),Y
+        const INDIRECT_DATA = 0x20;
+        // DMC samples, there's no APU to play them yet
+        const PCM = 0x40;
+    }
+}
+
+bitflags! {
+    // one byte per CHR-ROM byte, following the PRG ones
+    pub struct Chr: u8 {
+        // fetched by the PPU to draw a frame
+        const RENDERED = 0x01;
+        // read by the CPU through $2007
+        const READ = 0x02;
+    }
+}
+
+// What each byte of a cartridge was used for, saved in the `.cdl` format of
+// FCEUX and Mesen: the PRG flags, then the CHR flags. There is no PPU yet,
+// so the CHR half is only carried over from loaded files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeDataLog {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>,
+}
+
+impl CodeDataLog {
+    pub fn new(rom: &Rom) -> CodeDataLog {
+        CodeDataLog {
+            prg: vec![0; rom.prg_rom.len()],
+            chr: vec![0; rom.chr_rom.len()],
+        }
+    }
+
+    pub fn from_bytes(rom: &Rom, data: &[u8]) -> Result<CodeDataLog, String> {
+        let mut log = CodeDataLog::new(rom);
+        if data.len() != log.prg.len() + log.chr.len() {
+            return Err(format!(
+                "log is {} bytes, the cartridge has {} of PRG-ROM and {} of CHR-ROM",
+                data.len(),
+                log.prg.len(),
+                log.chr.len()
+            ));
+        }
+        let (prg, chr) = data.split_at(log.prg.len());
+        log.prg.copy_from_slice(prg);
+        log.chr.copy_from_slice(chr);
+        Ok(log)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = self.prg.clone();
+        data.extend_from_slice(&self.chr);
+        data
+    }
+
+    pub fn prg_flags(&self, offset: usize) -> Prg {
+        Prg::from_bits_truncate(self.prg[offset])
+    }
+
+    pub fn is_code(&self, offset: usize) -> bool {
+        self.prg_flags(offset).contains(Prg::CODE)
+    }
+
+    // PRG bytes seen as code, as data, and not seen at all
+    pub fn summary(&self) -> (usize, usize, usize) {
+        let count = |flag| self.prg.iter().filter(|flags| Prg::from_bits_truncate(**flags).contains(flag)).count();
+        let unused = self.prg.iter().filter(|flags| **flags & (Prg::CODE | Prg::DATA).bits == 0).count();
+        (count(Prg::CODE), count(Prg::DATA), unused)
+    }
+
+    fn mark(&mut self, addr: u16, prg_offset: usize, flags: Prg) {
+        let window = ((addr - PRG_ROM) >> 13) as u8;
+        let byte = &mut self.prg[prg_offset];
+        *byte = (*byte & !Prg::WINDOW.bits) | flags.bits | (window << 2);
+    }
+}
+
+// what the instruction being run has told the logger so far
+struct Decoding {
+    operands: (u16, u16),
+    mode: Mode,
+    // the last instruction was JMP ($xxxx)
+    jumped_indirect: bool,
+}
+
+// Fills a log from bus hooks while the machine runs. Opcode fetches and the
+// operand bytes after them are code, any other read of PRG-ROM is data.
+pub struct CodeDataLogger {
+    log: Rc<RefCell<CodeDataLog>>,
+    hook: HookId,
+}
+
+impl CodeDataLogger {
+    // carries on from the log at `path` if there is one
+    pub fn open(path: &Path, cpu: &mut CPU) -> Result<CodeDataLogger, String> {
+        let rom = cpu.bus.rom().ok_or("the code/data logger needs an iNES cartridge")?;
+        let log = match fs::read(path) {
+            Ok(data) => CodeDataLog::from_bytes(rom, &data).map_err(|e| format!("can't load {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => CodeDataLog::new(rom),
+            Err(e) => return Err(format!("can't read {}: {}", path.display(), e)),
+        };
+        Ok(CodeDataLogger::attach(log, cpu))
+    }
+
+    pub fn attach(log: CodeDataLog, cpu: &mut CPU) -> CodeDataLogger {
+        let log = Rc::new(RefCell::new(log));
+        // only mapper 0 loads, so PRG-ROM never moves
+        let prg_len = log.borrow().prg.len();
+        let offset = move |addr: u16| (addr >= PRG_ROM).then(|| (addr - PRG_ROM) as usize % prg_len);
+        let mut decoding = Decoding {
+            operands: (0, 0),
+            mode: Mode::Implied,
+            jumped_indirect: false,
+        };
+        let shared = log.clone();
+        let hook = cpu.bus.hooks.add(Access::READ | Access::EXECUTE, 0..=0xffff, move |access, addr, value| {
+            let flags = if access == Access::EXECUTE {
+                let op = OPCODES_MAP.get(&value);
+                let size = op.map_or(1, |op| op.size()) as u16;
+                let mut flags = Prg::CODE;
+                if decoding.jumped_indirect {
+                    flags |= Prg::INDIRECT_CODE;
+                }
+                decoding.operands = (addr.wrapping_add(1), addr.wrapping_add(size));
+                decoding.mode = op.map_or(Mode::Implied, |op| op.mode);
+                decoding.jumped_indirect = decoding.mode == Mode::Indirect;
+                flags
+            } else if (decoding.operands.0..decoding.operands.1).contains(&addr) {
+                Prg::CODE
+            } else if matches!(decoding.mode, Mode::IndirectX | Mode::IndirectY) {
+                // the pointer is in zero page, so in ROM this is the target
+                Prg::DATA | Prg::INDIRECT_DATA
+            } else {
+                Prg::DATA
+            };
+            if let Some(prg_offset) = offset(addr) {
+                shared.borrow_mut().mark(addr, prg_offset, flags);
+            }
+            None
+        });
+        CodeDataLogger { log, hook }
+    }
+
+    pub fn log(&self) -> Ref<'_, CodeDataLog> {
+        self.log.borrow()
+    }
+
+    pub fn detach(self, cpu: &mut CPU) -> CodeDataLog {
+        cpu.bus.hooks.remove(self.hook);
+        self.log.borrow().clone()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.log.borrow().to_bytes()).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,99 @@
+use super::*;
+use crate::testutil::{Ines, prg_at_c000};
+
+const PROGRAM: &str = "
+    lda $c020
+    lda #$20
+    sta $00
+    lda #$c0
+    sta $01
+    ldy #$01
+    lda ($00),y
+    jmp ($c030)
+";
+
+// a 16KiB cart running PROGRAM from $C000, data at $C020, a pointer to $C040
+// at $C030
+fn cart() -> CPU {
+    let mut prg = prg_at_c000(PROGRAM);
+    prg[0x30] = 0x40;
+    prg[0x31] = 0xc0;
+    Ines::new(&prg).chr(1).cpu()
+}
+
+// flags for a byte seen at $C000-$DFFF
+fn c000(flags: Prg) -> u8 {
+    flags.bits | 2 << 2
+}
+
+#[test]
+fn test_code_and_data() {
+    let mut cpu = cart();
+    let logger = CodeDataLogger::attach(CodeDataLog::new(cpu.bus.rom().unwrap()), &mut cpu);
+    cpu.reset();
+    for _ in 0..9 {
+        cpu.step().unwrap();
+    }
+    let log = logger.log();
+
+    // LDA $C020 and its operand bytes
+    assert_eq!(log.prg[..3], [c000(Prg::CODE); 3]);
+    assert_eq!(log.prg[0x20], c000(Prg::DATA));
+    assert_eq!(log.prg[0x21], c000(Prg::DATA | Prg::INDIRECT_DATA));
+    // the JMP pointer is plain data, where it lands is indirect code
+    assert_eq!(log.prg[0x30], c000(Prg::DATA));
+    assert_eq!(log.prg[0x40], c000(Prg::CODE | Prg::INDIRECT_CODE));
+    // the reset vector was read as data
+    assert_eq!(log.prg_flags(0x3ffc), Prg::DATA | Prg::WINDOW);
+    assert!(!log.is_code(0x42));
+    assert_eq!(log.chr, vec![0; 0x2000]);
+    let (code, data, unused) = log.summary();
+    assert_eq!((code, data), (19, 6));
+    assert_eq!(code + data + unused, 0x4000);
+}
+
+#[test]
+fn test_mirrors_share_a_byte() {
+    let mut cpu = cart();
+    let logger = CodeDataLogger::attach(CodeDataLog::new(cpu.bus.rom().unwrap()), &mut cpu);
+    cpu.mem_read(0x8010);
+    assert_eq!(logger.log().prg[0x10], Prg::DATA.bits);
+    // the window is where it was seen last
+    cpu.mem_read(0xc010);
+    assert_eq!(logger.log().prg[0x10], c000(Prg::DATA));
+    // peeks aren't accesses
+    cpu.peek(0x8011);
+    assert_eq!(logger.log().prg[0x11], 0);
+    logger.detach(&mut cpu);
+    assert!(cpu.bus.hooks.is_empty());
+}
+
+#[test]
+fn test_cdl_files() {
+    let mut cpu = cart();
+    let path = std::env::temp_dir().join(format!("cdl_test_{}.cdl", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    let logger = CodeDataLogger::open(&path, &mut cpu).unwrap();
+    cpu.step().unwrap();
+    logger.save(&path).unwrap();
+    let data = fs::read(&path).unwrap();
+    assert_eq!(data.len(), 0x4000 + 0x2000);
+    assert_eq!(data[0], c000(Prg::CODE));
+    logger.detach(&mut cpu);
+
+    // a second session adds to the first
+    let mut cpu = cart();
+    let logger = CodeDataLogger::open(&path, &mut cpu).unwrap();
+    assert!(logger.log().is_code(1));
+    cpu.mem_read(0x9000);
+    assert_eq!(logger.log().prg_flags(0x1000), Prg::DATA);
+    drop(logger);
+
+    fs::write(&path, [0u8; 16]).unwrap();
+    assert!(CodeDataLogger::open(&path, &mut cart()).is_err());
+    fs::remove_file(&path).unwrap();
+
+    let mut cpu = CPU::new();
+    assert!(CodeDataLogger::open(&path, &mut cpu).is_err());
+}
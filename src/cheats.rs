@@ -0,0 +1,260 @@
+use std::cell::{Ref, RefCell};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cli::Args;
+use crate::cpu::CPU;
+use crate::hooks::{Access, HookId};
+
+#[cfg(test)]
+mod cheats_test;
+
+// the Game Genie alphabet, each letter a nibble
+const GAME_GENIE: &str = "APZLGITYEOXUKSVN";
+
+// Replaces what the CPU reads at one address, optionally only while the
+// real value is `compare`, which keeps a ROM patch to the bank it was made for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    // as it was entered, Game Genie letters or `addr:value`
+    pub code: String,
+    pub name: String,
+    pub addr: u16,
+    pub value: u8,
+    pub compare: Option<u8>,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    // a raw code
+    pub fn new(addr: u16, value: u8, compare: Option<u8>) -> Cheat {
+        let code = match compare {
+            Some(compare) => format!("{:04X}?{:02X}:{:02X}", addr, compare, value),
+            None => format!("{:04X}:{:02X}", addr, value),
+        };
+        Cheat {
+            code,
+            name: String::new(),
+            addr,
+            value,
+            compare,
+            enabled: true,
+        }
+    }
+
+    // a 6 or 8 letter Game Genie code, `addr:value` or `addr?compare:value`,
+    // all in hex
+    pub fn parse(code: &str) -> Result<Cheat, String> {
+        let code = code.trim();
+        let (addr, value, compare) = match code.split_once(':') {
+            Some((target, value)) => {
+                let (addr, compare) = match target.split_once('?') {
+                    Some((addr, compare)) => (addr, Some(parse_hex(compare, code)? as u8)),
+                    None => (target, None),
+                };
+                let (addr, value) = (parse_hex(addr, code)?, parse_hex(value, code)?);
+                if addr > 0xffff || value > 0xff {
+                    return Err(format!("invalid cheat code: {}", code));
+                }
+                (addr as u16, value as u8, compare)
+            }
+            None => game_genie(code)?,
+        };
+        Ok(Cheat {
+            code: code.to_string(),
+            name: String::new(),
+            addr,
+            value,
+            compare,
+            enabled: true,
+        })
+    }
+
+    pub fn named(mut self, name: &str) -> Cheat {
+        self.name = name.to_string();
+        self
+    }
+
+    fn applies(&self, addr: u16, value: u8) -> bool {
+        self.enabled && self.addr == addr && self.compare.is_none_or(|compare| compare == value)
+    }
+}
+
+fn parse_hex(text: &str, code: &str) -> Result<u32, String> {
+    let text = text.trim().trim_start_matches('$');
+    u32::from_str_radix(text, 16).map_err(|_| format!("invalid cheat code: {}", code))
+}
+
+// Letters scatter the bits of the address, value and compare; the address
+// always lands in $8000-$FFFF.
+fn game_genie(code: &str) -> Result<(u16, u8, Option<u8>), String> {
+    let n: Vec<u16> = code
+        .chars()
+        .map(|letter| GAME_GENIE.find(letter.to_ascii_uppercase()).map(|n| n as u16))
+        .collect::<Option<_>>()
+        .filter(|n: &Vec<u16>| n.len() == 6 || n.len() == 8)
+        .ok_or_else(|| format!("invalid cheat code: {}", code))?;
+    let addr = 0x8000
+        | ((n[3] & 7) << 12)
+        | ((n[5] & 7) << 8)
+        | ((n[4] & 8) << 8)
+        | ((n[2] & 7) << 4)
+        | ((n[1] & 8) << 4)
+        | (n[4] & 7)
+        | (n[3] & 8);
+    let value = ((n[1] & 7) << 4) | ((n[0] & 8) << 4) | (n[0] & 7);
+    if n.len() == 6 {
+        return Ok((addr, (value | (n[5] & 8)) as u8, None));
+    }
+    let compare = ((n[7] & 7) << 4) | ((n[6] & 8) << 4) | (n[6] & 7) | (n[5] & 8);
+    Ok((addr, (value | (n[7] & 8)) as u8, Some(compare as u8)))
+}
+
+// The cheats of a machine. One bus hook looks them up on each read and
+// opcode fetch, so the ROM and RAM are never changed and switching a cheat
+// off restores the real value at once.
+pub struct Cheats {
+    cheats: Rc<RefCell<Vec<Cheat>>>,
+    hook: HookId,
+    // the `<program>.cht` the list is kept in
+    path: Option<PathBuf>,
+}
+
+impl Cheats {
+    pub fn attach(cpu: &mut CPU) -> Cheats {
+        let cheats: Rc<RefCell<Vec<Cheat>>> = Rc::default();
+        let shared = cheats.clone();
+        let hook = cpu.bus.hooks.add(Access::READ | Access::EXECUTE, 0..=0xffff, move |_, addr, value| {
+            let cheats = shared.borrow();
+            cheats.iter().find(|cheat| cheat.applies(addr, value)).map(|cheat| cheat.value)
+        });
+        Cheats { cheats, hook, path: None }
+    }
+
+    // The `<program>.cht` list if there is one, then the `--cheat` codes it
+    // doesn't have yet, which `save_list` adds to it; codes it has switched
+    // off are switched on. Headless runs only get the codes, like they get
+    // no battery save. Without any cheats nothing is attached, so reads skip
+    // the hook.
+    pub fn from_args(args: &Args, cpu: &mut CPU) -> Result<Option<Cheats>, String> {
+        let path = match (&args.program, &args.headless) {
+            (Some(program), None) => Some(Path::new(program).with_extension("cht")),
+            _ => None,
+        };
+        let mut list = match &path {
+            Some(path) => read_list(path)?,
+            None => Vec::new(),
+        };
+        for code in &args.cheats {
+            let cheat = Cheat::parse(code)?;
+            let key = (cheat.addr, cheat.value, cheat.compare);
+            match list.iter_mut().find(|known| (known.addr, known.value, known.compare) == key) {
+                Some(known) => known.enabled = true,
+                None => list.push(cheat),
+            }
+        }
+        if list.is_empty() {
+            return Ok(None);
+        }
+        let mut cheats = Cheats::attach(cpu);
+        cheats.path = path;
+        for cheat in list {
+            cheats.add(cheat);
+        }
+        Ok(Some(cheats))
+    }
+
+    pub fn add(&mut self, cheat: Cheat) -> usize {
+        let mut cheats = self.cheats.borrow_mut();
+        cheats.push(cheat);
+        cheats.len() - 1
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Cheat> {
+        let mut cheats = self.cheats.borrow_mut();
+        (index < cheats.len()).then(|| cheats.remove(index))
+    }
+
+    // returns false if there's no such cheat
+    pub fn set_enabled(&mut self, index: usize, enabled: bool) -> bool {
+        match self.cheats.borrow_mut().get_mut(index) {
+            Some(cheat) => {
+                cheat.enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn list(&self) -> Ref<'_, Vec<Cheat>> {
+        self.cheats.borrow()
+    }
+
+    pub fn detach(self, cpu: &mut CPU) {
+        cpu.bus.hooks.remove(self.hook);
+    }
+
+    // adds the cheats of a list written by `save`
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        for cheat in read_list(path)? {
+            self.add(cheat);
+        }
+        Ok(())
+    }
+
+    // one `on|off <code> <name>` line per cheat, so codes can't have spaces
+    // and names can't span lines
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::new();
+        for cheat in self.list().iter() {
+            if cheat.code.contains(char::is_whitespace) {
+                return Err(format!("can't save cheat code {:?}, it has spaces", cheat.code));
+            }
+            if cheat.name.contains(['\n', '\r']) {
+                return Err(format!("can't save cheat name {:?}, it spans lines", cheat.name));
+            }
+            let state = if cheat.enabled { "on" } else { "off" };
+            text += format!("{} {} {}", state, cheat.code, cheat.name).trim_end();
+            text += "\n";
+        }
+        fs::write(path, text).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+
+    // writes the list back to the `<program>.cht` it came from, if any
+    pub fn save_list(&self) -> Result<(), String> {
+        match &self.path {
+            Some(path) => self.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+// a list written by `Cheats::save`; a missing file is an empty list
+fn read_list(path: &Path) -> Result<Vec<Cheat>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("can't read {}: {}", path.display(), e)),
+    };
+    let mut cheats = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |e: String| format!("{} line {}: {}", path.display(), lineno + 1, e);
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let enabled = match fields.next() {
+            Some("on") => true,
+            Some("off") => false,
+            _ => return Err(error("expected `on` or `off`".to_string())),
+        };
+        let code = fields.next().ok_or_else(|| error("missing code".to_string()))?;
+        let mut cheat = Cheat::parse(code).map_err(error)?.named(fields.next().unwrap_or("").trim());
+        cheat.enabled = enabled;
+        cheats.push(cheat);
+    }
+    Ok(cheats)
+}
@@ -0,0 +1,130 @@
+use super::*;
+use crate::testutil::{Ines, prg_at_c000};
+
+// a 16KiB cart running `lda $c010` from $C000, with $05 at $C010
+fn cart() -> CPU {
+    let mut prg = prg_at_c000("lda $c010\nbrk");
+    prg[0x10] = 0x05;
+    Ines::new(&prg).cpu()
+}
+
+#[test]
+fn test_game_genie_codes() {
+    let cheat = Cheat::parse("SXIOPO").unwrap();
+    assert_eq!((cheat.addr, cheat.value, cheat.compare), (0x91d9, 0xad, None));
+    assert_eq!(Cheat::parse("sxiopo").unwrap().addr, 0x91d9);
+
+    let cheat = Cheat::parse("NNNNNNNN").unwrap();
+    assert_eq!((cheat.addr, cheat.value, cheat.compare), (0xffff, 0xff, Some(0xff)));
+    // the same letter is a value bit in a 6 letter code, a compare bit in an 8 letter one
+    assert_eq!(Cheat::parse("AAAAAE").unwrap().value, 0x08);
+    let cheat = Cheat::parse("AAAAAEAA").unwrap();
+    assert_eq!((cheat.addr, cheat.value, cheat.compare), (0x8000, 0x00, Some(0x08)));
+
+    assert!(Cheat::parse("SXIOP").is_err());
+    assert!(Cheat::parse("SXIOPB").is_err());
+}
+
+#[test]
+fn test_raw_codes() {
+    let cheat = Cheat::parse("0075:09").unwrap();
+    assert_eq!((cheat.addr, cheat.value, cheat.compare), (0x0075, 0x09, None));
+    let cheat = Cheat::parse("$c010?05:09").unwrap();
+    assert_eq!((cheat.addr, cheat.value, cheat.compare), (0xc010, 0x09, Some(0x05)));
+
+    assert!(Cheat::parse("10000:01").is_err());
+    assert!(Cheat::parse("0075:100").is_err());
+    assert!(Cheat::parse("0075:zz").is_err());
+}
+
+#[test]
+fn test_reads_are_replaced() {
+    let mut cpu = cart();
+    let mut cheats = Cheats::attach(&mut cpu);
+    let wrong_bank = cheats.add(Cheat::parse("c010?06:07").unwrap());
+    cheats.add(Cheat::parse("c010?05:09").unwrap());
+    assert_eq!(cpu.mem_read(0xc010), 0x09);
+    // the ROM itself is left alone
+    assert_eq!(cpu.peek(0xc010), 0x05);
+
+    cheats.set_enabled(1, false);
+    assert_eq!(cpu.mem_read(0xc010), 0x05);
+    cheats.remove(wrong_bank);
+    cheats.set_enabled(0, true);
+    assert!(!cheats.set_enabled(1, true));
+
+    // opcode fetches too: `lda $c010` becomes `lda #$10`
+    cheats.add(Cheat::parse("c000:a9").unwrap());
+    cpu.step().unwrap();
+    assert_eq!(cpu.register_a, 0x10);
+    assert_eq!(cpu.program_counter, 0xc002);
+
+    cheats.detach(&mut cpu);
+    assert!(cpu.bus.hooks.is_empty());
+}
+
+#[test]
+fn test_save_and_load() {
+    let mut cpu = CPU::new();
+    let mut cheats = Cheats::attach(&mut cpu);
+    cheats.add(Cheat::parse("SXIOPO").unwrap().named("infinite lives"));
+    cheats.add(Cheat::parse("0075?03:09").unwrap());
+    cheats.set_enabled(1, false);
+
+    let path = std::env::temp_dir().join(format!("cheats_test_{}.cht", std::process::id()));
+    cheats.save(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "on SXIOPO infinite lives\noff 0075?03:09\n");
+
+    let mut loaded = Cheats::attach(&mut cpu);
+    loaded.load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(*loaded.list(), *cheats.list());
+
+    loaded.load(&path).unwrap();
+    assert_eq!(loaded.list().len(), 2);
+
+    // what the list couldn't read back
+    loaded.add(Cheat::parse("0075 :09").unwrap());
+    assert_eq!(loaded.save(&path).unwrap_err(), "can't save cheat code \"0075 :09\", it has spaces");
+    loaded.remove(2);
+    loaded.add(Cheat::parse("0075:09").unwrap().named("two\nlines"));
+    assert_eq!(loaded.save(&path).unwrap_err(), "can't save cheat name \"two\\nlines\", it spans lines");
+    assert!(!path.exists());
+}
+
+#[test]
+fn test_no_hook_without_cheats() {
+    let args = |list: &[&str]| Args::parse(&list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>()).unwrap();
+    let mut cpu = CPU::new();
+    assert!(Cheats::from_args(&args(&["--headless"]), &mut cpu).unwrap().is_none());
+    assert!(cpu.bus.hooks.is_empty());
+
+    let cheats = Cheats::from_args(&args(&["--headless", "--cheat", "0075:09"]), &mut cpu).unwrap().unwrap();
+    assert_eq!(cheats.list().len(), 1);
+    assert_eq!(cpu.mem_read(0x75), 0x09);
+}
+
+#[test]
+fn test_list_is_kept_per_program() {
+    let dir = std::env::temp_dir().join(format!("cheats-list-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("game.nes").to_str().unwrap().to_string();
+    let args = |code: &str| Args::parse(&[program.clone(), "--cheat".to_string(), code.to_string()]).unwrap();
+
+    let cheats = Cheats::from_args(&args("0075:09"), &mut CPU::new()).unwrap().unwrap();
+    cheats.save_list().unwrap();
+    assert_eq!(fs::read_to_string(dir.join("game.cht")).unwrap(), "on 0075:09\n");
+    // a code the list has already isn't added twice
+    let cheats = Cheats::from_args(&args("0075:09"), &mut CPU::new()).unwrap().unwrap();
+    assert_eq!(cheats.list().len(), 1);
+    let cheats = Cheats::from_args(&args("SXIOPO"), &mut CPU::new()).unwrap().unwrap();
+    cheats.save_list().unwrap();
+    assert_eq!(fs::read_to_string(dir.join("game.cht")).unwrap(), "on 0075:09\non SXIOPO\n");
+    // one switched off in the list is switched on again
+    fs::write(dir.join("game.cht"), "off 0075:09\n").unwrap();
+    let cheats = Cheats::from_args(&args("0075:09"), &mut CPU::new()).unwrap().unwrap();
+    assert_eq!(cheats.list().len(), 1);
+    assert!(cheats.list()[0].enabled);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,396 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::asm;
+use crate::cartridge::Rom;
+use crate::easy6502::{self, LOAD_ADDRESS};
+use crate::headless;
+use crate::loader::{Format, Program};
+use crate::machine::{Machine, PowerOn, RamInit};
+use crate::movie::Movie;
+use crate::region::Region;
+use crate::trace::{self, Columns};
+
+#[cfg(test)]
+mod cli_test;
+
+pub const USAGE: &str = "usage: nes [options] [program]
+
+Runs the program, or the built-in snake game when none is given. Programs
+are iNES ROMs or, for the Easy6502 machine, one of:
+
+    .asm .s               assembly source, assembled at the origin
+    .hex .ihx             Intel HEX
+    .srec .s19 .s28 .s37  Motorola S-records
+    .prg                  2 byte little endian load address, then the code
+    anything else         raw binary loaded at the origin
+
+options:
+    --machine <name>      easy6502 or nes (default: detected from the file)
+    --origin <addr>       load address for raw binaries and source (default: $0600)
+    --region <name>       ntsc, pal or dendy (default: ntsc)
+    --seed <n>            seed for everything random: the Easy6502 byte at $fe and
+                          random RAM (default: 0 headless, else a random seed)
+    --ram-init <name>     what RAM and the A, X and Y registers hold at power on:
+                          zeros, ff or random (default: zeros)
+    --scale <n>           window scale factor (default: 10)
+    --fullscreen          start in fullscreen
+    --paused              start paused, press F1 to resume or F2 to advance a frame
+    --load-slot <n>       load a save state slot on launch (0-9)
+    --trace <file>        write an instruction trace to file
+    --trace-columns <list>
+                          comma separated trace columns: pc, bytes, disasm, effective,
+                          registers, flags, stack, ppu, cycles, or the nestest / all
+                          presets (default: nestest)
+    --trace-range <a>-<b> only trace instructions between two addresses
+    --trace-ring <n>      keep the last n instructions, printed if the CPU fails
+                          (up to 1048576)
+    --symbols <file>      labels for traces, from a ca65 .dbg, FCEUX .nl or Mesen .mlb
+                          file; may be repeated
+    --cdl <file>          log which ROM bytes run as code or are read as data, in
+                          the FCEUX .cdl format; an existing log is added to
+    --profile <file>      write the cycles spent per routine and instruction to file
+    --profile-folded <file>
+                          write the profile as folded stacks, for flamegraph.pl
+    --coverage <file>     write a disassembly with how often each instruction ran and
+                          which way each branch went
+    --lcov <file>         write coverage as an lcov tracefile, against the source lines
+                          of a ca65 .dbg from --symbols, or else the --coverage listing
+    --cheat <code>        a Game Genie code, `addr:value` or `addr?compare:value` in
+                          hex; may be repeated, `<program>.cht` is loaded as well
+                          and saved with the new codes on exit
+    --gdb <port>          wait for a GDB remote protocol client on localhost:port
+                          and let it drive the machine instead of running it;
+                          it can search RAM with the monitor commands below
+    --movie <file>        play back joypad input from an FCEUX .fm2 movie
+    --record <file>       record joypad input to an FCEUX .fm2 movie, from power on
+                          or from the --load-slot state; a --movie brings its own
+                          seed and RAM pattern
+    --rewind-interval <n> frames between rewind snapshots (default: 1)
+    --rewind-budget <n>   MiB of memory kept for rewinding, 0 disables it (default: 16)
+    --help                show this message
+
+keys:
+    F1 / Pause            pause / resume
+    F2                    pause, then advance one frame per press
+    F3 / F4               slower / faster: 25%, 50%, 100%, 2x, 4x, uncapped
+    Tab (hold)            fast forward, as fast as the host allows
+    F6                    select the next save state slot
+    F5 / F7               save / load the selected slot, loads and rewinding are off
+                          while a movie plays or records
+    F8                    turn the trace log off / on
+    Backspace (hold)      rewind
+    arrows, X, Z          joypad d-pad, A and B
+    S, A                  turbo A and B, pressed every other frame while held
+    Return, Right Shift   joypad Start and Select
+    other keys            typed characters go to $ff on the Easy6502 machine, which
+                          gets WASD, x, z, Return and space from the joypad
+
+headless options:
+    --headless            run without opening a window
+    --frames <n>          stop after n frames; without it a run that hasn't stopped
+                          by frame 36000 fails
+    --until-pc <addr>     stop when the program counter reaches addr
+    --input <file>        scripted input, one `<frame> <key>` pair per line
+    --dump-frame <file>   write the final framebuffer as a PPM image
+    --dump-ram <file>     write the final 64KiB memory image
+    --hash                print the hash of the final framebuffer
+    --expect-hash <hex>   exit with status 1 if the final frame hash differs
+
+gdb monitor commands, as `monitor <command>` from a --gdb client:
+    search new [16] [signed]    start a RAM search, over bytes or words
+    search eq|ne|gt|lt [value]  keep the candidates whose value compares as asked
+                                with the last search, or with the value
+    search list                 show the candidates
+    pin <addr> [value]          hold a hex address at the value, or at what it holds
+    cheats                      show the pinned addresses
+    values are decimal, or hex after $ or 0x";
+
+const HEADLESS_OPTIONS: &[&str] = &[
+    "--frames", "--until-pc", "--input", "--dump-frame", "--dump-ram", "--hash", "--expect-hash",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MachineKind {
+    Easy6502,
+    Nes,
+}
+
+impl MachineKind {
+    pub fn detect(program: &[u8]) -> MachineKind {
+        if Rom::is_ines(program) {
+            MachineKind::Nes
+        } else {
+            MachineKind::Easy6502
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub program: Option<String>,
+    pub machine: Option<MachineKind>,
+    pub origin: Option<u16>,
+    pub region: Region,
+    pub seed: Option<u64>,
+    pub ram_init: RamInit,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub paused: bool,
+    pub load_slot: Option<u8>,
+    pub trace: Option<String>,
+    pub trace_columns: Columns,
+    pub trace_range: Option<RangeInclusive<u16>>,
+    pub trace_ring: usize,
+    pub symbols: Vec<String>,
+    pub cdl: Option<String>,
+    pub profile: Option<String>,
+    pub profile_folded: Option<String>,
+    pub coverage: Option<String>,
+    pub lcov: Option<String>,
+    pub cheats: Vec<String>,
+    pub gdb: Option<u16>,
+    pub movie: Option<String>,
+    pub record: Option<String>,
+    pub rewind_interval: usize,
+    pub rewind_budget: usize,
+    pub help: bool,
+    pub headless: Option<headless::Options>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Args {
+            program: None,
+            machine: None,
+            origin: None,
+            region: Region::Ntsc,
+            seed: None,
+            ram_init: RamInit::Zeros,
+            scale: 10,
+            fullscreen: false,
+            paused: false,
+            load_slot: None,
+            trace: None,
+            trace_columns: Columns::NESTEST,
+            trace_range: None,
+            trace_ring: 0,
+            symbols: Vec::new(),
+            cdl: None,
+            profile: None,
+            profile_folded: None,
+            coverage: None,
+            lcov: None,
+            cheats: Vec::new(),
+            gdb: None,
+            movie: None,
+            record: None,
+            rewind_interval: 1,
+            rewind_budget: 16 << 20,
+            help: false,
+            headless: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut headless = false;
+        let mut headless_opts = headless::Options::default();
+        let mut headless_only = None;
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if HEADLESS_OPTIONS.contains(&arg.as_str()) {
+                headless_only.get_or_insert_with(|| arg.clone());
+            }
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--machine" => {
+                    parsed.machine = match value()?.as_str() {
+                        "easy6502" => Some(MachineKind::Easy6502),
+                        "nes" => Some(MachineKind::Nes),
+                        other => return Err(format!("unknown machine: {}", other)),
+                    }
+                }
+                "--origin" => {
+                    let origin = parse_number(&value()?)?;
+                    if origin > 0xffff {
+                        return Err(format!("invalid origin: {}", origin));
+                    }
+                    parsed.origin = Some(origin as u16);
+                }
+                "--region" => {
+                    let name = value()?;
+                    parsed.region = Region::from_name(&name)
+                        .ok_or_else(|| format!("unknown region: {}", name))?;
+                }
+                "--seed" => parsed.seed = Some(parse_number(&value()?)?),
+                "--ram-init" => {
+                    let name = value()?;
+                    parsed.ram_init = RamInit::from_name(&name)
+                        .ok_or_else(|| format!("unknown RAM pattern: {}", name))?;
+                }
+                "--scale" => {
+                    let scale = parse_number(&value()?)?;
+                    if scale == 0 || scale > 64 {
+                        return Err(format!("invalid scale: {}", scale));
+                    }
+                    parsed.scale = scale as u32;
+                }
+                "--fullscreen" => parsed.fullscreen = true,
+                "--paused" => parsed.paused = true,
+                "--load-slot" => {
+                    let slot = parse_number(&value()?)?;
+                    if slot > 9 {
+                        return Err(format!("invalid slot: {}", slot));
+                    }
+                    parsed.load_slot = Some(slot as u8);
+                }
+                "--trace" => parsed.trace = Some(value()?),
+                "--trace-columns" => parsed.trace_columns = Columns::parse(&value()?)?,
+                "--trace-range" => parsed.trace_range = Some(parse_range(&value()?)?),
+                "--trace-ring" => {
+                    let size = parse_number(&value()?)?;
+                    if size > trace::MAX_RING as u64 {
+                        return Err(format!("trace ring of {} is over {}", size, trace::MAX_RING));
+                    }
+                    parsed.trace_ring = size as usize;
+                }
+                "--symbols" => parsed.symbols.push(value()?),
+                "--cdl" => parsed.cdl = Some(value()?),
+                "--profile" => parsed.profile = Some(value()?),
+                "--profile-folded" => parsed.profile_folded = Some(value()?),
+                "--coverage" => parsed.coverage = Some(value()?),
+                "--lcov" => parsed.lcov = Some(value()?),
+                "--cheat" => parsed.cheats.push(value()?),
+                "--gdb" => {
+                    let port = parse_number(&value()?)?;
+                    if port == 0 || port > 0xffff {
+                        return Err(format!("invalid port: {}", port));
+                    }
+                    parsed.gdb = Some(port as u16);
+                }
+                "--movie" => parsed.movie = Some(value()?),
+                "--record" => parsed.record = Some(value()?),
+                "--rewind-interval" => parsed.rewind_interval = parse_number(&value()?)?.max(1) as usize,
+                "--rewind-budget" => {
+                    let mib = parse_number(&value()?)?;
+                    parsed.rewind_budget = usize::try_from(mib)
+                        .ok()
+                        .and_then(|mib| mib.checked_mul(1 << 20))
+                        .ok_or_else(|| format!("invalid rewind budget: {}", mib))?;
+                }
+                "--help" | "-h" => parsed.help = true,
+                "--headless" => headless = true,
+                "--frames" => headless_opts.frames = Some(parse_number(&value()?)? as usize),
+                "--until-pc" => {
+                    let pc = parse_number(&value()?)?;
+                    if pc > 0xffff {
+                        return Err(format!("invalid address: {}", pc));
+                    }
+                    headless_opts.until_pc = Some(pc as u16);
+                }
+                "--input" => headless_opts.input = Some(value()?),
+                "--dump-frame" => headless_opts.dump_frame = Some(value()?),
+                "--dump-ram" => headless_opts.dump_ram = Some(value()?),
+                "--hash" => headless_opts.print_hash = true,
+                "--expect-hash" => {
+                    let hex = value()?;
+                    let hash = u64::from_str_radix(hex.trim_start_matches("0x"), 16)
+                        .map_err(|_| format!("invalid hash: {}", hex))?;
+                    headless_opts.expect_hash = Some(hash);
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+                _ => {
+                    if parsed.program.is_some() {
+                        return Err(format!("unexpected argument: {}", arg));
+                    }
+                    parsed.program = Some(arg.clone());
+                }
+            }
+        }
+
+        if headless {
+            parsed.headless = Some(headless_opts);
+        } else if let Some(option) = headless_only {
+            return Err(format!("{} requires --headless", option));
+        }
+        Ok(parsed)
+    }
+
+    // how the machine powers on: as the --movie did, or from --seed and --ram-init
+    pub fn power_on(&self) -> Result<PowerOn, String> {
+        if let Some(path) = &self.movie {
+            let movie = Movie::load(Path::new(path))?;
+            // the movie says whether it was made on a PAL console
+            let region = match (movie.pal, self.region) {
+                (true, _) => Region::Pal,
+                (false, Region::Pal) => Region::Ntsc,
+                (false, region) => region,
+            };
+            return Ok(PowerOn { seed: movie.seed, ram: movie.ram, region });
+        }
+        let seed = match (self.seed, &self.headless) {
+            (Some(seed), _) => seed,
+            (None, Some(_)) => 0,
+            (None, None) => rand::random(),
+        };
+        Ok(PowerOn { seed, ram: self.ram_init, region: self.region })
+    }
+
+    // reads the program, falling back to the snake game when none was given
+    pub fn load(&self) -> Result<Machine, String> {
+        let power = self.power_on()?;
+        let origin = self.origin.unwrap_or(LOAD_ADDRESS);
+        let path = match &self.program {
+            Some(path) => path,
+            None if self.machine == Some(MachineKind::Nes) => {
+                return Err("the NES machine needs a ROM".to_string())
+            }
+            None => {
+                let code = asm::assemble(easy6502::SNAKE, origin)?;
+                return Machine::easy6502_with(&Program::at(origin, code), power);
+            }
+        };
+        let data = fs::read(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+        let kind = self.machine.unwrap_or_else(|| MachineKind::detect(&data));
+        if kind == MachineKind::Nes {
+            return Machine::nes_with(&data, power);
+        }
+        let program = if easy6502::is_source(Path::new(path)) {
+            let source = String::from_utf8_lossy(&data);
+            asm::assemble(&source, origin).map(|code| Program::at(origin, code))
+        } else {
+            Format::from_path(Path::new(path)).parse(&data, origin)
+        };
+        Machine::easy6502_with(&program.map_err(|e| format!("{}: {}", path, e))?, power)
+    }
+}
+
+// `<from>-<to>`, both ends included
+fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let invalid = || format!("invalid address range: {}", text);
+    let (from, to) = text.split_once('-').ok_or_else(invalid)?;
+    let (from, to) = (parse_number(from.trim())?, parse_number(to.trim())?);
+    if from > to || to > 0xffff {
+        return Err(invalid());
+    }
+    Ok(from as u16..=to as u16)
+}
+
+// accepts decimal, `0x` and `$` prefixed hex
+pub fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u64>()
+    };
+    parsed.map_err(|_| format!("invalid number: {}", text))
+}
@@ -0,0 +1,70 @@
+use super::*;
+
+fn parse(args: &str) -> Result<Args, String> {
+    Args::parse(&args.split_whitespace().map(String::from).collect::<Vec<_>>())
+}
+
+#[test]
+fn test_defaults() {
+    let args = parse("").unwrap();
+    assert_eq!(args.program, None);
+    assert_eq!((args.machine, args.origin, args.seed), (None, None, None));
+    assert_eq!((args.region, args.ram_init, args.scale), (Region::Ntsc, RamInit::Zeros, 10));
+    assert_eq!((args.rewind_interval, args.rewind_budget), (1, 16 << 20));
+    assert!(args.headless.is_none() && !args.help);
+}
+
+#[test]
+fn test_options() {
+    let args = parse(
+        "--machine nes --origin $8000 --region dendy --seed 0x10 --ram-init random --scale 3 \
+         --fullscreen --paused --load-slot 9 --trace-range $8000-$80ff --trace-ring 64 \
+         --symbols a.nl --symbols b.mlb --cheat SXIOPO --gdb 2345 --rewind-interval 0 \
+         --rewind-budget 2 game.nes",
+    )
+    .unwrap();
+    assert_eq!(args.program.as_deref(), Some("game.nes"));
+    assert_eq!((args.machine, args.origin, args.seed), (Some(MachineKind::Nes), Some(0x8000), Some(0x10)));
+    assert_eq!((args.region, args.ram_init, args.scale), (Region::Dendy, RamInit::Random, 3));
+    assert!(args.fullscreen && args.paused);
+    assert_eq!((args.load_slot, args.gdb), (Some(9), Some(2345)));
+    assert_eq!((args.trace_range, args.trace_ring), (Some(0x8000..=0x80ff), 64));
+    assert_eq!((args.symbols.len(), args.cheats.len()), (2, 1));
+    // at least every frame
+    assert_eq!((args.rewind_interval, args.rewind_budget), (1, 2 << 20));
+}
+
+#[test]
+fn test_invalid_values() {
+    for (args, error) in [
+        ("--machine c64", "unknown machine: c64"),
+        ("--origin 0x10000", "invalid origin: 65536"),
+        ("--region secam", "unknown region: secam"),
+        ("--ram-init ones", "unknown RAM pattern: ones"),
+        ("--scale 0", "invalid scale: 0"),
+        ("--load-slot 10", "invalid slot: 10"),
+        ("--gdb 70000", "invalid port: 70000"),
+        ("--trace-range $80ff-$8000", "invalid address range: $80ff-$8000"),
+        ("--trace-ring 1048577", "trace ring of 1048577 is over 1048576"),
+        ("--rewind-budget 18446744073709551615", "invalid rewind budget: 18446744073709551615"),
+        ("--seed x", "invalid number: x"),
+        ("--seed", "missing value for --seed"),
+        ("--mute", "unknown option: --mute"),
+        ("a.nes b.nes", "unexpected argument: b.nes"),
+    ] {
+        assert_eq!(parse(args).unwrap_err(), error, "{}", args);
+    }
+}
+
+#[test]
+fn test_headless_options() {
+    let args = parse("--headless --frames 10 --until-pc $0610 --hash --expect-hash 0xabc --input in.txt").unwrap();
+    let opts = args.headless.unwrap();
+    assert_eq!((opts.frames, opts.until_pc, opts.expect_hash), (Some(10), Some(0x0610), Some(0xabc)));
+    assert!(opts.print_hash);
+    assert_eq!(opts.input.as_deref(), Some("in.txt"));
+
+    assert_eq!(parse("--frames 10").unwrap_err(), "--frames requires --headless");
+    assert_eq!(parse("--headless --until-pc 0x10000").unwrap_err(), "invalid address: 65536");
+    assert_eq!(parse("--headless --expect-hash xyz").unwrap_err(), "invalid hash: xyz");
+}
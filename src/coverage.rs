@@ -0,0 +1,228 @@
+use std::cell::{Ref, RefCell};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::rc::Rc;
+
+use crate::cli::Args;
+use crate::cpu::CPU;
+use crate::disasm;
+use crate::hooks::{Access, HookId};
+use crate::opcodes::{Mode, OPCODES_MAP};
+use crate::symbols::{self, Symbols};
+
+#[cfg(test)]
+mod coverage_test;
+
+const STACK: u16 = 0x0100;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+// How often each instruction address ran and which way each branch went.
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub executed: BTreeMap<u16, u64>,
+    pub branches: BTreeMap<u16, Branch>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.executed.get(&addr).copied().unwrap_or(0)
+    }
+
+    // Disassembly from the lowest address run to the highest, with run
+    // counts and branch outcomes. Bytes that would start an instruction
+    // overlapping one that ran are shown as `.db`.
+    pub fn listing(&self, cpu: &CPU, symbols: &Symbols) -> String {
+        self.listing_lines(cpu, symbols).into_iter().map(|(_, line)| line + "\n").collect()
+    }
+
+    // the listing, each line with the address of its instruction
+    fn listing_lines(&self, cpu: &CPU, symbols: &Symbols) -> Vec<(Option<u16>, String)> {
+        let (first, last) = match (self.executed.keys().next(), self.executed.keys().next_back()) {
+            (Some(first), Some(last)) => (*first as u32, *last as u32),
+            _ => return Vec::new(),
+        };
+        let label = |addr| symbols.label(&cpu.bus, addr).map(str::to_string);
+        let mut lines = Vec::new();
+        let mut addr = first;
+        while addr <= last {
+            let at = addr as u16;
+            let mut instruction = disasm::decode(|addr| cpu.peek(addr), at);
+            let overlaps = (1..instruction.size()).any(|i| self.executed.contains_key(&at.wrapping_add(i)));
+            if overlaps && !self.executed.contains_key(&at) {
+                instruction = disasm::Instruction { address: at, op: None, bytes: vec![cpu.peek(at)] };
+            }
+            if let Some(name) = label(at) {
+                lines.push((None, format!("{}:", name)));
+            }
+            let hits = match self.executed.get(&at) {
+                Some(hits) => hits.to_string(),
+                None => "-".to_string(),
+            };
+            let bytes: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+            let mut line = format!(
+                "{:>10}  {:04X}  {:<8}  {}",
+                hits,
+                at,
+                bytes.join(" "),
+                instruction.format_with(label)
+            );
+            if let Some(branch) = self.branches.get(&at) {
+                let _ = write!(line, "  ; taken {}, not taken {}", branch.taken, branch.not_taken);
+            }
+            lines.push((instruction.op.map(|_| at), line));
+            addr += instruction.size() as u32;
+        }
+        lines
+    }
+
+    // An lcov tracefile. With source lines from a ca65 debug file the
+    // counts go to those; otherwise to the lines of `listing`, written to
+    // `listing_path`. Source lines that assembled to something other than a
+    // single instruction, data mostly, are left out.
+    pub fn lcov(&self, cpu: &CPU, symbols: &Symbols, listing_path: &str) -> String {
+        // per file, per line: hits and the branch there, if any
+        let mut files: BTreeMap<&str, BTreeMap<u32, (u64, Option<Branch>)>> = BTreeMap::new();
+        let source = symbols.source_lines();
+        if source.is_empty() {
+            let file = files.entry(listing_path).or_default();
+            for (lineno, (addr, _)) in self.listing_lines(cpu, symbols).into_iter().enumerate() {
+                if let Some(addr) = addr {
+                    file.insert(lineno as u32 + 1, (self.hits(addr), self.branch_at(cpu, addr)));
+                }
+            }
+        }
+        for line in source {
+            let addr = match symbols::resolve(&cpu.bus, line.location) {
+                Some(addr) => addr,
+                None => continue,
+            };
+            let instruction = disasm::decode(|addr| cpu.peek(addr), addr);
+            if instruction.op.is_none() || instruction.size() as u32 != line.size {
+                continue;
+            }
+            let entry = files.entry(&line.file).or_default().entry(line.line).or_default();
+            entry.0 += self.hits(addr);
+            entry.1 = entry.1.or(self.branch_at(cpu, addr));
+        }
+
+        let mut out = String::from("TN:\n");
+        for (file, lines) in files {
+            let _ = writeln!(out, "SF:{}", file);
+            let (mut branches, mut branches_hit) = (0, 0);
+            for (line, (_, branch)) in &lines {
+                if let Some(branch) = branch {
+                    let count = |hits: u64| if branch.taken + branch.not_taken == 0 { "-".to_string() } else { hits.to_string() };
+                    let _ = writeln!(out, "BRDA:{},0,0,{}", line, count(branch.taken));
+                    let _ = writeln!(out, "BRDA:{},0,1,{}", line, count(branch.not_taken));
+                    branches += 2;
+                    branches_hit += (branch.taken > 0) as u32 + (branch.not_taken > 0) as u32;
+                }
+            }
+            let _ = writeln!(out, "BRF:{}\nBRH:{}", branches, branches_hit);
+            for (line, (hits, _)) in &lines {
+                let _ = writeln!(out, "DA:{},{}", line, hits);
+            }
+            let hit = lines.values().filter(|(hits, _)| *hits > 0).count();
+            let _ = writeln!(out, "LF:{}\nLH:{}\nend_of_record", lines.len(), hit);
+        }
+        out
+    }
+
+    // conditional branches get an entry even if they never ran
+    fn branch_at(&self, cpu: &CPU, addr: u16) -> Option<Branch> {
+        let op = OPCODES_MAP.get(&cpu.peek(addr))?;
+        (op.mode == Mode::Relative).then(|| self.branches.get(&addr).copied().unwrap_or_default())
+    }
+}
+
+// a branch that ran, its outcome known from the next opcode fetch or, if an
+// interrupt comes first, from the return address it pushes
+struct Pending {
+    at: u16,
+    pushed_hi: Option<u8>,
+}
+
+// Fills a `Coverage` from bus hooks while the machine runs.
+pub struct CoverageRecorder {
+    coverage: Rc<RefCell<Coverage>>,
+    hook: HookId,
+}
+
+impl CoverageRecorder {
+    // a recorder if `--coverage` or `--lcov` asked for one
+    pub fn from_args(args: &Args, cpu: &mut CPU, symbols: &Symbols) -> Result<Option<CoverageRecorder>, String> {
+        if args.lcov.is_some() && args.coverage.is_none() && symbols.source_lines().is_empty() {
+            return Err("--lcov needs a ca65 .dbg file from --symbols, or a --coverage listing to refer to".to_string());
+        }
+        Ok((args.coverage.is_some() || args.lcov.is_some()).then(|| CoverageRecorder::attach(cpu)))
+    }
+
+    pub fn attach(cpu: &mut CPU) -> CoverageRecorder {
+        let coverage = Rc::new(RefCell::new(Coverage::new()));
+        let shared = coverage.clone();
+        let mut pending: Option<Pending> = None;
+        let hook = cpu.bus.hooks.add(Access::EXECUTE | Access::WRITE, 0..=0xffff, move |access, addr, value| {
+            let mut coverage = shared.borrow_mut();
+            let landed = match (access, pending.as_mut()) {
+                (Access::EXECUTE, _) => Some(addr),
+                (_, Some(branch)) if addr >> 8 == STACK >> 8 => match branch.pushed_hi {
+                    None => {
+                        branch.pushed_hi = Some(value);
+                        None
+                    }
+                    Some(hi) => Some(u16::from_le_bytes([value, hi])),
+                },
+                _ => None,
+            };
+            if let (Some(landed), Some(branch)) = (landed, pending.as_ref()) {
+                let outcome = coverage.branches.entry(branch.at).or_default();
+                if landed == branch.at.wrapping_add(2) {
+                    outcome.not_taken += 1;
+                } else {
+                    outcome.taken += 1;
+                }
+                pending = None;
+            }
+            if access == Access::EXECUTE {
+                *coverage.executed.entry(addr).or_default() += 1;
+                if OPCODES_MAP.get(&value).is_some_and(|op| op.mode == Mode::Relative) {
+                    pending = Some(Pending { at: addr, pushed_hi: None });
+                }
+            }
+            None
+        });
+        CoverageRecorder { coverage, hook }
+    }
+
+    pub fn coverage(&self) -> Ref<'_, Coverage> {
+        self.coverage.borrow()
+    }
+
+    // writes what `--coverage` and `--lcov` asked for
+    pub fn save(&self, args: &Args, cpu: &CPU, symbols: &Symbols) -> Result<(), String> {
+        let coverage = self.coverage();
+        if let Some(path) = &args.coverage {
+            fs::write(path, coverage.listing(cpu, symbols)).map_err(|e| format!("can't write {}: {}", path, e))?;
+        }
+        if let Some(path) = &args.lcov {
+            let listing = args.coverage.as_deref().unwrap_or_default();
+            fs::write(path, coverage.lcov(cpu, symbols, listing)).map_err(|e| format!("can't write {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn detach(self, cpu: &mut CPU) -> Coverage {
+        cpu.bus.hooks.remove(self.hook);
+        self.coverage.borrow().clone()
+    }
+}
@@ -0,0 +1,112 @@
+use super::*;
+use crate::testutil::cpu_with;
+use crate::cpu::NMI_VECTOR;
+
+const COUNTDOWN: &str = "
+        ldx #$03
+    loop:
+        dex
+        bne loop
+        brk
+";
+
+fn run(cpu: &mut CPU) {
+    for _ in 0..1000 {
+        if !cpu.step().unwrap() {
+            return;
+        }
+    }
+    panic!("the program didn't halt");
+}
+
+#[test]
+fn test_instructions_and_branches() {
+    let mut cpu = cpu_with(COUNTDOWN);
+    let recorder = CoverageRecorder::attach(&mut cpu);
+    run(&mut cpu);
+    let coverage = recorder.detach(&mut cpu);
+
+    assert_eq!(coverage.hits(0x0600), 1);
+    assert_eq!(coverage.hits(0x0602), 3);
+    assert_eq!(coverage.hits(0x0601), 0);
+    assert_eq!(coverage.branches[&0x0603], Branch { taken: 2, not_taken: 1 });
+    assert!(cpu.bus.hooks.is_empty());
+}
+
+#[test]
+fn test_branch_before_an_interrupt() {
+    let mut cpu = cpu_with("ldx #$01\nbne skip\nnop\nskip:\nbrk\nrti");
+    cpu.mem_write_u16(NMI_VECTOR, 0x0606);
+    let recorder = CoverageRecorder::attach(&mut cpu);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    // the NMI comes before the instruction the branch went to
+    cpu.nmi_pending = true;
+    run(&mut cpu);
+
+    let coverage = recorder.coverage();
+    assert_eq!(coverage.branches[&0x0602], Branch { taken: 1, not_taken: 0 });
+    assert_eq!(coverage.hits(0x0606), 1);
+    assert_eq!(coverage.hits(0x0605), 1);
+}
+
+#[test]
+fn test_listing() {
+    let mut cpu = cpu_with(COUNTDOWN);
+    let recorder = CoverageRecorder::attach(&mut cpu);
+    run(&mut cpu);
+    let mut symbols = Symbols::new();
+    symbols.parse_nl("$0602#loop#\n", None).unwrap();
+
+    let listing = recorder.coverage().listing(&cpu, &symbols);
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(
+        lines,
+        [
+            "         1  0600  A2 03     LDX #$03",
+            "loop:",
+            "         3  0602  CA        DEX",
+            "         3  0603  D0 FD     BNE loop  ; taken 2, not taken 1",
+            "         1  0605  00        BRK",
+        ]
+    );
+}
+
+#[test]
+fn test_lcov_against_source_lines() {
+    let mut cpu = cpu_with(COUNTDOWN);
+    let recorder = CoverageRecorder::attach(&mut cpu);
+    run(&mut cpu);
+    let mut symbols = Symbols::new();
+    // line 9 is three bytes of data after the BRK
+    let dbg = r#"file	id=0,name="countdown.s",size=100,mtime=0x5F000000,mod=0
+seg	id=0,name="CODE",start=0x000600,size=0x0009,addrsize=absolute,type=rw
+span	id=0,seg=0,start=0,size=2
+span	id=1,seg=0,start=2,size=1
+span	id=2,seg=0,start=3,size=2
+span	id=3,seg=0,start=5,size=1
+span	id=4,seg=0,start=6,size=3
+line	id=0,file=0,line=3,span=0
+line	id=1,file=0,line=5,span=1
+line	id=2,file=0,line=6,span=2
+line	id=3,file=0,line=7,span=3
+line	id=4,file=0,line=9,span=4
+line	id=5,file=0,line=6,type=2,span=1
+"#;
+    symbols.parse_dbg(dbg).unwrap();
+
+    let expected = "TN:\nSF:countdown.s\nBRDA:6,0,0,2\nBRDA:6,0,1,1\nBRF:2\nBRH:2\n\
+                    DA:3,1\nDA:5,3\nDA:6,3\nDA:7,1\nLF:4\nLH:4\nend_of_record\n";
+    assert_eq!(recorder.coverage().lcov(&cpu, &symbols, "unused.lst"), expected);
+}
+
+#[test]
+fn test_lcov_against_the_listing() {
+    let mut cpu = cpu_with("ldx #$00\nbne skip\nnop\nskip:\nbrk");
+    let recorder = CoverageRecorder::attach(&mut cpu);
+    run(&mut cpu);
+
+    let expected = "TN:\nSF:out.lst\nBRDA:2,0,0,0\nBRDA:2,0,1,1\nBRF:2\nBRH:1\n\
+                    DA:1,1\nDA:2,1\nDA:3,1\nDA:4,1\nLF:4\nLH:4\nend_of_record\n";
+    assert_eq!(recorder.coverage().lcov(&cpu, &Symbols::new(), "out.lst"), expected);
+}
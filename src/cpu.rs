@@ -1,16 +1,66 @@
 use std::collections::HashMap;
-use std::time::Duration;
+use std::fmt;
+use crate::bus::Bus;
+use crate::callstack::{CallStack, Entry, Frame};
+use crate::loader::Program;
+use crate::opcodes::OPCODES_MAP;
+use crate::savestate::{StateReader, StateWriter, CPU_CHUNK};
 pub mod op_test;
-mod op;
+pub mod op;
 
 #[derive(Clone)]
 struct OpCode {
-    name: String,
+    name: &'static str,
     op_length: u8,
+    // `step` counts the cycles of `opcodes::OPCODES`, which covers every opcode
+    #[allow(dead_code)]
     cycles: u8,
     mode: AddressingMode,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+    Brk,
+}
+
+impl Interrupt {
+    pub fn vector(&self) -> u16 {
+        match self {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq | Interrupt::Brk => IRQ_VECTOR,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Interrupt::Nmi => "NMI",
+            Interrupt::Irq => "IRQ",
+            Interrupt::Brk => "BRK",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CpuError {
+    UnknownOpcode { pc: u16, code: u8 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { pc, code } => {
+                write!(f, "unknown opcode ${:02x} at ${:04x}", code, pc)
+            }
+        }
+    }
+}
+
+pub const NMI_VECTOR: u16 = 0xfffa;
+pub const RESET_VECTOR: u16 = 0xfffc;
+pub const IRQ_VECTOR: u16 = 0xfffe;
+
 #[derive(Clone)]
 #[allow(non_camel_case_types)]
 pub struct CPU {
@@ -20,7 +70,20 @@ pub struct CPU {
     pub status: u8,
     pub program_counter: u16,
     pub stack_counter: u8,
-    memory: [u8; 0x10000],
+    pub bus: Bus,
+    // Easy6502 and the unit tests end programs with BRK, everything else
+    // wants the real interrupt through $fffe
+    pub halt_on_brk: bool,
+    // CPU cycles since power on
+    pub cycles: u64,
+    // set by indexed addressing when the index carried into the next page
+    page_crossed: bool,
+    // raised by the PPU at vblank, taken before the next instruction
+    pub nmi_pending: bool,
+    // level triggered, mappers and the APU hold it, taken while I is clear
+    pub irq_line: bool,
+    // the JSRs and interrupts being run, beside the real stack
+    pub call_stack: CallStack,
 
     op_map: HashMap<u8, OpCode>,
 }
@@ -50,33 +113,29 @@ enum StatusFlag {
     Interrupt = 0b0000_0100,
     DecimalMode = 0b0000_1000,
     BreakCommand = 0b0001_0000,
-    Overflow = 0b0010_0000,
-    Negative = 0b0100_0000,
+    // not a real flag, reads as 1 whenever the status is pushed
+    Unused = 0b0010_0000,
+    Overflow = 0b0100_0000,
+    Negative = 0b1000_0000,
 }
 
 impl StatusFlag {
     fn reverse(&self) -> u8 {
-        return 0b1111_1111 ^ (*self as u8);
+        0b1111_1111 ^ (*self as u8)
     }
 
     fn among(&self, status: u8) -> bool {
-        return status & (*self as u8) == (*self as u8);
+        status & (*self as u8) == (*self as u8)
     }
 
     fn add(&self, status: &mut u8) {
         *status = *status | *self;
     }
     fn remove(&self, status: &mut u8) {
-        *status = *status & !(*self as u8);
+        *status &= !(*self as u8);
     }
     fn test(&self, status: u8) -> bool {
-        return *self & status == *self;
-    }
-    fn or_get(&self, status: u8) -> u8 {
-        return status | *self;
-    }
-    fn and_get(&self, status: u8) -> u8 {
-        return status & *self;
+        *self & status == *self
     }
 }
 
@@ -95,7 +154,6 @@ impl BitAnd<StatusFlag> for u8 {
 }
 
 use std::ops::BitOr;
-use std::thread::sleep;
 impl BitOr<u8> for StatusFlag {
     type Output = u8;
     fn bitor(self, rhs: u8) -> Self::Output {
@@ -111,19 +169,19 @@ impl BitOr<StatusFlag> for u8 {
 
 impl PartialEq<u8> for StatusFlag {
     fn eq(&self, other: &u8) -> bool {
-        return *other == *self as u8;
+        *other == *self as u8
     }
 }
 impl PartialEq<StatusFlag> for u8 {
     fn eq(&self, other: &StatusFlag) -> bool {
-        return *self == *other as u8;
+        *self == *other as u8
     }
 }
 
 impl OpCode {
-    pub fn new(name: &str, op_length: u8, cycles: u8, mode: AddressingMode) -> Self {
+    pub fn new(name: &'static str, op_length: u8, cycles: u8, mode: AddressingMode) -> Self {
         OpCode {
-            name: name.to_string(),
+            name,
             op_length,
             cycles,
             mode,
@@ -131,8 +189,18 @@ impl OpCode {
     }
 }
 
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl CPU {
     pub fn new() -> Self {
+        CPU::with_bus(Bus::new())
+    }
+
+    pub fn with_bus(bus: Bus) -> Self {
         let mut op_map: HashMap<u8, OpCode> = HashMap::new();
 
         // LDA:
@@ -553,6 +621,9 @@ impl CPU {
         // BVS
         op_map.insert(0x70, OpCode::new("BVS", 2, 2, AddressingMode::Immediate));
 
+        // BMI
+        op_map.insert(0x30, OpCode::new("BMI", 2, 2, AddressingMode::Immediate));
+
         CPU {
             register_a: 0,
             register_x: 0,
@@ -560,17 +631,27 @@ impl CPU {
             status: 0,
             program_counter: 0,
             stack_counter: 0,
-            memory: [0u8; 0x10000],
+            bus,
+            halt_on_brk: true,
+            cycles: 0,
+            page_crossed: false,
+            nmi_pending: false,
+            irq_line: false,
+            call_stack: CallStack::new(),
             op_map,
         }
     }
 
     pub fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+        self.bus.mem_read(addr)
     }
 
     pub fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data
+        self.bus.mem_write(addr, data)
+    }
+
+    pub fn peek(&self, addr: u16) -> u8 {
+        self.bus.peek(addr)
     }
 
     pub fn load_and_run(&mut self, program: Vec<u8>) {
@@ -581,199 +662,306 @@ impl CPU {
 
     pub fn mem_read_u16(&mut self, pos: u16) -> u16 {
         let lo = self.mem_read(pos) as u16;
-        let hi = self.mem_read(pos + 1) as u16;
-        (hi << 8) | (lo as u16)
+        let hi = self.mem_read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
     }
 
     pub fn mem_write_u16(&mut self, pos: u16, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xff) as u8;
         self.mem_write(pos, lo);
-        self.mem_write(pos + 1, hi);
+        self.mem_write(pos.wrapping_add(1), hi);
     }
 
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
         self.status = 0;
-        self.program_counter = self.mem_read_u16(0xFFFC);
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
         /* [0x0100 .. 0x1ff] */
         self.stack_counter = 0xff;
+        // the reset sequence itself takes 7 cycles
+        self.cycles = 7;
+        self.call_stack.clear();
     }
 
-    pub fn load(&mut self, program: Vec<u8>) {
-        // normal address
-        // self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
+    // the reset button: memory and registers survive, the CPU only skips
+    // three stack slots and disables interrupts before jumping to the vector
+    pub fn soft_reset(&mut self) {
+        self.stack_counter = self.stack_counter.wrapping_sub(3);
+        StatusFlag::Interrupt.add(&mut self.status);
+        self.program_counter = self.mem_read_u16(RESET_VECTOR);
+        self.call_stack.clear();
+    }
 
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.bus.rom_hash());
+        self.write_state(&mut state);
+        state.finish()
+    }
 
-        // greedy snake
-        self.memory[0x0600..(0x0600 + program.len())].copy_from_slice(&program[..]);
-        // set where address the program start
-        self.mem_write_u16(0xFFFC, 0x0600);
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let state = StateReader::new(data)?;
+        if state.rom_hash != self.bus.rom_hash() {
+            return Err("save state was made with a different ROM".to_string());
+        }
+        self.read_state(&state)
+    }
+
+    // the CPU and bus chunks, for machines that add their own and check the
+    // hash themselves
+    pub fn write_state(&self, state: &mut StateWriter) {
+        let pc = self.program_counter.to_le_bytes();
+        let mut registers = vec![
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.status,
+            pc[0],
+            pc[1],
+            self.stack_counter,
+        ];
+        registers.extend_from_slice(&self.cycles.to_le_bytes());
+        registers.extend_from_slice(&[self.nmi_pending as u8, self.irq_line as u8]);
+        state.chunk(CPU_CHUNK, &registers);
+        self.bus.save_state(state);
+    }
+
+    pub fn read_state(&mut self, state: &StateReader) -> Result<(), String> {
+        let registers = state.require(CPU_CHUNK, 7)?;
+        self.bus.load_state(state)?;
+        self.register_a = registers[0];
+        self.register_x = registers[1];
+        self.register_y = registers[2];
+        self.status = registers[3];
+        self.program_counter = u16::from_le_bytes([registers[4], registers[5]]);
+        self.stack_counter = registers[6];
+        // states from before the cycle count was saved keep counting on
+        if let Some(cycles) = registers.get(7..15) {
+            self.cycles = u64::from_le_bytes(cycles.try_into().unwrap());
+        }
+        if let Some(lines) = registers.get(15..17) {
+            self.nmi_pending = lines[0] != 0;
+            self.irq_line = lines[1] != 0;
+        }
+        self.call_stack.clear();
+        Ok(())
+    }
+
+    // loads a bare program at $0600 and starts it there, like Easy6502 does
+    pub fn load(&mut self, program: Vec<u8>) {
+        self.load_program(&Program::at(0x0600, program))
+            .expect("program doesn't fit in memory");
+    }
+
+    pub fn load_program(&mut self, program: &Program) -> Result<(), String> {
+        program.load_into(self)
     }
 
     pub fn run(&mut self) {
         self.run_with_callbacks(|_|{});
     }
 
+    // for tests and tools that expect a well behaved program, panics on errors
     pub fn run_with_callbacks<F>(&mut self, mut callback: F) where F: FnMut(&mut CPU) {
         loop {
             callback(self);
-            if self.program_counter < self.mem_read_u16(0xFFFC) {
-                panic!("invalid program_counter:{}", self.program_counter);
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => return,
+                Err(e) => panic!("{}", e),
             }
-            let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            if self.op_map.contains_key(&code) {
-                let op = self.op_map[&code].clone();
-                // self.program_counter += (op.op_length - 1) as u16;
-                let mode = &op.mode;
-                match op.name.as_str() {
-                    "LDA" => {
-                        self.lda(&mode);
-                    }
-                    "LDX" => {
-                        self.ldx(&mode);
-                    }
-                    "LDY" => {
-                        self.ldy(&mode);
-                    }
-                    "STA" => {
-                        self.sta(&mode);
-                    }
-                    "STX" => {
-                        self.stx(&mode);
-                    }
-                    "STY" => {
-                        self.sty(&mode);
-                    }
-                    "ADC" => {
-                        self.adc(&mode);
-                    }
-                    "SBC" => {
-                        self.sbc(&mode);
-                    }
-                    "AND" => {
-                        self.and(&mode);
-                    }
-                    "ORA" => {
-                        self.ora(&mode);
-                    }
-                    "EOR" => {
-                        self.eor(&mode);
-                    }
-                    "ASL" => {
-                        self.asl(&mode);
-                    }
-                    "LSR" => {
-                        self.lsr(&mode);
-                    }
-                    "ROL" => {
-                        self.rol(&mode);
-                    }
-                    "ROR" => {
-                        self.ror(&mode);
-                    }
-                    "BIT" => {
-                        self.bit(&mode);
-                    }
-                    "CMP" => {
-                        self.cmp(&mode);
-                    }
-                    "CPX" => {
-                        self.cpx(&mode);
-                    }
-                    "CPY" => {
-                        self.cpy(&mode);
-                    }
-                    "DEC" => {
-                        self.dec(&mode);
-                    }
-                    "INC" => {
-                        self.inc(&mode);
-                    }
-                    "JMP" => {
-                        self.jmp(&mode);
-                    }
-                    "BCC" => {
-                        self.bcc(&mode);
-                    }
-                    "BCS" => {
-                        self.bcs(&mode);
-                    }
-                    "BEQ" => {
-                        self.beq(&mode);
-                    }
-                    "BMI" => {
-                        self.bmi(&mode);
-                    }
-                    "BNE" => {
-                        self.bne(&mode);
-                    }
-                    "BPL" => {
-                        self.bpl(&mode);
-                    }
-                    "BVC" => {
-                        self.bvc(&mode);
-                    }
-                    "BVS" => {
-                        self.bvs(&mode);
-                    }
-                    _ => {
-                        panic!("Internal error in op_map match~");
-                    }
+        }
+    }
+
+    // the interrupt the next `step` takes instead of an instruction, if any
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        if self.nmi_pending {
+            Some(Interrupt::Nmi)
+        } else if self.irq_line && !StatusFlag::Interrupt.among(self.status) {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    // execute a single instruction, returns false once BRK is reached
+    pub fn step(&mut self) -> Result<bool, CpuError> {
+        if let Some(interrupt) = self.pending_interrupt() {
+            self.nmi_pending &= interrupt != Interrupt::Nmi;
+            let (from, sp) = (self.program_counter, self.stack_counter);
+            self.interrupt(interrupt.vector());
+            self.entered(Entry::Interrupt(interrupt), from, from, sp);
+            return Ok(true);
+        }
+        let pc = self.program_counter;
+        let code = self.bus.fetch(pc);
+        let cycles = match OPCODES_MAP.get(&code) {
+            Some(info) => info.cycles,
+            None => return Err(CpuError::UnknownOpcode { pc, code }),
+        };
+        self.program_counter = self.program_counter.wrapping_add(1);
+        self.cycles += cycles as u64;
+        self.page_crossed = false;
+
+        if let Some(op) = self.op_map.get(&code).cloned() {
+            // self.program_counter += (op.op_length - 1) as u16;
+            let mode = &op.mode;
+            match op.name {
+                "LDA" => {
+                    self.lda(mode);
                 }
-                if op.name != "JMP" {
-                    self.program_counter += (op.op_length - 1) as u16;
+                "LDX" => {
+                    self.ldx(mode);
                 }
-                continue;
-            }
-
-            // single address mode
-            match code {
-                op::TAX => self.tax(),
-                op::TAY => self.tay(),
-                op::TSX => self.tsx(),
-                op::TXA => self.txa(),
-                op::TXS => self.txs(),
-                op::TYA => self.tya(),
-                op::SEC => self.sec(),
-                op::CLC => self.clc(),
-                0x0a => self.asl_accumulate(),
-                0x4a => self.lsr_accumulate(),
-                0x2a => self.rol_accumulate(),
-                0x6a => self.ror_accumulate(),
-                op::DEX => self.dex(),
-                op::DEY => self.dey(),
-                op::CLI => self.cli(),
-                op::CLD => self.cld(),
-                op::CLV => self.clv(),
-                op::INY => self.iny(),
-                op::INX => self.inx(),
-                op::NOP => self.nop(),
-                op::PHA => self.pha(),
-                op::PHP => self.php(),
-                op::PLA => self.pla(),
-                op::PLP => self.plp(),
-                op::RTI => self.rti(),
-                op::JSR => self.jsr(),
-                op::RTS => self.rts(),
-                op::SED => self.sed(),
-                op::SEI => self.sei(),
-                // op::BRK => self.brk(),
-                0x00 => {
-                    // sleep(Duration::new(5, 0));
-                    return;
+                "LDY" => {
+                    self.ldy(mode);
+                }
+                "STA" => {
+                    self.sta(mode);
+                }
+                "STX" => {
+                    self.stx(mode);
+                }
+                "STY" => {
+                    self.sty(mode);
+                }
+                "ADC" => {
+                    self.adc(mode);
+                }
+                "SBC" => {
+                    self.sbc(mode);
+                }
+                "AND" => {
+                    self.and(mode);
+                }
+                "ORA" => {
+                    self.ora(mode);
+                }
+                "EOR" => {
+                    self.eor(mode);
+                }
+                "ASL" => {
+                    self.asl(mode);
+                }
+                "LSR" => {
+                    self.lsr(mode);
+                }
+                "ROL" => {
+                    self.rol(mode);
+                }
+                "ROR" => {
+                    self.ror(mode);
+                }
+                "BIT" => {
+                    self.bit(mode);
+                }
+                "CMP" => {
+                    self.cmp(mode);
+                }
+                "CPX" => {
+                    self.cpx(mode);
+                }
+                "CPY" => {
+                    self.cpy(mode);
+                }
+                "DEC" => {
+                    self.dec(mode);
+                }
+                "INC" => {
+                    self.inc(mode);
+                }
+                "JMP" => {
+                    self.jmp(mode);
+                }
+                "BCC" => {
+                    self.bcc(mode);
+                }
+                "BCS" => {
+                    self.bcs(mode);
+                }
+                "BEQ" => {
+                    self.beq(mode);
+                }
+                "BMI" => {
+                    self.bmi(mode);
+                }
+                "BNE" => {
+                    self.bne(mode);
+                }
+                "BPL" => {
+                    self.bpl(mode);
+                }
+                "BVC" => {
+                    self.bvc(mode);
+                }
+                "BVS" => {
+                    self.bvs(mode);
                 }
                 _ => {
-                    println!("{:x}", code);
-                    todo!()
+                    panic!("Internal error in op_map match~");
                 }
             }
-            /*if code != op::JSR && code != op::RTI {
-                self.program_counter += 1;
-            }*/
+            if op.name != "JMP" {
+                self.program_counter = self.program_counter.wrapping_add((op.op_length - 1) as u16);
+            }
+            let reads = matches!(op.name, "LDA" | "LDX" | "LDY" | "ADC" | "SBC" | "AND" | "ORA" | "EOR" | "CMP");
+            if reads && self.page_crossed {
+                self.cycles += 1;
+            }
+            return Ok(true);
+        }
+
+        // single address mode
+        match code {
+            op::TAX => self.tax(),
+            op::TAY => self.tay(),
+            op::TSX => self.tsx(),
+            op::TXA => self.txa(),
+            op::TXS => self.txs(),
+            op::TYA => self.tya(),
+            op::SEC => self.sec(),
+            op::CLC => self.clc(),
+            0x0a => self.asl_accumulate(),
+            0x4a => self.lsr_accumulate(),
+            0x2a => self.rol_accumulate(),
+            0x6a => self.ror_accumulate(),
+            op::DEX => self.dex(),
+            op::DEY => self.dey(),
+            op::CLI => self.cli(),
+            op::CLD => self.cld(),
+            op::CLV => self.clv(),
+            op::INY => self.iny(),
+            op::INX => self.inx(),
+            op::NOP => self.nop(),
+            op::PHA => self.pha(),
+            op::PHP => self.php(),
+            op::PLA => self.pla(),
+            op::PLP => self.plp(),
+            op::RTI => {
+                self.rti();
+                self.call_stack.leave(pc, self.stack_counter, self.program_counter);
+            }
+            op::JSR => {
+                let sp = self.stack_counter;
+                self.jsr();
+                self.entered(Entry::Call, pc, pc.wrapping_add(3), sp);
+            }
+            op::RTS => {
+                self.rts();
+                self.call_stack.leave(pc, self.stack_counter, self.program_counter);
+            }
+            op::SED => self.sed(),
+            op::SEI => self.sei(),
+            op::BRK if self.halt_on_brk => return Ok(false),
+            op::BRK => {
+                let sp = self.stack_counter;
+                self.brk();
+                self.entered(Entry::Interrupt(Interrupt::Brk), pc, pc.wrapping_add(2), sp);
+            }
+            _ => unreachable!("opcode ${:02x} is documented but not implemented", code),
         }
+        Ok(true)
     }
 
     fn lda(&mut self, mode: &AddressingMode) {
@@ -812,57 +1000,87 @@ impl CPU {
         self.mem_write(addr, self.register_y);
     }
 
-    fn add_rega_and_value(&mut self, value: u8) {
-        let (result, carry) = self.register_a.overflowing_add(value);
-        if carry {
-            StatusFlag::Carry.add(&mut self.status);
+    fn set_flag(&mut self, flag: StatusFlag, on: bool) {
+        if on {
+            flag.add(&mut self.status);
         } else {
-            StatusFlag::Carry.remove(&mut self.status);
+            flag.remove(&mut self.status);
         }
+    }
 
-        let (_, overflowed) = (self.register_a as i8).overflowing_add(value as i8);
-        if overflowed {
-            StatusFlag::Overflow.add(&mut self.status);
-        } else {
-            StatusFlag::Overflow.remove(&mut self.status);
-        }
+    // SBC is ADC of the inverted value, the carry acting as "no borrow"
+    fn add_rega_and_value(&mut self, value: u8) {
+        let carry = StatusFlag::Carry.among(self.status) as u16;
+        let sum = self.register_a as u16 + value as u16 + carry;
+        let result = sum as u8;
+        self.set_flag(StatusFlag::Carry, sum > 0xff);
+        self.set_flag(
+            StatusFlag::Overflow,
+            (self.register_a ^ result) & (value ^ result) & 0x80 != 0,
+        );
         self.register_a = result;
         self.update_zero_and_negative_flags(self.register_a);
     }
 
-    fn adc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-        self.add_rega_and_value(value);
+    // NMOS behaviour: N, V and Z come from the intermediate results, only A and
+    // C are meaningful for valid BCD operands
+    fn adc_decimal(&mut self, value: u8) {
+        let a = self.register_a;
+        let carry = StatusFlag::Carry.among(self.status) as i16;
+        let binary = a.wrapping_add(value).wrapping_add(carry as u8);
+
+        let mut low = (a & 0x0f) as i16 + (value & 0x0f) as i16 + carry;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+        let mut sum = (a & 0xf0) as i16 + (value & 0xf0) as i16 + low;
+        let signed = (a & 0xf0) as i8 as i16 + (value & 0xf0) as i8 as i16 + low;
+        self.update_zero_and_negative_flags(sum as u8);
+        self.set_flag(StatusFlag::Zero, binary == 0);
+        self.set_flag(StatusFlag::Overflow, !(-128..=127).contains(&signed));
+        if sum >= 0xa0 {
+            sum += 0x60;
+        }
+        self.set_flag(StatusFlag::Carry, sum >= 0x100);
+        self.register_a = sum as u8;
     }
 
-    fn sbc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-        self.add_rega_and_value(!value + 1);
+    // flags are those of the binary subtraction on NMOS parts
+    fn sbc_decimal(&mut self, value: u8) {
+        let a = self.register_a;
+        let carry = StatusFlag::Carry.among(self.status) as i16;
+
+        let mut low = (a & 0x0f) as i16 - (value & 0x0f) as i16 + carry - 1;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0f) - 0x10;
+        }
+        let mut result = (a & 0xf0) as i16 - (value & 0xf0) as i16 + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+        self.add_rega_and_value(!value);
+        self.register_a = result as u8;
     }
 
-    /*
-    fn sbc(&mut self, mode: &AddressingMode) {
+    fn adc(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let value = self.mem_read(addr);
-
-        let (result, carry) = self.register_a.overflowing_sub(value);
-        if carry {
-            self.status = self.status & StatusFlag::Carry.reverse();
+        if StatusFlag::DecimalMode.among(self.status) {
+            self.adc_decimal(value);
         } else {
-            self.status = self.status | StatusFlag::Carry;
+            self.add_rega_and_value(value);
         }
+    }
 
-        let (_, overflowed) = (self.register_a as i8).overflowing_sub(value as i8);
-        if overflowed {
-            self.status = self.status | StatusFlag::Overflow;
+    fn sbc(&mut self, mode: &AddressingMode) {
+        let addr = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        if StatusFlag::DecimalMode.among(self.status) {
+            self.sbc_decimal(value);
         } else {
-            self.status = self.status & StatusFlag::Overflow.reverse();
+            self.add_rega_and_value(!value);
         }
-        self.register_a = result;
-        self.update_zero_and_negative_flags(self.register_a);
-    }*/
+    }
 
     fn and(&mut self, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
@@ -918,68 +1136,48 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_a);
     }
 
-    fn bcc(&mut self, mode: &AddressingMode) {
-        if !StatusFlag::Carry.among(self.status) {
-            let addr = self.get_operand_address(&mode);
+    // a taken branch costs a cycle, one more if it lands in another page
+    fn branch(&mut self, mode: &AddressingMode, condition: bool) {
+        if condition {
+            let addr = self.get_operand_address(mode);
             let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
+            let next = self.program_counter.wrapping_add(1);
+            let target = next.wrapping_add(value as i8 as u16);
+            self.cycles += if next & 0xff00 == target & 0xff00 { 1 } else { 2 };
+            self.program_counter = target.wrapping_sub(1);
         }
     }
 
+    fn bcc(&mut self, mode: &AddressingMode) {
+        self.branch(mode, !StatusFlag::Carry.among(self.status));
+    }
+
     fn bcs(&mut self, mode: &AddressingMode) {
-        if StatusFlag::Carry.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, StatusFlag::Carry.among(self.status));
     }
 
     fn beq(&mut self, mode: &AddressingMode) {
-        if StatusFlag::Zero.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, StatusFlag::Zero.among(self.status));
     }
 
     fn bne(&mut self, mode: &AddressingMode) {
-        if !StatusFlag::Zero.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, !StatusFlag::Zero.among(self.status));
     }
 
     fn bmi(&mut self, mode: &AddressingMode) {
-        if StatusFlag::Negative.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, StatusFlag::Negative.among(self.status));
     }
 
     fn bpl(&mut self, mode: &AddressingMode) {
-        if !StatusFlag::Negative.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, !StatusFlag::Negative.among(self.status));
     }
 
     fn bvc(&mut self, mode: &AddressingMode) {
-        if !StatusFlag::Overflow.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, !StatusFlag::Overflow.among(self.status));
     }
 
     fn bvs(&mut self, mode: &AddressingMode) {
-        if StatusFlag::Overflow.among(self.status) {
-            let addr = self.get_operand_address(&mode);
-            let value = self.mem_read(addr);
-            self.program_counter = ((self.program_counter as i16) + ((value as i8) as i16)) as u16;
-        }
+        self.branch(mode, StatusFlag::Overflow.among(self.status));
     }
 
     fn lsr(&mut self, mode: &AddressingMode) {
@@ -1105,52 +1303,23 @@ impl CPU {
         }
     }
 
-    fn cmp(&mut self, mode: &AddressingMode) {
+    fn compare(&mut self, register: u8, mode: &AddressingMode) {
         let addr = self.get_operand_address(mode);
         let value = self.mem_read(addr);
+        self.set_flag(StatusFlag::Carry, register >= value);
+        self.update_zero_and_negative_flags(register.wrapping_sub(value));
+    }
 
-        if self.register_a >= value {
-            StatusFlag::Carry.add(&mut self.status);
-        } else {
-            StatusFlag::Carry.remove(&mut self.status);
-        }
-        if self.register_a == value {
-            StatusFlag::Zero.add(&mut self.status);
-        } else {
-            StatusFlag::Zero.remove(&mut self.status);
-        }
+    fn cmp(&mut self, mode: &AddressingMode) {
+        self.compare(self.register_a, mode);
     }
 
     fn cpx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-
-        if self.register_x >= value {
-            StatusFlag::Carry.add(&mut self.status);
-        } else {
-            StatusFlag::Carry.remove(&mut self.status);
-        }
-        if self.register_x == value {
-            StatusFlag::Zero.add(&mut self.status);
-        } else {
-            StatusFlag::Zero.remove(&mut self.status);
-        }
+        self.compare(self.register_x, mode);
     }
 
     fn cpy(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr);
-
-        if self.register_y >= value {
-            StatusFlag::Carry.add(&mut self.status);
-        } else {
-            StatusFlag::Carry.remove(&mut self.status);
-        }
-        if self.register_y == value {
-            StatusFlag::Zero.add(&mut self.status);
-        } else {
-            StatusFlag::Zero.remove(&mut self.status);
-        }
+        self.compare(self.register_y, mode);
     }
 
     fn dec(&mut self, mode: &AddressingMode) {
@@ -1172,8 +1341,8 @@ impl CPU {
 
     fn jsr(&mut self) {
         let addr = self.get_operand_address(&AddressingMode::Absolute);
-        // self.program_counter + 2 -1
-        self.push_u16(self.program_counter + 1);
+        // the return address is the last byte of the JSR, RTS adds one
+        self.push_u16(self.program_counter.wrapping_add(1));
         self.program_counter = addr;
     }
 
@@ -1186,9 +1355,7 @@ impl CPU {
     }
 
     fn php(&mut self) {
-        let mut status = self.status;
-        StatusFlag::BreakCommand.add(&mut status);
-        self.push(status);
+        self.push(self.status | StatusFlag::BreakCommand as u8 | StatusFlag::Unused as u8);
     }
 
     fn pla(&mut self) {
@@ -1196,20 +1363,18 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_a);
     }
 
+    // B and bit 5 only exist on the stack
     fn plp(&mut self) {
-        self.status = self.pop();
+        self.status = self.pop() & !(StatusFlag::BreakCommand as u8 | StatusFlag::Unused as u8);
     }
 
     fn rti(&mut self) {
-        self.status = self.pop();
-        // todo: need verify from spec
+        self.plp();
         self.program_counter = self.pop_u16();
     }
 
     fn rts(&mut self) {
-        // todo: need verify from spec
-        self.program_counter = self.pop_u16();
-        self.program_counter += 1;
+        self.program_counter = self.pop_u16().wrapping_add(1);
     }
 
     fn jmp(&mut self, mode: &AddressingMode) {
@@ -1218,7 +1383,10 @@ impl CPU {
             self.program_counter = addr;
         } else {
             assert!(*mode == AddressingMode::Indirect);
-            self.program_counter = self.mem_read_u16(addr);
+            // the pointer's high byte is fetched without carrying into the page
+            let lo = self.mem_read(addr) as u16;
+            let hi = self.mem_read((addr & 0xff00) | (addr as u8).wrapping_add(1) as u16) as u16;
+            self.program_counter = hi << 8 | lo;
         }
     }
 
@@ -1307,66 +1475,67 @@ impl CPU {
         StatusFlag::Carry.remove(&mut self.status);
     }
 
-    fn brk(&mut self) {
+    // BRK is two bytes long, the byte after the opcode is skipped on return
+    // NMI and IRQ: like BRK, without the padding byte and with B clear
+    fn interrupt(&mut self, vector: u16) {
         self.push_u16(self.program_counter);
-        self.push(self.status);
-
-        // todo: force interrupt
-        let value = self.mem_read_u16(0xfffe);
-        self.program_counter = value;
+        self.push(self.status | StatusFlag::Unused as u8);
+        StatusFlag::Interrupt.add(&mut self.status);
+        self.program_counter = self.mem_read_u16(vector);
+        self.cycles += 7;
+    }
 
-        StatusFlag::BreakCommand.add(&mut self.status);
+    fn entered(&mut self, entry: Entry, from: u16, return_to: u16, sp: u8) {
+        self.call_stack.enter(Frame {
+            entry,
+            from,
+            target: self.program_counter,
+            return_to,
+            sp,
+        });
+    }
 
-        // side effect
+    fn brk(&mut self) {
+        self.push_u16(self.program_counter.wrapping_add(1));
+        self.php();
         StatusFlag::Interrupt.add(&mut self.status);
-        // StatusFlag::DecimalMode.remove(&mut self.status);
+        self.program_counter = self.mem_read_u16(IRQ_VECTOR);
     }
 
+    // high byte first, so the low byte ends up at the lower address
     fn push_u16(&mut self, value: u16) {
-        let lo:u8 = (value & 0xff) as u8;
-        let hi:u8 = ((value >> 8) & 0xff) as u8;
-        self.push(lo);
-        self.push(hi);
+        self.push((value >> 8) as u8);
+        self.push(value as u8);
     }
 
+    // the stack pointer wraps around inside page 1
     fn push(&mut self, value: u8) {
         self.mem_write(self.stack_counter as u16 + 0x100, value);
-        let (result, overflow) = self.stack_counter.overflowing_sub(1);
-        self.stack_counter = result;
-        if overflow {
-            panic!("overflow at push");
-        }
+        self.stack_counter = self.stack_counter.wrapping_sub(1);
     }
 
     fn pop_u16(&mut self) -> u16 {
-        let mut result:u16;
-        result = self.pop() as u16;
-        result <<= 8;
-        result |= self.pop() as u16;
-        return result;
+        let lo = self.pop() as u16;
+        let hi = self.pop() as u16;
+        hi << 8 | lo
     }
 
     fn pop(&mut self) -> u8 {
-        let (result, overflow) = self.stack_counter.overflowing_add(1);
-        self.stack_counter = result;
-        if overflow {
-            panic!("overflow at pop");
-        }
-        let value = self.mem_read(self.stack_counter as u16 + 0x100);
-        return value;
+        self.stack_counter = self.stack_counter.wrapping_add(1);
+        self.mem_read(self.stack_counter as u16 + 0x100)
     }
 
     fn update_zero_and_negative_flags(&mut self, result: u8) {
         if result == 0 {
             self.status = self.status | StatusFlag::Zero;
         } else {
-            self.status = self.status & StatusFlag::Zero.reverse();
+            self.status &= StatusFlag::Zero.reverse();
         }
 
         if result & 0b1000_0000 != 0 {
             self.status = self.status | StatusFlag::Negative;
         } else {
-            self.status = self.status & StatusFlag::Negative.reverse();
+            self.status &= StatusFlag::Negative.reverse();
         }
     }
 
@@ -1378,34 +1547,30 @@ impl CPU {
 
             AddressingMode::ZeroPage_X => {
                 let pos = self.mem_read(self.program_counter);
-                let addr = pos.wrapping_add(self.register_x) as u16;
-                addr
+                pos.wrapping_add(self.register_x) as u16
             }
 
             AddressingMode::ZeroPage_Y => {
                 let pos = self.mem_read(self.program_counter);
-                let addr = pos.wrapping_add(self.register_y) as u16;
-                addr
+                pos.wrapping_add(self.register_y) as u16
             }
 
             AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
 
             AddressingMode::Absolute_X => {
                 let base = self.mem_read_u16(self.program_counter);
-                let addr = base.wrapping_add(self.register_x as u16);
-                addr
+                self.indexed(base, self.register_x)
             }
 
             AddressingMode::Absolute_Y => {
                 let base = self.mem_read_u16(self.program_counter);
-                let addr = base.wrapping_add(self.register_y as u16);
-                addr
+                self.indexed(base, self.register_y)
             }
 
             AddressingMode::Indirect_X => {
                 let base = self.mem_read(self.program_counter);
 
-                let ptr: u8 = (base as u8).wrapping_add(self.register_x);
+                let ptr: u8 = base.wrapping_add(self.register_x);
                 let lo = self.mem_read(ptr as u16);
                 let hi = self.mem_read(ptr.wrapping_add(1) as u16);
                 (hi as u16) << 8 | (lo as u16)
@@ -1417,29 +1582,33 @@ impl CPU {
                 //todo: check let ptr: u8 = (base as u8).wrapping_add(self.register_y);
                 // this is similar to Absolute_Y
                 let lo = self.mem_read(base as u16);
-                let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
+                let hi = self.mem_read(base.wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
-                let deref = deref_base.wrapping_add(self.register_y as u16);
-                deref
+                self.indexed(deref_base, self.register_y)
             }
 
-            AddressingMode::Indirect => {
-                todo!();
-            }
+            // only JMP uses it, the pointer is read in `jmp`
+            AddressingMode::Indirect => self.mem_read_u16(self.program_counter),
             AddressingMode::NoneAddressing => {
                 panic!("mode {:?} is not supported", mode);
             }
         }
     }
 
+    fn indexed(&mut self, base: u16, index: u8) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        self.page_crossed = base & 0xff00 != addr & 0xff00;
+        addr
+    }
+
     pub fn negative(&self) -> bool {
-        return StatusFlag::Negative.among(self.status);
+        StatusFlag::Negative.among(self.status)
     }
     pub fn zero(&self) -> bool {
-        return StatusFlag::Zero.among(self.status);
+        StatusFlag::Zero.among(self.status)
     }
     pub fn overflow(&self) -> bool {
-        return StatusFlag::Overflow.among(self.status);
+        StatusFlag::Overflow.among(self.status)
     }
 }
 pub const LDA_IMMEDIATE: u8 = 0xa9u8;
@@ -104,7 +104,7 @@ fn test_adc_overflow_and_carry() {
 #[test]
 fn test_sbc() {
     let mut cpu = CPU::new();
-    cpu.load_and_run(vec![0x69, 0x02,/* 2 */ 0xe9, 0x01, 0x00]);
+    cpu.load_and_run(vec![0x69, 0x02,/* 2 */ SEC, 0xe9, 0x01, 0x00]);
     assert_eq!(cpu.register_a, 1);
     assert_eq!(cpu.status & StatusFlag::Carry, StatusFlag::Carry);
     assert_eq!(cpu.status & StatusFlag::Overflow, 0);
@@ -114,14 +114,13 @@ fn test_sbc() {
 fn test_sbc_overflow_and_carry() {
     let mut cpu = CPU::new();
     // test carry: if overflow with unsigned, clear carry flag
-    // todo: does sbc perform signed minus?
-    cpu.load_and_run(vec![0xe9, 0x01, 0x00]);
+    cpu.load_and_run(vec![SEC, 0xe9, 0x01, 0x00]);
     assert_eq!(cpu.status & StatusFlag::Carry, 0);
     assert_eq!(cpu.register_a, 255);
 
     cpu = CPU::new();
     // test overflow with signed
-    cpu.load_and_run(vec![0x69, 0x7f, /* 0x7f */ 0xe9, 0xff, 0x00]);
+    cpu.load_and_run(vec![0x69, 0x7f, /* 0x7f */ SEC, 0xe9, 0xff, 0x00]);
     assert_eq!(cpu.status & StatusFlag::Overflow, StatusFlag::Overflow);
     assert_eq!(cpu.register_a, 0x80);
 }
@@ -541,3 +540,162 @@ fn test_bne() {
 
 
 
+
+#[test]
+fn test_adc_adds_the_carry() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![SEC, 0x69, 0x01, 0x00]);
+    assert_eq!(cpu.register_a, 2);
+    assert!(!StatusFlag::Carry.among(cpu.status));
+}
+
+#[test]
+fn test_decimal_adc_sbc() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![op::SED, LDA_IMMEDIATE, 0x58, 0x69, 0x46, 0x00]);
+    assert_eq!(cpu.register_a, 0x04);
+    assert!(StatusFlag::Carry.among(cpu.status));
+
+    cpu.load_and_run(vec![op::SED, SEC, LDA_IMMEDIATE, 0x12, 0xe9, 0x21, 0x00]);
+    assert_eq!(cpu.register_a, 0x91);
+    assert!(!StatusFlag::Carry.among(cpu.status));
+}
+
+#[test]
+fn test_cmp_sets_negative() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![LDA_IMMEDIATE, 0x01, 0xc9, 0x02, 0x00]);
+    assert!(cpu.negative());
+    assert!(!StatusFlag::Carry.among(cpu.status));
+}
+
+#[test]
+fn test_bmi() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![op::LDX, 0xff, op::BMI, 0x02, op::LDX, 0x01, op::BRK]);
+    assert_eq!(cpu.register_x, 0xff);
+}
+
+#[test]
+fn test_jmp_indirect_stays_in_the_page() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x02ff, 0x10);
+    cpu.mem_write(0x0200, 0x06);
+    cpu.mem_write(0x0300, 0x07);
+    cpu.load_and_run(vec![0x6c, 0xff, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, op::INX, 0x00]);
+    assert_eq!(cpu.register_x, 1);
+}
+
+#[test]
+fn test_jsr_pushes_the_high_byte_first() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![op::JSR, 0x04, 0x06, 0x00, 0x00]);
+    assert_eq!(cpu.mem_read(0x01ff), 0x06);
+    assert_eq!(cpu.mem_read(0x01fe), 0x02);
+}
+
+#[test]
+fn test_stack_wraps_around() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![LDX_IMMEDIATE, 0x00, op::TXS, LDA_IMMEDIATE, 0x42, op::PHA, op::PLA, op::PLA, 0x00]);
+    assert_eq!(cpu.mem_read(0x0100), 0x42);
+    assert_eq!(cpu.stack_counter, 0x01);
+}
+
+#[test]
+fn test_php_plp_status_bits() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![op::PHP, op::PLA, 0x00]);
+    assert_eq!(cpu.register_a, 0x30);
+
+    cpu.load_and_run(vec![LDA_IMMEDIATE, 0xff, op::PHA, op::PLP, 0x00]);
+    assert_eq!(cpu.status, 0xcf);
+}
+
+#[test]
+fn test_brk_goes_through_the_irq_vector() {
+    let mut cpu = CPU::new();
+    cpu.halt_on_brk = false;
+    // BRK, padding byte, INX; the handler at $0700 does INY and RTI
+    cpu.load(vec![0x00, 0xff, op::INX]);
+    cpu.mem_write_u16(IRQ_VECTOR, 0x0700);
+    cpu.mem_write(0x0700, op::INY);
+    cpu.mem_write(0x0701, op::RTI);
+    cpu.reset();
+    for _ in 0..4 {
+        cpu.step().unwrap();
+    }
+    assert_eq!(cpu.register_y, 1);
+    assert_eq!(cpu.register_x, 1);
+    assert_eq!(cpu.program_counter, 0x0603);
+    assert_eq!(cpu.mem_read(0x01fd) & 0x30, 0x30);
+}
+
+#[test]
+fn test_every_documented_opcode_is_implemented() {
+    for info in crate::opcodes::OPCODES {
+        let mut cpu = CPU::new();
+        cpu.halt_on_brk = false;
+        cpu.mem_write(0x0600, info.code);
+        cpu.program_counter = 0x0600;
+        cpu.step().unwrap();
+    }
+}
+
+#[test]
+fn test_cycles_count_page_crossings_and_taken_branches() {
+    let cycles = |program: Vec<u8>, x: u8| {
+        let mut cpu = CPU::new();
+        cpu.load(program);
+        cpu.reset();
+        cpu.register_x = x;
+        let start = cpu.cycles;
+        cpu.step().unwrap();
+        cpu.cycles - start
+    };
+    // LDA $02f0,X
+    assert_eq!(cycles(vec![0xbd, 0xf0, 0x02], 0x0f), 4);
+    assert_eq!(cycles(vec![0xbd, 0xf0, 0x02], 0x10), 5);
+    // stores always take the extra cycle, it's in their base count
+    assert_eq!(cycles(vec![0x9d, 0xf0, 0x02], 0x10), 5);
+    // BEQ not taken, BNE taken, BNE taken into the previous page
+    assert_eq!(cycles(vec![0xf0, 0x02], 0), 2);
+    assert_eq!(cycles(vec![0xd0, 0x02], 0), 3);
+    assert_eq!(cycles(vec![0xd0, 0xf0], 0), 4);
+}
+
+#[test]
+fn test_unknown_opcode_is_an_error() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![0xe8, 0x02]);
+    cpu.reset();
+    assert_eq!(cpu.step(), Ok(true));
+    assert_eq!(cpu.step(), Err(CpuError::UnknownOpcode { pc: 0x0601, code: 0x02 }));
+    assert_eq!(cpu.program_counter, 0x0601);
+}
+
+#[test]
+fn test_nmi_and_irq() {
+    let mut cpu = CPU::new();
+    cpu.load(vec![op::INX, op::INX]);
+    cpu.mem_write_u16(NMI_VECTOR, 0x0700);
+    cpu.mem_write_u16(IRQ_VECTOR, 0x0800);
+    cpu.reset();
+    cpu.status = 0x01;
+
+    cpu.nmi_pending = true;
+    assert_eq!(cpu.pending_interrupt(), Some(Interrupt::Nmi));
+    cpu.step().unwrap();
+    assert!(!cpu.nmi_pending);
+    assert_eq!(cpu.program_counter, 0x0700);
+    // the return address, then the status with B clear
+    assert_eq!(cpu.mem_read(0x01fd), 0x21);
+    assert_eq!(cpu.mem_read_u16(0x01fe), 0x0600);
+
+    // the IRQ line waits for I to clear
+    cpu.irq_line = true;
+    assert_eq!(cpu.pending_interrupt(), None);
+    cpu.status = 0;
+    cpu.step().unwrap();
+    assert_eq!(cpu.program_counter, 0x0800);
+}
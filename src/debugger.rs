@@ -0,0 +1,278 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::callstack::Entry;
+use crate::cpu::{CpuError, Interrupt, CPU};
+use crate::disasm;
+use crate::hooks::{Access, HookId};
+use crate::machine::Machine;
+use crate::symbols::Symbols;
+
+mod expr;
+
+pub use expr::Expr;
+
+#[cfg(test)]
+mod debugger_test;
+
+const BRK: u8 = 0x00;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    // stops before the instruction at the address runs
+    Pc(u16),
+    // execute stops before the instruction, read and write right after the
+    // instruction that made the access
+    Watch(Access, RangeInclusive<u16>),
+    // stops before the CPU takes the interrupt, or before the BRK runs
+    Interrupt(Interrupt),
+}
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub kind: Kind,
+    pub condition: Option<Expr>,
+    // hits to let through before stopping
+    pub ignore: u64,
+    // removed the first time it stops
+    pub temporary: bool,
+    pub enabled: bool,
+    // times it triggered with its condition true, ignored hits included
+    pub hits: u64,
+}
+
+impl Breakpoint {
+    pub fn new(kind: Kind) -> Breakpoint {
+        Breakpoint {
+            kind,
+            condition: None,
+            ignore: 0,
+            temporary: false,
+            enabled: true,
+            hits: 0,
+        }
+    }
+
+    pub fn pc(addr: u16) -> Breakpoint {
+        Breakpoint::new(Kind::Pc(addr))
+    }
+
+    pub fn watch(access: Access, range: RangeInclusive<u16>) -> Breakpoint {
+        Breakpoint::new(Kind::Watch(access, range))
+    }
+
+    pub fn interrupt(interrupt: Interrupt) -> Breakpoint {
+        Breakpoint::new(Kind::Interrupt(interrupt))
+    }
+
+    pub fn condition(mut self, text: &str) -> Result<Breakpoint, String> {
+        self.condition = Some(Expr::parse(text)?);
+        Ok(self)
+    }
+
+    pub fn ignore(mut self, hits: u64) -> Breakpoint {
+        self.ignore = hits;
+        self
+    }
+
+    pub fn temporary(mut self) -> Breakpoint {
+        self.temporary = true;
+        self
+    }
+
+    fn stops_before(&self, cpu: &CPU) -> bool {
+        // with an interrupt pending the next step runs no instruction
+        let pending = cpu.pending_interrupt();
+        let pc = cpu.program_counter;
+        match &self.kind {
+            Kind::Pc(addr) => pending.is_none() && pc == *addr,
+            Kind::Watch(access, range) => {
+                pending.is_none() && access.contains(Access::EXECUTE) && range.contains(&pc)
+            }
+            Kind::Interrupt(Interrupt::Brk) => pending.is_none() && cpu.peek(pc) == BRK,
+            Kind::Interrupt(interrupt) => pending == Some(*interrupt),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub addr: u16,
+    pub value: u8,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    // the breakpoint's id, and for read and write watchpoints the access
+    Breakpoint(usize, Option<MemoryAccess>),
+    // BRK on a machine that halts on it
+    Halted,
+    Error(CpuError),
+    StepLimit,
+}
+
+// Breakpoints and watchpoints checked around each `Machine::step`. The
+// engine only drives the machine, frontends decide what a stop means.
+#[derive(Default)]
+pub struct Debugger {
+    pub symbols: Symbols,
+    breakpoints: BTreeMap<usize, Breakpoint>,
+    next_id: usize,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.breakpoints.insert(id, breakpoint);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Breakpoint> {
+        self.breakpoints.remove(&id)
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Breakpoint> {
+        self.breakpoints.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Breakpoint> {
+        self.breakpoints.get_mut(&id)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint)> {
+        self.breakpoints.iter().map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    // Runs up to `max_steps` instructions. Breakpoints on the current PC
+    // don't stop the first one, so calling `run` again resumes.
+    pub fn run(&mut self, machine: &mut Machine, max_steps: u64) -> Stop {
+        let accesses = Rc::new(RefCell::new(Vec::new()));
+        let hooks = self.install_watchpoints(machine.cpu_mut(), &accesses);
+        let stop = self.run_steps(machine, max_steps, &accesses);
+        for hook in hooks {
+            machine.cpu_mut().bus.hooks.remove(hook);
+        }
+        stop
+    }
+
+    pub fn step(&mut self, machine: &mut Machine) -> Stop {
+        self.run(machine, 1)
+    }
+
+    // an expression that may use labels, `update_snake + 3` or
+    // `[snake_length] > 8`; ROM labels resolve in the bank mapped now
+    pub fn expr(&self, cpu: &CPU, text: &str) -> Result<Expr, String> {
+        Expr::parse_with(text, |name| self.symbols.address(&cpu.bus, name))
+    }
+
+    // `label`, `label+N` or `$XXXX`
+    pub fn describe(&self, cpu: &CPU, addr: u16) -> String {
+        self.symbols.describe(&cpu.bus, addr)
+    }
+
+    // the calls the CPU is in, outermost first: `NMI → update_sprites → draw_snake`
+    pub fn backtrace(&self, cpu: &CPU) -> String {
+        let frames: Vec<String> = cpu
+            .call_stack
+            .frames()
+            .iter()
+            .map(|frame| match frame.entry {
+                Entry::Call => self.describe(cpu, frame.target),
+                Entry::Interrupt(interrupt) => interrupt.name().to_string(),
+            })
+            .collect();
+        frames.join(" → ")
+    }
+
+    pub fn disassemble(&self, cpu: &CPU, addr: u16) -> String {
+        let instruction = disasm::decode(|addr| cpu.peek(addr), addr);
+        instruction.format_with(|addr| self.symbols.label(&cpu.bus, addr).map(str::to_string))
+    }
+
+    fn run_steps(
+        &mut self,
+        machine: &mut Machine,
+        max_steps: u64,
+        accesses: &Rc<RefCell<Vec<(usize, MemoryAccess)>>>,
+    ) -> Stop {
+        for step in 0..max_steps {
+            if step > 0 {
+                let before: Vec<usize> = self
+                    .breakpoints
+                    .iter()
+                    .filter(|(_, breakpoint)| breakpoint.enabled && breakpoint.stops_before(machine.cpu()))
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in before {
+                    if self.trigger(id, machine.cpu()) {
+                        return Stop::Breakpoint(id, None);
+                    }
+                }
+            }
+            match machine.step() {
+                Ok(true) => {}
+                Ok(false) => return Stop::Halted,
+                Err(e) => return Stop::Error(e),
+            }
+            let seen: Vec<(usize, MemoryAccess)> = accesses.borrow_mut().drain(..).collect();
+            for (id, access) in seen {
+                if self.trigger(id, machine.cpu()) {
+                    return Stop::Breakpoint(id, Some(access));
+                }
+            }
+        }
+        Stop::StepLimit
+    }
+
+    // one hook per read or write watchpoint, logging into `accesses`
+    fn install_watchpoints(
+        &self,
+        cpu: &mut CPU,
+        accesses: &Rc<RefCell<Vec<(usize, MemoryAccess)>>>,
+    ) -> Vec<HookId> {
+        let mut hooks = Vec::new();
+        for (id, breakpoint) in &self.breakpoints {
+            let (access, range) = match &breakpoint.kind {
+                Kind::Watch(access, range) if breakpoint.enabled => (*access - Access::EXECUTE, range),
+                _ => continue,
+            };
+            if access.is_empty() {
+                continue;
+            }
+            let (id, log) = (*id, accesses.clone());
+            hooks.push(cpu.bus.hooks.add(access, range.clone(), move |access, addr, value| {
+                log.borrow_mut().push((id, MemoryAccess { access, addr, value }));
+                None
+            }));
+        }
+        hooks
+    }
+
+    // counts the hit if the condition holds, returns whether to stop
+    fn trigger(&mut self, id: usize, cpu: &CPU) -> bool {
+        let breakpoint = match self.breakpoints.get_mut(&id) {
+            Some(breakpoint) => breakpoint,
+            // a temporary one that already stopped in this step
+            None => return false,
+        };
+        if breakpoint.condition.as_ref().is_some_and(|condition| !condition.is_true(cpu)) {
+            return false;
+        }
+        breakpoint.hits += 1;
+        if breakpoint.hits <= breakpoint.ignore {
+            return false;
+        }
+        if breakpoint.temporary {
+            self.breakpoints.remove(&id);
+        }
+        true
+    }
+}
@@ -0,0 +1,123 @@
+use super::*;
+use crate::testutil::machine;
+
+const COUNTDOWN: &str = "
+        ldx #$05
+    loop:
+        stx $10
+        dex
+        bne loop
+        brk
+";
+
+#[test]
+fn test_pc_breakpoints_resume() {
+    let mut machine = machine(COUNTDOWN);
+    let mut debugger = Debugger::new();
+    let id = debugger.add(Breakpoint::pc(0x0604));
+
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(id, None));
+    assert_eq!(machine.cpu().register_x, 5);
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(id, None));
+    assert_eq!(machine.cpu().register_x, 4);
+    assert_eq!(debugger.get(id).unwrap().hits, 2);
+
+    debugger.remove(id);
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Halted);
+    assert_eq!(debugger.run(&mut machine, 3), Stop::Halted);
+}
+
+#[test]
+fn test_conditions_hit_counts_and_temporary_breakpoints() {
+    let mut machine = machine(COUNTDOWN);
+    let mut debugger = Debugger::new();
+    let conditional = debugger.add(Breakpoint::pc(0x0604).condition("X == #$02").unwrap());
+    let ignoring = debugger.add(Breakpoint::pc(0x0605).ignore(3));
+    let temporary = debugger.add(Breakpoint::pc(0x0602).temporary());
+
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(temporary, None));
+    assert!(debugger.get(temporary).is_none());
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(conditional, None));
+    assert_eq!(machine.cpu().register_x, 2);
+    // DEX has run four times when BNE comes up for the fourth time
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(ignoring, None));
+    assert_eq!(machine.cpu().register_x, 1);
+    assert_eq!(debugger.get(conditional).unwrap().hits, 1);
+    assert_eq!(debugger.get(ignoring).unwrap().hits, 4);
+
+    debugger.get_mut(ignoring).unwrap().enabled = false;
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Halted);
+}
+
+#[test]
+fn test_conditions_see_memory() {
+    let mut machine = machine(COUNTDOWN);
+    let mut debugger = Debugger::new();
+    let id = debugger.add(Breakpoint::pc(0x0604).condition("[$0010] == 3 && A == 0").unwrap());
+    assert_eq!(debugger.run(&mut machine, 1000), Stop::Breakpoint(id, None));
+    assert_eq!(machine.cpu().register_x, 3);
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut machine = machine("lda $20\nsta $21\nldx #$01\nstx $21\nbrk");
+    let mut debugger = Debugger::new();
+    let read = debugger.add(Breakpoint::watch(Access::READ, 0x20..=0x20));
+    let write = debugger.add(Breakpoint::watch(Access::WRITE, 0x21..=0x21).condition("X == 1").unwrap());
+    let execute = debugger.add(Breakpoint::watch(Access::EXECUTE, 0x0604..=0x0605));
+
+    let access = |access, addr, value| Some(MemoryAccess { access, addr, value });
+    // read and write watchpoints stop after the instruction
+    assert_eq!(debugger.run(&mut machine, 100), Stop::Breakpoint(read, access(Access::READ, 0x20, 0)));
+    assert_eq!(machine.cpu().program_counter, 0x0602);
+    assert_eq!(debugger.run(&mut machine, 100), Stop::Breakpoint(execute, None));
+    assert_eq!(machine.cpu().program_counter, 0x0604);
+    assert_eq!(debugger.run(&mut machine, 100), Stop::Breakpoint(write, access(Access::WRITE, 0x21, 1)));
+    // the hooks are gone between runs
+    assert!(machine.cpu().bus.hooks.is_empty());
+}
+
+#[test]
+fn test_interrupt_breakpoints() {
+    let mut machine = machine("nop\ncli\nnop\nbrk");
+    machine.cpu_mut().mem_write_u16(crate::cpu::IRQ_VECTOR, 0x0602);
+    machine.cpu_mut().status = 0x04;
+    machine.cpu_mut().irq_line = true;
+    let mut debugger = Debugger::new();
+    let irq = debugger.add(Breakpoint::interrupt(Interrupt::Irq));
+    let brk = debugger.add(Breakpoint::interrupt(Interrupt::Brk));
+
+    // the IRQ comes once CLI has run, before the next instruction
+    assert_eq!(debugger.run(&mut machine, 100), Stop::Breakpoint(irq, None));
+    assert_eq!(machine.cpu().program_counter, 0x0602);
+    assert_eq!(debugger.run(&mut machine, 100), Stop::Breakpoint(brk, None));
+    assert_eq!(machine.cpu().program_counter, 0x0603);
+    assert_eq!(machine.cpu().stack_counter, 0xfc);
+
+    let nmi = Breakpoint::interrupt(Interrupt::Nmi);
+    assert!(!nmi.stops_before(machine.cpu()));
+    machine.cpu_mut().nmi_pending = true;
+    assert!(nmi.stops_before(machine.cpu()));
+    // the NMI is taken first, the BRK isn't about to run
+    assert!(!debugger.get(brk).unwrap().stops_before(machine.cpu()));
+}
+
+#[test]
+fn test_expressions() {
+    let mut cpu = CPU::new();
+    cpu.register_a = 0x10;
+    cpu.register_x = 3;
+    cpu.mem_write(0xff, 0x80);
+    let eval = |text: &str| Expr::parse(text).unwrap().eval(&cpu);
+
+    assert_eq!(eval("A == #$10 && [$00FF] != 0"), 1);
+    assert_eq!(eval("[$ff] & $80"), 0x80);
+    assert_eq!(eval("x + 1 == 4 || 0"), 1);
+    assert_eq!(eval("!(a >= 17) && %11 == x"), 1);
+    assert_eq!(eval("[$fc + x]"), 0x80);
+    assert_eq!(eval("1 - 2 - 3"), -4);
+    assert!(Expr::parse("A == ").is_err());
+    assert!(Expr::parse("Q == 1").is_err());
+    assert!(Expr::parse("(A == 1").is_err());
+    assert!(Expr::parse("A @ 1").is_err());
+}
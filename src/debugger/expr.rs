@@ -0,0 +1,264 @@
+use std::fmt;
+
+use crate::cpu::CPU;
+
+// Conditions over registers and memory:
+//
+//   A == #$10 && [$00FF] != 0
+//   ([$10] & $80) != 0 || PC >= $C000
+//
+// Registers are A, X, Y, P, SP and PC, `[addr]` is the byte at addr, read
+// without side effects. Numbers are `$` hex, `%` binary or decimal, a
+// leading `#` is allowed. From loosest to tightest the operators are `||`,
+// `&&`, comparisons, `|`, `^`, `&`, `+` and `-`, then unary `!` and `-`.
+// Other names are looked up as labels when parsing. Anything but 0 is true.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Expr>),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    P,
+    SP,
+    PC,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+const LEVELS: &[&[(&str, Op)]] = &[
+    &[("||", Op::Or)],
+    &[("&&", Op::And)],
+    &[("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)],
+    &[("|", Op::BitOr)],
+    &[("^", Op::BitXor)],
+    &[("&", Op::BitAnd)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+];
+
+// longest first, so `<=` isn't read as `<`
+const SYMBOLS: &[&str] = &[
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[", "]",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        Expr::parse_with(text, |_| None)
+    }
+
+    // `labels` gives the address of a name that isn't a register
+    pub fn parse_with(text: &str, labels: impl Fn(&str) -> Option<u16>) -> Result<Expr, String> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            position: 0,
+            labels: &labels,
+        };
+        let expr = parser.binary(0)?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected `{}` in condition", token)),
+        }
+    }
+
+    pub fn eval(&self, cpu: &CPU) -> i64 {
+        match self {
+            Expr::Number(value) => *value,
+            Expr::Register(register) => match register {
+                Register::A => cpu.register_a as i64,
+                Register::X => cpu.register_x as i64,
+                Register::Y => cpu.register_y as i64,
+                Register::P => cpu.status as i64,
+                Register::SP => cpu.stack_counter as i64,
+                Register::PC => cpu.program_counter as i64,
+            },
+            Expr::Memory(addr) => cpu.peek(addr.eval(cpu) as u16) as i64,
+            Expr::Not(inner) => (inner.eval(cpu) == 0) as i64,
+            Expr::Negate(inner) => inner.eval(cpu).wrapping_neg(),
+            Expr::Binary(op, left, right) => {
+                let left = left.eval(cpu);
+                // `||` and `&&` short circuit, a memory read is cheap but not free
+                match op {
+                    Op::Or if left != 0 => return 1,
+                    Op::And if left == 0 => return 0,
+                    _ => {}
+                }
+                let right = right.eval(cpu);
+                match op {
+                    Op::Or | Op::And => (right != 0) as i64,
+                    Op::Eq => (left == right) as i64,
+                    Op::Ne => (left != right) as i64,
+                    Op::Lt => (left < right) as i64,
+                    Op::Le => (left <= right) as i64,
+                    Op::Gt => (left > right) as i64,
+                    Op::Ge => (left >= right) as i64,
+                    Op::BitOr => left | right,
+                    Op::BitXor => left ^ right,
+                    Op::BitAnd => left & right,
+                    Op::Add => left.wrapping_add(right),
+                    Op::Sub => left.wrapping_sub(right),
+                }
+            }
+        }
+    }
+
+    pub fn is_true(&self, cpu: &CPU) -> bool {
+        self.eval(cpu) != 0
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || "#$%_".contains(c)))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected `{}` in condition", rest.chars().next().unwrap()));
+            }
+            tokens.push(word(&rest[..end])?);
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn word(text: &str) -> Result<Token, String> {
+    let number = text.strip_prefix('#').unwrap_or(text);
+    let invalid = || format!("invalid number in condition: {}", text);
+    if let Some(hex) = number.strip_prefix('$') {
+        return i64::from_str_radix(hex, 16).map(Token::Number).map_err(|_| invalid());
+    }
+    if let Some(bin) = number.strip_prefix('%') {
+        return i64::from_str_radix(bin, 2).map(Token::Number).map_err(|_| invalid());
+    }
+    if number.starts_with(|c: char| c.is_ascii_digit()) {
+        return number.parse().map(Token::Number).map_err(|_| invalid());
+    }
+    Ok(Token::Name(text.to_string()))
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    labels: &'a dyn Fn(&str) -> Option<u16>,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.tokens.get(self.position) {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}` in condition", symbol))
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = LEVELS[level].iter().find(|(symbol, _)| self.eat(symbol)).map(|(_, op)| *op) {
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let inner = self.binary(0)?;
+            self.expect(")")?;
+            return Ok(inner);
+        }
+        if self.eat("[") {
+            let addr = self.binary(0)?;
+            self.expect("]")?;
+            return Ok(Expr::Memory(Box::new(addr)));
+        }
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(value)) => Ok(Expr::Number(value)),
+            Some(Token::Name(name)) => register(&name)
+                .map(Expr::Register)
+                .or_else(|| (self.labels)(&name).map(|addr| Expr::Number(addr as i64)))
+                .ok_or_else(|| format!("unknown name in condition: {}", name)),
+            Some(token) => Err(format!("unexpected `{}` in condition", token)),
+            None => Err("condition ends too early".to_string()),
+        }
+    }
+}
+
+fn register(name: &str) -> Option<Register> {
+    match name.to_ascii_uppercase().as_str() {
+        "A" => Some(Register::A),
+        "X" => Some(Register::X),
+        "Y" => Some(Register::Y),
+        "P" => Some(Register::P),
+        "SP" => Some(Register::SP),
+        "PC" => Some(Register::PC),
+        _ => None,
+    }
+}
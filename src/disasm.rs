@@ -0,0 +1,85 @@
+use std::fmt;
+
+use crate::opcodes::{Mode, OpInfo, OPCODES_MAP};
+
+#[cfg(test)]
+mod disasm_test;
+
+// One decoded instruction. Undocumented opcodes decode as a single `.db`
+// byte so a listing can always carry on with the next address.
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub address: u16,
+    pub op: Option<&'static OpInfo>,
+    pub bytes: Vec<u8>,
+}
+
+// `read` must not have side effects, it may be called on I/O registers
+pub fn decode(read: impl Fn(u16) -> u8, address: u16) -> Instruction {
+    let op = OPCODES_MAP.get(&read(address)).copied();
+    let size = op.map_or(1, |op| op.size());
+    let bytes = (0..size as u16).map(|i| read(address.wrapping_add(i))).collect();
+    Instruction { address, op, bytes }
+}
+
+impl Instruction {
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    // the byte or little endian word after the opcode
+    pub fn operand(&self) -> u16 {
+        match self.bytes[..] {
+            [_, lo] => lo as u16,
+            [_, lo, hi] => u16::from_le_bytes([lo, hi]),
+            _ => 0,
+        }
+    }
+
+    // where a branch, JMP or JSR goes, when it's known without the registers
+    pub fn target(&self) -> Option<u16> {
+        let op = self.op?;
+        match op.mode {
+            Mode::Relative => {
+                let next = self.address.wrapping_add(2);
+                Some(next.wrapping_add(self.operand() as u8 as i8 as u16))
+            }
+            Mode::Absolute if matches!(op.mnemonic, "JMP" | "JSR") => Some(self.operand()),
+            _ => None,
+        }
+    }
+
+    // like `to_string`, with `label` naming the addresses it knows; immediate
+    // values are never looked up
+    pub fn format_with(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        let op = match self.op {
+            Some(op) => op,
+            None => return format!(".db ${:02X}", self.bytes[0]),
+        };
+        let value = self.operand();
+        let zero_page = || label(value).unwrap_or_else(|| format!("${:02X}", value));
+        let absolute = |addr| label(addr).unwrap_or_else(|| format!("${:04X}", addr));
+        let operand = match op.mode {
+            Mode::Implied => return op.mnemonic.to_string(),
+            Mode::Accumulator => "A".to_string(),
+            Mode::Immediate => format!("#${:02X}", value),
+            Mode::ZeroPage => zero_page(),
+            Mode::ZeroPageX => format!("{},X", zero_page()),
+            Mode::ZeroPageY => format!("{},Y", zero_page()),
+            Mode::Absolute => absolute(value),
+            Mode::AbsoluteX => format!("{},X", absolute(value)),
+            Mode::AbsoluteY => format!("{},Y", absolute(value)),
+            Mode::Indirect => format!("({})", absolute(value)),
+            Mode::IndirectX => format!("({},X)", zero_page()),
+            Mode::IndirectY => format!("({}),Y", zero_page()),
+            Mode::Relative => absolute(self.target().unwrap()),
+        };
+        format!("{} {}", op.mnemonic, operand)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format_with(|_| None))
+    }
+}
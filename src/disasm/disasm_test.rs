@@ -0,0 +1,60 @@
+use super::*;
+use crate::asm::assemble;
+
+fn listing(source: &str) -> Vec<String> {
+    let code = assemble(source, 0x0600).unwrap();
+    let read = |addr: u16| code.get(addr.wrapping_sub(0x0600) as usize).copied().unwrap_or(0);
+    let mut lines = Vec::new();
+    let mut address = 0x0600;
+    while ((address - 0x0600) as usize) < code.len() {
+        let instruction = decode(read, address);
+        lines.push(instruction.to_string());
+        address += instruction.size();
+    }
+    lines
+}
+
+#[test]
+fn test_addressing_modes() {
+    let source = "
+        clc
+        asl a
+        lda #$01
+        lda $10
+        sta $10,x
+        ldx $10,y
+        sta $0200
+        lda $0200,x
+        lda $0200,y
+        jmp ($1234)
+        lda ($20,x)
+        sta ($20),y
+    ";
+    assert_eq!(
+        listing(source),
+        [
+            "CLC", "ASL A", "LDA #$01", "LDA $10", "STA $10,X", "LDX $10,Y", "STA $0200",
+            "LDA $0200,X", "LDA $0200,Y", "JMP ($1234)", "LDA ($20,X)", "STA ($20),Y",
+        ]
+    );
+}
+
+#[test]
+fn test_branch_and_jump_targets() {
+    let code = assemble("loop: dex\nbne loop\njsr loop\nbeq end\nend: rts", 0x0600).unwrap();
+    let read = |addr: u16| code[(addr - 0x0600) as usize];
+    let bne = decode(read, 0x0601);
+    assert_eq!(bne.to_string(), "BNE $0600");
+    assert_eq!(bne.target(), Some(0x0600));
+    assert_eq!(decode(read, 0x0603).target(), Some(0x0600));
+    assert_eq!(decode(read, 0x0606).to_string(), "BEQ $0608");
+    assert_eq!(decode(read, 0x0608).target(), None);
+}
+
+#[test]
+fn test_undocumented_opcodes_take_one_byte() {
+    let instruction = decode(|_| 0x02, 0x8000);
+    assert_eq!(instruction.to_string(), ".db $02");
+    assert_eq!(instruction.size(), 1);
+    assert!(instruction.op.is_none());
+}
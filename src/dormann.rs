@@ -0,0 +1,106 @@
+use std::fmt;
+
+use crate::cpu::CPU;
+use crate::loader::Program;
+
+#[cfg(test)]
+mod dormann_test;
+
+// Klaus Dormann's 6502 test suite, github.com/Klaus2m5/6502_65C02_functional_tests
+//
+// Both tests signal the end with a trap, a jump or branch to itself. The
+// functional test traps at a fixed address once everything passed and right
+// after the failing check otherwise, with the number of the running test in
+// `test_case`. The decimal test ends with a 65C02 STP ($db) at its DONE label
+// and leaves ERROR at 0 when it passed.
+pub const FUNCTIONAL_LOAD: u16 = 0x0000;
+pub const FUNCTIONAL_START: u16 = 0x0400;
+// for the prebuilt bin_files/6502_functional_test.bin
+pub const FUNCTIONAL_SUCCESS: u16 = 0x3469;
+const TEST_CASE: u16 = 0x0200;
+
+// the decimal test has no prebuilt binary, its source uses `org $200`
+pub const DECIMAL_START: u16 = 0x0200;
+const DECIMAL_ERROR: u16 = 0x000b;
+const STP: u8 = 0xdb;
+
+// a bit over the ~30M instructions the functional test takes
+pub const MAX_STEPS: usize = 100_000_000;
+
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    Passed,
+    // the functional test knows which test failed, the decimal one doesn't
+    Failed { pc: u16, test: Option<u8> },
+    TimedOut { pc: u16 },
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Outcome::Passed => write!(f, "passed"),
+            Outcome::Failed { pc, test: Some(test) } => {
+                write!(f, "test {:02x} failed, trapped at ${:04x}", test, pc)
+            }
+            Outcome::Failed { pc, test: None } => write!(f, "failed, stopped at ${:04x}", pc),
+            Outcome::TimedOut { pc } => write!(f, "gave up waiting for a trap, pc at ${:04x}", pc),
+        }
+    }
+}
+
+enum Stop {
+    Trap(u16),
+    Stp(u16),
+    Halted(u16),
+    TimedOut(u16),
+}
+
+fn load(image: &[u8], load: u16, start: u16) -> Result<CPU, String> {
+    let mut program = Program::default();
+    program.add_segment(load, image.to_vec());
+    let mut cpu = CPU::new();
+    // both tests check BRK, which has to go through the IRQ vector
+    cpu.halt_on_brk = false;
+    cpu.load_program(&program)?;
+    cpu.program_counter = start;
+    Ok(cpu)
+}
+
+fn run(cpu: &mut CPU, max_steps: usize) -> Stop {
+    for _ in 0..max_steps {
+        let pc = cpu.program_counter;
+        if cpu.peek(pc) == STP {
+            return Stop::Stp(pc);
+        }
+        // an unknown opcode stops the run just like a halting BRK
+        if !cpu.step().unwrap_or(false) {
+            return Stop::Halted(pc);
+        }
+        if cpu.program_counter == pc {
+            return Stop::Trap(pc);
+        }
+    }
+    Stop::TimedOut(cpu.program_counter)
+}
+
+pub fn run_functional(image: &[u8], success: u16, max_steps: usize) -> Result<Outcome, String> {
+    let mut cpu = load(image, FUNCTIONAL_LOAD, FUNCTIONAL_START)?;
+    Ok(match run(&mut cpu, max_steps) {
+        Stop::Trap(pc) if pc == success => Outcome::Passed,
+        Stop::Trap(pc) | Stop::Stp(pc) | Stop::Halted(pc) => Outcome::Failed {
+            pc,
+            test: Some(cpu.peek(TEST_CASE)),
+        },
+        Stop::TimedOut(pc) => Outcome::TimedOut { pc },
+    })
+}
+
+// `load_address` is where the image starts, a full 64K dump loads at 0
+pub fn run_decimal(image: &[u8], load_address: u16, max_steps: usize) -> Result<Outcome, String> {
+    let mut cpu = load(image, load_address, DECIMAL_START)?;
+    Ok(match run(&mut cpu, max_steps) {
+        Stop::Trap(_) | Stop::Stp(_) if cpu.peek(DECIMAL_ERROR) == 0 => Outcome::Passed,
+        Stop::Trap(pc) | Stop::Stp(pc) | Stop::Halted(pc) => Outcome::Failed { pc, test: None },
+        Stop::TimedOut(pc) => Outcome::TimedOut { pc },
+    })
+}
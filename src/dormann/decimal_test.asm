@@ -0,0 +1,189 @@
+; Bruce Clark's decimal mode test (6502.org/tutorials/decimal_mode.html,
+; appendix B), the same one 6502_decimal_test.a65 wraps, in Easy6502 syntax.
+; Checks A, N, V, Z and C as an NMOS 6502 sets them for all 256 x 256
+; operands and both carry values. ERROR is 0 at the end if everything matched.
+
+define N1     $00
+define N2     $01
+define HA     $02
+define HNVZC  $03
+define DA     $04
+define DNVZC  $05
+define AR     $06
+define NF     $07
+define VF     $08
+define ZF     $09
+define CF     $0a
+define ERROR  $0b
+define N1L    $0c
+define N1H    $0d
+define N2L    $0e
+define N2H    $0f       ; two bytes
+
+test:
+  ldy #1                ; loops through both values of the carry
+  sty ERROR             ; 1 until the test passes
+  lda #0
+  sta N1
+  sta N2
+loop1:
+  lda N2
+  and #$0f
+  sta N2L
+  lda N2
+  and #$f0
+  sta N2H
+  ora #$0f
+  sta N2H+1
+loop2:
+  lda N1
+  and #$0f
+  sta N1L
+  lda N1
+  and #$f0
+  sta N1H
+  jsr add
+  jsr a6502
+  jsr compare
+  bne done
+  jsr sub
+  jsr s6502
+  jsr compare
+  bne done
+  inc N1
+  bne loop2
+  inc N2
+  bne loop1
+  dey
+  bpl loop1
+  lda #0
+  sta ERROR
+done:
+  dcb $db               ; 65C02 STP, where the harness stops
+
+; actual decimal result and flags, binary result and flags, and the
+; predicted accumulator, carry and V for N1 + N2
+add:
+  sed
+  cpy #1
+  lda N1
+  adc N2
+  sta DA
+  php
+  pla
+  sta DNVZC
+  cld
+  cpy #1
+  lda N1
+  adc N2
+  sta HA
+  php
+  pla
+  sta HNVZC
+  cpy #1
+  lda N1L
+  adc N2L
+  cmp #$0a
+  ldx #0
+  bcc a1
+  inx
+  adc #5                ; add 6, the carry is set
+  and #$0f
+  sec
+a1:
+  ora N1H
+  adc N2H,x             ; N2 & $f0, or that + $10 when the low digit carried
+  php
+  bcs a2
+  cmp #$a0
+  bcc a3
+a2:
+  adc #$5f              ; add $60, the carry is set
+  sec
+a3:
+  sta AR
+  php
+  pla
+  sta CF
+  pla
+  sta VF                ; all of P, N comes from here too
+  rts
+
+; actual decimal result and flags, binary result and flags for N1 - N2
+sub:
+  sed
+  cpy #1
+  lda N1
+  sbc N2
+  sta DA
+  php
+  pla
+  sta DNVZC
+  cld
+  cpy #1
+  lda N1
+  sbc N2
+  sta HA
+  php
+  pla
+  sta HNVZC
+  rts
+
+; predicted accumulator for N1 - N2
+sub1:
+  cpy #1
+  lda N1L
+  sbc N2L
+  ldx #0
+  bcs s11
+  inx
+  sbc #5                ; subtract 6, the carry is clear
+  and #$0f
+  clc
+s11:
+  ora N1H
+  sbc N2H,x
+  bcs s12
+  sbc #$5f              ; subtract $60, the carry is clear
+s12:
+  sta AR
+  rts
+
+; Z set when the actual results match the predicted ones
+compare:
+  lda DA
+  cmp AR
+  bne c1
+  lda DNVZC
+  eor NF
+  and #$80
+  bne c1
+  lda DNVZC
+  eor VF
+  and #$40
+  bne c1
+  lda DNVZC
+  eor ZF
+  and #2
+  bne c1
+  lda DNVZC
+  eor CF
+  and #1
+c1:
+  rts
+
+a6502:
+  lda VF
+  sta NF
+  lda HNVZC
+  sta ZF
+  rts
+
+s6502:
+  jsr sub1
+  lda HNVZC
+  sta NF
+  sta VF
+  sta ZF
+  sta CF
+  rts
@@ -0,0 +1,61 @@
+use std::env;
+use std::fs;
+
+use super::*;
+use crate::asm::assemble;
+
+// the image the functional test expects: zero page and stack, code at $0400
+fn functional_image(source: &str) -> Vec<u8> {
+    let mut image = vec![0; FUNCTIONAL_START as usize];
+    image.extend(assemble(source, FUNCTIONAL_START).unwrap());
+    image
+}
+
+#[test]
+fn test_trap_at_success_passes() {
+    let image = functional_image("ldx #3\nloop: dex\nbne loop\nsuccess: jmp success");
+    assert_eq!(run_functional(&image, 0x0405, 1000).unwrap(), Outcome::Passed);
+}
+
+#[test]
+fn test_other_trap_reports_the_test_number() {
+    let image = functional_image("lda #$2a\nsta $0200\nfail: bne fail\njmp fail");
+    let outcome = run_functional(&image, 0x0409, 1000).unwrap();
+    assert_eq!(outcome, Outcome::Failed { pc: 0x0405, test: Some(0x2a) });
+    assert_eq!(outcome.to_string(), "test 2a failed, trapped at $0405");
+}
+
+#[test]
+fn test_no_trap_times_out() {
+    let image = functional_image("loop: inx\njmp loop");
+    assert!(matches!(run_functional(&image, 0x0400, 1000).unwrap(), Outcome::TimedOut { .. }));
+}
+
+#[test]
+fn test_decimal_mode() {
+    let image = assemble(include_str!("decimal_test.asm"), DECIMAL_START).unwrap();
+    assert_eq!(run_decimal(&image, DECIMAL_START, MAX_STEPS).unwrap(), Outcome::Passed);
+}
+
+// The real binaries aren't checked in, point these at them and run the
+// ignored tests to run the suite:
+//   NES_FUNCTIONAL_TEST=.../6502_functional_test.bin
+//   NES_DECIMAL_TEST=.../6502_decimal_test.bin (assembled at $0200)
+#[test]
+#[ignore = "needs NES_FUNCTIONAL_TEST"]
+fn test_klaus_functional_binary() {
+    let path = env::var("NES_FUNCTIONAL_TEST").expect("NES_FUNCTIONAL_TEST isn't set");
+    let image = fs::read(path).unwrap();
+    let outcome = run_functional(&image, FUNCTIONAL_SUCCESS, MAX_STEPS).unwrap();
+    assert_eq!(outcome, Outcome::Passed, "{}", outcome);
+}
+
+#[test]
+#[ignore = "needs NES_DECIMAL_TEST"]
+fn test_klaus_decimal_binary() {
+    let path = env::var("NES_DECIMAL_TEST").expect("NES_DECIMAL_TEST isn't set");
+    let image = fs::read(path).unwrap();
+    let load = if image.len() == 0x10000 { 0 } else { DECIMAL_START };
+    let outcome = run_decimal(&image, load, MAX_STEPS).unwrap();
+    assert_eq!(outcome, Outcome::Passed, "{}", outcome);
+}
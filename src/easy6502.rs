@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use crate::asm;
+use crate::cpu::{CpuError, CPU};
+use crate::joypad::Buttons;
+use crate::loader::Program;
+
+#[cfg(test)]
+mod easy6502_test;
+
+// The conventions of the Easy6502 simulator (skilldrick.github.io/easy6502):
+//
+//   $00fe           a new random byte before every instruction
+//   $00ff           ASCII code of the last key pressed
+//   $0200 - $05ff   32x32 screen, one byte per pixel, low nibble picks the color
+//   $0600           where programs are loaded and start
+pub const RANDOM_PORT: u16 = 0x00fe;
+pub const KEY_PORT: u16 = 0x00ff;
+pub const SCREEN_START: u16 = 0x0200;
+pub const LOAD_ADDRESS: u16 = 0x0600;
+
+// the key a joypad button types when pressed: WASD for the d-pad, as the
+// tutorial games read it
+const BUTTON_KEYS: [(Buttons, u8); 8] = [
+    (Buttons::UP, b'w'),
+    (Buttons::LEFT, b'a'),
+    (Buttons::DOWN, b's'),
+    (Buttons::RIGHT, b'd'),
+    (Buttons::A, b'x'),
+    (Buttons::B, b'z'),
+    (Buttons::START, 0x0d),
+    (Buttons::SELECT, b' '),
+];
+
+pub const WIDTH: usize = 32;
+pub const HEIGHT: usize = 32;
+pub const FRAME_SIZE: usize = WIDTH * 3 * HEIGHT;
+
+// instructions per frame, roughly what the old loop got through in 1/60s
+pub const STEPS_PER_FRAME: usize = 240;
+
+// the palette of the original simulator
+pub const PALETTE: [(u8, u8, u8); 16] = [
+    (0x00, 0x00, 0x00),
+    (0xff, 0xff, 0xff),
+    (0x88, 0x00, 0x00),
+    (0xaa, 0xff, 0xee),
+    (0xcc, 0x44, 0xcc),
+    (0x00, 0xcc, 0x55),
+    (0x00, 0x00, 0xaa),
+    (0xee, 0xee, 0x77),
+    (0xdd, 0x88, 0x55),
+    (0x66, 0x44, 0x00),
+    (0xff, 0x77, 0x77),
+    (0x33, 0x33, 0x33),
+    (0x77, 0x77, 0x77),
+    (0xaa, 0xff, 0x66),
+    (0x00, 0x88, 0xff),
+    (0xbb, 0xbb, 0xbb),
+];
+
+pub fn color(byte: u8) -> (u8, u8, u8) {
+    PALETTE[(byte & 0x0f) as usize]
+}
+
+// the snake game from the Easy6502 tutorial, run when no program is given
+pub const SNAKE: &str = include_str!("easy6502/snake.asm");
+
+// `.asm` and `.s` files are assembled, everything else is loaded as is
+pub fn is_source(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|ext| ext.to_str()),
+        Some("asm") | Some("s")
+    )
+}
+
+#[derive(Clone)]
+pub struct Easy6502Machine {
+    pub cpu: CPU,
+    // kept to load again on a power cycle
+    program: Program,
+    program_hash: u64,
+}
+
+impl Easy6502Machine {
+    pub fn new(program: Vec<u8>) -> Self {
+        Easy6502Machine::with_program(&Program::at(LOAD_ADDRESS, program)).expect("program doesn't fit in memory")
+    }
+
+    pub fn from_source(source: &str) -> Result<Self, String> {
+        Ok(Easy6502Machine::new(asm::assemble(source, LOAD_ADDRESS)?))
+    }
+
+    // for programs that don't follow the $0600 convention
+    pub fn with_program(program: &Program) -> Result<Self, String> {
+        let mut cpu = CPU::new();
+        cpu.load_program(program)?;
+        cpu.reset();
+        Ok(Easy6502Machine {
+            cpu,
+            program: program.clone(),
+            program_hash: program.hash(),
+        })
+    }
+
+    // loads the program over whatever memory holds and starts it again
+    pub fn reload(&mut self) {
+        self.cpu.load_program(&self.program).expect("the program fitted before");
+        self.cpu.reset();
+    }
+
+    // what save states are checked against, as the ROM hash is on the NES
+    pub fn program_hash(&self) -> u64 {
+        self.program_hash
+    }
+
+    pub fn press_key(&mut self, key: u8) {
+        self.cpu.bus.poke(KEY_PORT, key);
+    }
+
+    // Buttons pressed since the last frame type their keys. What was held is
+    // kept in the unused first joypad, so save states have it.
+    pub fn latch(&mut self, buttons: Buttons) {
+        let held = self.cpu.bus.joypads[0].buttons;
+        for (button, key) in BUTTON_KEYS {
+            if buttons.contains(button) && !held.contains(button) {
+                self.press_key(key);
+            }
+        }
+        self.cpu.bus.joypads[0].buttons = buttons;
+    }
+
+    // `random` is the byte at $fe for this instruction
+    pub fn step(&mut self, random: u8) -> Result<bool, CpuError> {
+        // the ports are devices, not CPU writes, so bus hooks don't see them
+        self.cpu.bus.poke(RANDOM_PORT, random);
+        self.cpu.step()
+    }
+
+    // fills `frame` with RGB24 pixels, returns whether anything changed
+    pub fn render(&self, frame: &mut [u8; FRAME_SIZE]) -> bool {
+        let mut update = false;
+        for (i, pixel) in frame.chunks_exact_mut(3).enumerate() {
+            let (r, g, b) = color(self.cpu.peek(SCREEN_START + i as u16));
+            if pixel != [r, g, b] {
+                pixel.copy_from_slice(&[r, g, b]);
+                update = true;
+            }
+        }
+        update
+    }
+}
@@ -0,0 +1,38 @@
+use super::*;
+
+#[test]
+fn test_palette_uses_the_low_nibble() {
+    assert_eq!(color(0x00), (0x00, 0x00, 0x00));
+    assert_eq!(color(0x01), (0xff, 0xff, 0xff));
+    assert_eq!(color(0x0e), (0x00, 0x88, 0xff));
+    assert_eq!(color(0x5e), color(0x0e));
+}
+
+#[test]
+fn test_program_draws_to_the_screen() {
+    let mut machine = Easy6502Machine::from_source("lda #$02\nsta $0200\nsta $05ff\nbrk").unwrap();
+    while machine.step(0).unwrap() {}
+
+    let mut frame = [0u8; FRAME_SIZE];
+    assert!(machine.render(&mut frame));
+    assert_eq!(&frame[..3], &[0x88, 0x00, 0x00]);
+    assert_eq!(&frame[FRAME_SIZE - 3..], &[0x88, 0x00, 0x00]);
+    assert_eq!(&frame[3..6], &[0x00, 0x00, 0x00]);
+    assert!(!machine.render(&mut frame));
+}
+
+#[test]
+fn test_each_instruction_gets_its_random_byte() {
+    let mut machine = Easy6502Machine::from_source("lda $fe\nsta $10\nlda $fe\nsta $11\nbrk").unwrap();
+    let mut random = 0x40..;
+    while machine.step(random.next().unwrap()).unwrap() {}
+    assert_eq!((machine.cpu.mem_read(0x10), machine.cpu.mem_read(0x11)), (0x40, 0x42));
+}
+
+#[test]
+fn test_keys_land_in_the_key_port() {
+    let mut machine = Easy6502Machine::new(vec![0xa5, 0xff, 0x00]);
+    machine.press_key(b'W');
+    while machine.step(0).unwrap() {}
+    assert_eq!(machine.cpu.register_a, b'W');
+}
@@ -0,0 +1,501 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::cheats::Cheats;
+use crate::debugger::{Breakpoint, Debugger, Kind, Stop};
+use crate::hooks::Access;
+use crate::machine::Machine;
+use crate::ramsearch::{self, Compare, Operand, RamSearch, View};
+
+#[cfg(test)]
+mod gdb_test;
+
+// instructions between checks for an interrupt from the client while running
+const SLICE: u64 = 10_000;
+
+// The registers as sent by `g` and `G`, in order: A, X, Y, P and SP as one
+// byte each, then PC as two bytes, little endian.
+const REGISTERS: usize = 6;
+
+// the largest packet, as told to the client in `qSupported`
+const PACKET_SIZE: usize = 0x4000;
+
+// the registers as `g` sends them, for clients that ask with `qXfer`
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.nes.6502">
+    <reg name="a" bitsize="8" type="uint8"/>
+    <reg name="x" bitsize="8" type="uint8"/>
+    <reg name="y" bitsize="8" type="uint8"/>
+    <reg name="p" bitsize="8" type="uint8"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// candidates `monitor search list` shows
+const LISTED: usize = 32;
+
+// also in cli::USAGE
+const MONITOR_HELP: &str = "\
+search new [16] [signed]    start a RAM search, over bytes or words
+search eq|ne|gt|lt [value]  keep the candidates whose value compares as asked
+                            with the last search, or with the value
+search list                 show the candidates
+pin <addr> [value]          hold a hex address at the value, or at what it holds
+cheats                      show the pinned addresses
+values are decimal, or hex after $ or 0x
+";
+
+// A stream the client talks to, which can hand over a byte that was sent
+// without waiting for one, to look for ^C. A client that hung up is an
+// `UnexpectedEof` error, not a byte still to come.
+pub trait Connection: Read + Write {
+    fn poll(&mut self) -> io::Result<Option<u8>>;
+}
+
+impl Connection for TcpStream {
+    fn poll(&mut self) -> io::Result<Option<u8>> {
+        self.set_nonblocking(true)?;
+        let mut byte = [0u8];
+        let read = self.read(&mut byte);
+        self.set_nonblocking(false)?;
+        match read {
+            Ok(0) => Err(ErrorKind::UnexpectedEof.into()),
+            Ok(_) => Ok(Some(byte[0])),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// waits for one client on localhost and serves it until it detaches
+pub fn serve(machine: &mut Machine, port: u16) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("can't listen on port {}: {}", port, e))?;
+    eprintln!("waiting for gdb on 127.0.0.1:{}", port);
+    let (stream, peer) = listener.accept().map_err(|e| format!("can't accept gdb: {}", e))?;
+    eprintln!("gdb connected from {}", peer);
+    stream.set_nodelay(true).map_err(|e| e.to_string())?;
+    Session::new(stream, machine).run().map_err(|e| format!("gdb connection: {}", e))
+}
+
+pub struct Session<'a, C: Connection> {
+    conn: C,
+    machine: &'a mut Machine,
+    debugger: Debugger,
+    // the debugger ids of the `Z` breakpoints, by type and address
+    breakpoints: HashMap<(u8, u16), usize>,
+    ack: bool,
+    // bytes polled while running that weren't ^C, read before the stream
+    received: VecDeque<u8>,
+    search: Option<RamSearch>,
+    // attached with the first pin
+    cheats: Option<Cheats>,
+}
+
+impl<'a, C: Connection> Session<'a, C> {
+    pub fn new(conn: C, machine: &'a mut Machine) -> Self {
+        Session {
+            conn,
+            machine,
+            debugger: Debugger::new(),
+            breakpoints: HashMap::new(),
+            ack: true,
+            received: VecDeque::new(),
+            search: None,
+            cheats: None,
+        }
+    }
+
+    // serves packets until the client detaches, kills or hangs up
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(packet) = self.receive()? {
+            match self.handle(&packet)? {
+                Some(reply) => self.send(&reply)?,
+                None => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    // None once the client is gone
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.received.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0u8];
+        Ok((self.conn.read(&mut byte)? == 1).then_some(byte[0]))
+    }
+
+    // `$<data>#<checksum>`, acked with `+` or `-`; None once the client is gone
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let Some(byte) = self.read_byte()? else {
+                return Ok(None);
+            };
+            // acks and stray ^C while stopped
+            if byte != b'$' {
+                continue;
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    None => return Ok(None),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut checksum = [0u8; 2];
+            for digit in &mut checksum {
+                match self.read_byte()? {
+                    Some(byte) => *digit = byte,
+                    None => return Ok(None),
+                }
+            }
+            let expected = std::str::from_utf8(&checksum).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let valid = expected == Some(data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+            if self.ack {
+                self.conn.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.conn, "${}#{:02x}", data, checksum)?;
+        self.conn.flush()
+    }
+
+    // the reply to a packet, None to end the session
+    fn handle(&mut self, packet: &str) -> io::Result<Option<String>> {
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => self.read_registers(),
+            "G" => ok_or_error(self.write_registers(args)),
+            "p" => match parse_hex(args) {
+                Ok(index) if (index as usize) < REGISTERS => self.read_register(index as usize),
+                _ => "E01".to_string(),
+            },
+            "P" => ok_or_error(self.write_register(args)),
+            "m" => self.read_memory(args).unwrap_or_else(|_| "E01".to_string()),
+            "M" => ok_or_error(self.write_memory(args)),
+            // both take an optional address to resume at
+            "s" | "c" if !args.is_empty() && parse_hex(args).is_err() => "E01".to_string(),
+            "s" | "c" => {
+                if let Ok(addr) = parse_hex(args) {
+                    self.machine.cpu_mut().program_counter = addr as u16;
+                }
+                if command == "s" {
+                    let stop = self.debugger.step(self.machine);
+                    self.stop_reply(&stop)
+                } else {
+                    self.resume()?
+                }
+            }
+            "Z" => ok_or_error(self.insert_breakpoint(args)),
+            "z" => ok_or_error(self.remove_breakpoint(args)),
+            "H" => "OK".to_string(),
+            "k" => return Ok(None),
+            "D" => {
+                self.send("OK")?;
+                return Ok(None);
+            }
+            "q" if args.starts_with("Supported") => {
+                format!("PacketSize={:x};QStartNoAckMode+;qXfer:features:read+", PACKET_SIZE)
+            }
+            "q" if args.starts_with("Xfer:features:read:") => {
+                read_features(&args["Xfer:features:read:".len()..]).unwrap_or_else(|_| "E01".to_string())
+            }
+            "q" if args == "Attached" => "1".to_string(),
+            "q" if args == "C" => "QC1".to_string(),
+            "q" if args == "fThreadInfo" => "m1".to_string(),
+            "q" if args == "sThreadInfo" => "l".to_string(),
+            // `monitor` commands; the reply is their output
+            "q" if args.starts_with("Rcmd,") => match unhex(&args[5..]) {
+                Ok(command) => {
+                    let output = self.monitor(&String::from_utf8_lossy(&command)).unwrap_or_else(|e| e + "\n");
+                    if output.is_empty() {
+                        "OK".to_string()
+                    } else {
+                        hex(output.as_bytes())
+                    }
+                }
+                Err(_) => "E01".to_string(),
+            },
+            // this packet was acked already, the client's ack of the reply is skipped
+            "Q" if args == "StartNoAckMode" => {
+                self.ack = false;
+                "OK".to_string()
+            }
+            // an empty reply tells the client the packet isn't supported
+            _ => String::new(),
+        };
+        Ok(Some(reply))
+    }
+
+    // runs in slices so a ^C from the client gets noticed
+    fn resume(&mut self) -> io::Result<String> {
+        loop {
+            let stop = self.debugger.run(self.machine, SLICE);
+            if stop != Stop::StepLimit {
+                return Ok(self.stop_reply(&stop));
+            }
+            // anything else is the start of the client's next packet
+            match self.conn.poll()? {
+                Some(0x03) => return Ok("S02".to_string()),
+                Some(byte) => self.received.push_back(byte),
+                None => {}
+            }
+        }
+    }
+
+    fn monitor(&mut self, command: &str) -> Result<String, String> {
+        let words: Vec<&str> = command.split_whitespace().collect();
+        let cpu = self.machine.cpu();
+        let mut out = String::new();
+        match words.as_slice() {
+            ["search", "new", options @ ..] => {
+                let mut view = View::default();
+                for option in options {
+                    match *option {
+                        "8" => view.word = false,
+                        "16" => view.word = true,
+                        "signed" => view.signed = true,
+                        _ => return Err(format!("unknown search option: {}", option)),
+                    }
+                }
+                let search = self.search.insert(RamSearch::new(cpu, view));
+                let _ = writeln!(out, "{} candidates", search.candidates().len());
+            }
+            ["search", "list"] => {
+                let search = self.search.as_ref().ok_or("no search, start one with `search new`")?;
+                let results = search.results(cpu);
+                for candidate in results.iter().take(LISTED) {
+                    let _ = writeln!(out, "${:04x}  {}  (was {})", candidate.addr, candidate.current, candidate.previous);
+                }
+                if results.len() > LISTED {
+                    let _ = writeln!(out, "and {} more", results.len() - LISTED);
+                }
+            }
+            ["search", compare, value @ ..] if value.len() <= 1 => {
+                let compare = Compare::parse(compare)?;
+                let operand = match value.first() {
+                    Some(value) => Operand::Value(ramsearch::parse_value(value)?),
+                    None => Operand::Previous,
+                };
+                let search = self.search.as_mut().ok_or("no search, start one with `search new`")?;
+                let _ = writeln!(out, "{} candidates", search.filter(cpu, compare, operand)?);
+            }
+            ["pin", addr, value @ ..] if value.len() <= 1 => {
+                let addr = parse_hex(addr.trim_start_matches('$').trim_start_matches("0x"))? as u16;
+                let value = value.first().map(|value| ramsearch::parse_value(value)).transpose()?;
+                let view = self.search.as_ref().map_or(View::default(), |search| search.view);
+                let pinned = view.pin(cpu, addr, value)?;
+                let cheats = self.cheats.get_or_insert_with(|| Cheats::attach(self.machine.cpu_mut()));
+                for cheat in pinned {
+                    let _ = writeln!(out, "{}", cheat.code);
+                    cheats.add(cheat);
+                }
+            }
+            ["cheats"] => {
+                for (i, cheat) in self.cheats.iter().flat_map(|cheats| cheats.list().clone()).enumerate() {
+                    let state = if cheat.enabled { "on" } else { "off" };
+                    let _ = writeln!(out, "{}  {} {}", i, state, cheat.code);
+                }
+            }
+            ["help"] => out += MONITOR_HELP,
+            _ => return Err(format!("unknown monitor command: {}\n{}", command.trim(), MONITOR_HELP.trim_end())),
+        }
+        Ok(out)
+    }
+
+    // SIGTRAP for breakpoints, SIGILL for opcodes the CPU doesn't know
+    fn stop_reply(&self, stop: &Stop) -> String {
+        match stop {
+            Stop::Breakpoint(id, Some(access)) => {
+                let watched = match self.debugger.get(*id).map(|breakpoint| &breakpoint.kind) {
+                    Some(Kind::Watch(watched, _)) => *watched,
+                    _ => access.access,
+                };
+                let kind = if watched.contains(Access::READ | Access::WRITE) {
+                    "awatch"
+                } else if watched.contains(Access::WRITE) {
+                    "watch"
+                } else {
+                    "rwatch"
+                };
+                format!("T05{}:{:04x};", kind, access.addr)
+            }
+            Stop::Breakpoint(_, None) | Stop::StepLimit => "S05".to_string(),
+            Stop::Halted => "W00".to_string(),
+            Stop::Error(_) => "S04".to_string(),
+        }
+    }
+
+    fn registers(&self) -> [u8; REGISTERS + 1] {
+        let cpu = self.machine.cpu();
+        let [pc_lo, pc_hi] = cpu.program_counter.to_le_bytes();
+        [cpu.register_a, cpu.register_x, cpu.register_y, cpu.status, cpu.stack_counter, pc_lo, pc_hi]
+    }
+
+    fn read_registers(&self) -> String {
+        hex(&self.registers())
+    }
+
+    fn read_register(&self, index: usize) -> String {
+        let registers = self.registers();
+        let size = if index == REGISTERS - 1 { 2 } else { 1 };
+        hex(&registers[index..index + size])
+    }
+
+    fn write_registers(&mut self, args: &str) -> Result<(), String> {
+        let bytes = unhex(args)?;
+        if bytes.len() != REGISTERS + 1 {
+            return Err("wrong register block size".to_string());
+        }
+        let cpu = self.machine.cpu_mut();
+        cpu.register_a = bytes[0];
+        cpu.register_x = bytes[1];
+        cpu.register_y = bytes[2];
+        cpu.status = bytes[3];
+        cpu.stack_counter = bytes[4];
+        cpu.program_counter = u16::from_le_bytes([bytes[5], bytes[6]]);
+        Ok(())
+    }
+
+    // `P<n>=<value>`, the value in target byte order
+    fn write_register(&mut self, args: &str) -> Result<(), String> {
+        let (index, value) = args.split_once('=').ok_or("missing `=`")?;
+        let index = parse_hex(index)? as usize;
+        let bytes = unhex(value)?;
+        let cpu = self.machine.cpu_mut();
+        let byte = *bytes.first().ok_or("missing value")?;
+        match index {
+            0 => cpu.register_a = byte,
+            1 => cpu.register_x = byte,
+            2 => cpu.register_y = byte,
+            3 => cpu.status = byte,
+            4 => cpu.stack_counter = byte,
+            5 => cpu.program_counter = u16::from_le_bytes([byte, bytes.get(1).copied().unwrap_or(0)]),
+            _ => return Err(format!("no register {}", index)),
+        }
+        Ok(())
+    }
+
+    // `m<addr>,<length>`, read without side effects
+    fn read_memory(&self, args: &str) -> Result<String, String> {
+        let (addr, length) = address_and_length(args)?;
+        let cpu = self.machine.cpu();
+        let bytes: Vec<u8> = (0..length).map(|i| cpu.peek(addr + i as u16)).collect();
+        Ok(hex(&bytes))
+    }
+
+    // `M<addr>,<length>:<bytes>`
+    fn write_memory(&mut self, args: &str) -> Result<(), String> {
+        let (range, data) = args.split_once(':').ok_or("missing `:`")?;
+        let (addr, length) = address_and_length(range)?;
+        let bytes = unhex(data)?;
+        if bytes.len() != length {
+            return Err("length doesn't match the data".to_string());
+        }
+        let cpu = self.machine.cpu_mut();
+        for (i, byte) in bytes.into_iter().enumerate() {
+            cpu.bus.poke(addr + i as u16, byte);
+        }
+        Ok(())
+    }
+
+    // `Z<type>,<addr>,<kind>`: 0 and 1 break on execution, 2 write, 3 read
+    // and 4 any access, `kind` being the watched length
+    fn insert_breakpoint(&mut self, args: &str) -> Result<(), String> {
+        let (kind, addr, length) = breakpoint_args(args)?;
+        let last = addr.checked_add(length.max(1) - 1).ok_or("the watched range wraps past $ffff")?;
+        let breakpoint = match kind {
+            0 | 1 => Breakpoint::pc(addr),
+            2 => Breakpoint::watch(Access::WRITE, addr..=last),
+            3 => Breakpoint::watch(Access::READ, addr..=last),
+            4 => Breakpoint::watch(Access::READ | Access::WRITE, addr..=last),
+            _ => return Err(format!("unknown breakpoint type {}", kind)),
+        };
+        let id = self.debugger.add(breakpoint);
+        if let Some(old) = self.breakpoints.insert((kind, addr), id) {
+            self.debugger.remove(old);
+        }
+        Ok(())
+    }
+
+    fn remove_breakpoint(&mut self, args: &str) -> Result<(), String> {
+        let (kind, addr, _) = breakpoint_args(args)?;
+        let id = self.breakpoints.remove(&(kind, addr)).ok_or("no such breakpoint")?;
+        self.debugger.remove(id);
+        Ok(())
+    }
+}
+
+fn ok_or_error(result: Result<(), String>) -> String {
+    match result {
+        Ok(()) => "OK".to_string(),
+        Err(_) => "E01".to_string(),
+    }
+}
+
+fn breakpoint_args(args: &str) -> Result<(u8, u16, u16), String> {
+    let mut fields = args.split(',');
+    let mut next = || fields.next().ok_or_else(|| "missing breakpoint field".to_string()).and_then(parse_hex);
+    Ok((next()? as u8, next()? as u16, next()? as u16))
+}
+
+// the length is held to what fits in a packet as hex, and the range to
+// the address space
+fn address_and_length(args: &str) -> Result<(u16, usize), String> {
+    let (addr, length) = args.split_once(',').ok_or("missing `,`")?;
+    let (addr, length) = (parse_hex(addr)?, parse_hex(length)?);
+    if length > (PACKET_SIZE / 2) as u64 {
+        return Err(format!("length {:#x} is over the packet size", length));
+    }
+    if addr.saturating_add(length) > 0x10000 {
+        return Err("the range runs past $ffff".to_string());
+    }
+    Ok((addr as u16, length as usize))
+}
+
+// `<annex>:<offset>,<length>` of `qXfer:features:read`; the part asked for
+// goes after `m` while more follows, after `l` once it's the last
+fn read_features(args: &str) -> Result<String, String> {
+    let (annex, range) = args.split_once(':').ok_or("missing `:`")?;
+    if annex != "target.xml" {
+        return Err(format!("no feature file {}", annex));
+    }
+    let (offset, length) = range.split_once(',').ok_or("missing `,`")?;
+    let offset = (parse_hex(offset)? as usize).min(TARGET_XML.len());
+    let length = (parse_hex(length)? as usize).min(PACKET_SIZE - 1);
+    let end = (offset + length).min(TARGET_XML.len());
+    let more = if end < TARGET_XML.len() { "m" } else { "l" };
+    Ok(format!("{}{}", more, &TARGET_XML[offset..end]))
+}
+
+fn parse_hex(text: &str) -> Result<u64, String> {
+    u64::from_str_radix(text, 16).map_err(|_| format!("invalid hex: {}", text))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(format!("invalid hex: {}", text));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| format!("invalid hex: {}", text)))
+        .collect()
+}
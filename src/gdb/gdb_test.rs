@@ -0,0 +1,226 @@
+use std::io::Cursor;
+
+use super::*;
+use crate::asm::assemble;
+use crate::loader::Program;
+
+struct Script {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+impl Read for Script {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl Write for Script {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// everything sent is already there to poll, after it the client is gone
+impl Connection for &mut Script {
+    fn poll(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0u8];
+        match self.input.read(&mut byte)? {
+            0 => Err(ErrorKind::UnexpectedEof.into()),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+}
+
+fn packet(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+// sends the packets, returns the replies and the acks
+fn talk(machine: &mut Machine, packets: &[&str]) -> (Vec<String>, String) {
+    let input: String = packets.iter().map(|data| packet(data) + "+").collect();
+    let mut script = Script {
+        input: Cursor::new(input.into_bytes()),
+        output: Vec::new(),
+    };
+    Session::new(&mut script, machine).run().unwrap();
+    let output = String::from_utf8(script.output).unwrap();
+    let mut output = output.as_str();
+    let (mut replies, mut acks) = (Vec::new(), String::new());
+    while let Some(start) = output.find('$') {
+        acks += &output[..start];
+        let end = output.find('#').unwrap();
+        replies.push(output[start + 1..end].to_string());
+        output = &output[end + 3..];
+    }
+    (replies, acks + output)
+}
+
+fn machine() -> Machine {
+    let code = assemble("ldx #$05\nloop: stx $10\ndex\nbne loop\nbrk", 0x0600).unwrap();
+    Machine::easy6502(&Program::at(0x0600, code)).unwrap()
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut machine = machine();
+    let (replies, acks) = talk(
+        &mut machine,
+        &[
+            "?",
+            "g",
+            "m0600,3",
+            "M0010,2:abcd",
+            "m0010,2",
+            "P0=7f",
+            "p0",
+            "p5",
+            "Gaabbccddee0007",
+            "qSupported:xmlRegisters=i386",
+            "vMustReplyEmpty",
+            "m0,2001",
+            "mffff,1",
+            "mffff,2",
+            "Mffff,2:0102",
+        ],
+    );
+    assert_eq!(
+        replies,
+        [
+            "S05", "00000000ff0006", "a20586", "OK", "abcd", "OK", "7f", "0006", "OK",
+            "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+", "", "E01", "00", "E01", "E01",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>()
+    );
+    assert_eq!(acks, "+".repeat(15));
+    let cpu = machine.cpu();
+    assert_eq!((cpu.register_a, cpu.register_x, cpu.register_y), (0xaa, 0xbb, 0xcc));
+    assert_eq!((cpu.status, cpu.stack_counter, cpu.program_counter), (0xdd, 0xee, 0x0700));
+}
+
+#[test]
+fn test_breakpoints_step_and_continue() {
+    let mut machine = machine();
+    let (replies, _) = talk(
+        &mut machine,
+        &["Z0,604,1", "c", "p1", "c", "p1", "z0,604,1", "z0,604,1", "s", "p5", "Z2,10,1", "c", "p1", "z2,10,1", "Z2,ffff,2", "c"],
+    );
+    assert_eq!(
+        replies,
+        ["OK", "S05", "05", "S05", "04", "OK", "E01", "S05", "0506", "OK", "T05watch:0010;", "03", "OK", "E01", "W00"]
+    );
+}
+
+#[test]
+fn test_access_watchpoints_stop_as_awatch() {
+    let mut machine = machine();
+    let (replies, _) = talk(&mut machine, &["Z4,10,1", "c", "z4,10,1", "Z3,10,1", "c"]);
+    assert_eq!(replies, ["OK", "T05awatch:0010;", "OK", "OK", "W00"]);
+}
+
+#[test]
+fn test_target_description() {
+    let (replies, _) = talk(
+        &mut machine(),
+        &[
+            "qXfer:features:read:target.xml:0,15",
+            &format!("qXfer:features:read:target.xml:{:x},100", TARGET_XML.len() - 10),
+            "qXfer:features:read:other.xml:0,100",
+        ],
+    );
+    assert_eq!(replies, ["m<?xml version=\"1.0\"?>", "l</target>\n", "E01"]);
+}
+
+#[test]
+fn test_bad_checksums_are_nacked_and_no_ack_mode() {
+    let mut machine = machine();
+    let mut script = Script {
+        input: Cursor::new(format!("$g#00{}{}{}", packet("QStartNoAckMode"), packet("?"), packet("D")).into_bytes()),
+        output: Vec::new(),
+    };
+    Session::new(&mut script, &mut machine).run().unwrap();
+    let output = String::from_utf8(script.output).unwrap();
+    assert_eq!(output, format!("-+{}{}{}", packet("OK"), packet("S05"), packet("OK")));
+}
+
+#[test]
+fn test_packets_sent_while_running_are_kept() {
+    let code = assemble("loop: jmp loop", 0x0600).unwrap();
+    let mut machine = Machine::easy6502(&Program::at(0x0600, code)).unwrap();
+    // the client sends a packet before its ^C gets through
+    let input = format!("{}{}\x03++", packet("c"), packet("m0600,1"));
+    let mut script = Script {
+        input: Cursor::new(input.into_bytes()),
+        output: Vec::new(),
+    };
+    Session::new(&mut script, &mut machine).run().unwrap();
+    let output = String::from_utf8(script.output).unwrap();
+    assert_eq!(output, format!("+{}+{}", packet("S02"), packet("4c")));
+}
+
+#[test]
+fn test_hanging_up_while_running_ends_the_session() {
+    let code = assemble("loop: jmp loop", 0x0600).unwrap();
+    let mut machine = Machine::easy6502(&Program::at(0x0600, code)).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (stream, _) = listener.accept().unwrap();
+    client.write_all(packet("c").as_bytes()).unwrap();
+    drop(client);
+    let error = Session::new(stream, &mut machine).run().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_monitor_ram_search() {
+    let monitor = |command: &str| format!("qRcmd,{}", hex(command.as_bytes()));
+    let output = |text: &str| hex(text.as_bytes());
+    let mut machine = machine();
+    machine.reseed(1);
+    let (replies, _) = talk(
+        &mut machine,
+        &[
+            "Z0,604,1",
+            "c",
+            &monitor("search new"),
+            "c",
+            &monitor("search lt"),
+            &monitor("search eq $04"),
+            &monitor("search list"),
+            &monitor("pin 10"),
+            &monitor("cheats"),
+            &monitor("search up"),
+        ],
+    );
+    assert_eq!(
+        replies,
+        [
+            "OK".to_string(),
+            "S05".to_string(),
+            output("2048 candidates\n"),
+            "S05".to_string(),
+            // $00fe is the random number port
+            output("2 candidates\n"),
+            output("1 candidates\n"),
+            output("$0010  4  (was 4)\n"),
+            output("0010:04\n"),
+            output("0  on 0010:04\n"),
+            output("unknown comparison: up\n"),
+        ]
+    );
+    assert_eq!(machine.cpu().mem_read(0x10), 0x04);
+}
+
+#[test]
+fn test_monitor_help_is_in_the_usage() {
+    for line in MONITOR_HELP.lines() {
+        assert!(crate::cli::USAGE.contains(&format!("\n    {}", line)), "{}", line);
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+
+use crate::cli::{parse_number, Args, MachineKind};
+use crate::easy6502;
+use crate::hash::fnv1a;
+use crate::joypad::Buttons;
+use crate::machine::Machine;
+use crate::session::Session;
+
+#[cfg(test)]
+mod headless_test;
+
+// frames a run without --frames gets before it's taken for a hang, ten
+// minutes of NTSC video
+pub const FRAME_LIMIT: usize = 36_000;
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub frames: Option<usize>,
+    pub until_pc: Option<u16>,
+    pub input: Option<String>,
+    pub dump_frame: Option<String>,
+    pub dump_ram: Option<String>,
+    pub print_hash: bool,
+    pub expect_hash: Option<u64>,
+}
+
+// each line is `<frame> <key>`, the key being a single character or a `$`/`0x` byte
+fn parse_input_script(text: &str) -> Result<Vec<(usize, u8)>, String> {
+    let mut events = Vec::new();
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split_whitespace();
+        let (frame, key) = match (fields.next(), fields.next()) {
+            (Some(frame), Some(key)) => (frame, key),
+            _ => return Err(format!("input line {}: expected `<frame> <key>`", lineno + 1)),
+        };
+        let frame = parse_number(frame)? as usize;
+        let key = if key.len() == 1 && key.is_ascii() {
+            key.as_bytes()[0]
+        } else if key.chars().count() == 1 {
+            return Err(format!("input line {}: `{}` isn't an ASCII key", lineno + 1, key));
+        } else {
+            match parse_number(key)? {
+                byte @ 0..=0xff => byte as u8,
+                _ => return Err(format!("input line {}: key {} is over $ff", lineno + 1, key)),
+            }
+        };
+        events.push((frame, key));
+    }
+    events.sort_by_key(|(frame, _)| *frame);
+    Ok(events)
+}
+
+// returns whether the run matched the expectations
+pub fn run(machine: &mut Machine, opts: &Options, session: &mut Session) -> Result<bool, String> {
+    let wants_frame = opts.dump_frame.is_some() || opts.print_hash || opts.expect_hash.is_some();
+    if machine.kind() == MachineKind::Nes && wants_frame {
+        return Err("the NES machine has no PPU yet, there is no frame to check".to_string());
+    }
+    let mut input = match &opts.input {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path, e))?;
+            parse_input_script(&text)?
+        }
+        None => Vec::new(),
+    }
+    .into_iter()
+    .peekable();
+
+    let frames = opts.frames.unwrap_or(FRAME_LIMIT);
+    let mut frame = 0;
+    'frames: while frame < frames {
+        session.latch(machine, [Buttons::empty(); 2]);
+        while let Some((_, key)) = input.next_if(|(at, _)| *at <= frame) {
+            machine.press_key(key);
+        }
+        machine.begin_frame();
+        while !machine.frame_done() {
+            if opts.until_pc == Some(machine.cpu().program_counter) {
+                break 'frames;
+            }
+            if !session.step(machine)? {
+                break 'frames;
+            }
+        }
+        frame += 1;
+    }
+    if opts.frames.is_none() && frame == FRAME_LIMIT {
+        return Err(format!("still running after {} frames, stop it with --frames or --until-pc", FRAME_LIMIT));
+    }
+
+    let mut frame = [0u8; easy6502::FRAME_SIZE];
+    machine.render(&mut frame);
+    let hash = fnv1a(&frame);
+
+    if let Some(path) = &opts.dump_frame {
+        let mut ppm = format!("P6\n{} {}\n255\n", easy6502::WIDTH, easy6502::HEIGHT).into_bytes();
+        ppm.extend_from_slice(&frame);
+        fs::write(path, ppm).map_err(|e| format!("can't write {}: {}", path, e))?;
+    }
+    if let Some(path) = &opts.dump_ram {
+        let ram: Vec<u8> = (0..=0xffffu16).map(|addr| machine.cpu().peek(addr)).collect();
+        fs::write(path, ram).map_err(|e| format!("can't write {}: {}", path, e))?;
+    }
+    if opts.print_hash {
+        println!("{:016x}", hash);
+    }
+    match opts.expect_hash {
+        Some(expected) if expected != hash => {
+            eprintln!("frame hash mismatch: expected {:016x}, got {:016x}", expected, hash);
+            Ok(false)
+        }
+        _ => Ok(true),
+    }
+}
+
+// a headless session as set up on the command line, returns the process exit status
+pub fn run_args(args: &Args, opts: &Options, machine: &mut Machine) -> i32 {
+    let mut session = match Session::from_args(args, machine) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 2;
+        }
+    };
+    let status = match run(machine, opts, &mut session) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(e) => {
+            eprintln!("{}", e);
+            2
+        }
+    };
+    session.finish(args, machine);
+    status
+}
@@ -0,0 +1,59 @@
+use super::*;
+use crate::testutil::machine;
+
+// a run with nothing set up around it
+fn run_plain(machine: &mut Machine, opts: &Options) -> Result<bool, String> {
+    let mut session = Session::from_args(&Args::default(), machine)?;
+    run(machine, opts, &mut session)
+}
+
+#[test]
+fn test_input_scripts() {
+    let events = parse_input_script("# comment\n3 $0d\n1 w  # up\n\n2 0x20\n").unwrap();
+    assert_eq!(events, [(1, b'w'), (2, b' '), (3, 0x0d)]);
+    assert!(parse_input_script("1").is_err());
+    // a character is one key, not the first byte of its encoding
+    assert_eq!(parse_input_script("0 w\n1 é").unwrap_err(), "input line 2: `é` isn't an ASCII key");
+    assert_eq!(parse_input_script("0 300").unwrap_err(), "input line 1: key 300 is over $ff");
+    assert_eq!(parse_input_script("0 $ff").unwrap(), [(0, 0xff)]);
+}
+
+#[test]
+fn test_runs_stop_at_brk_frames_or_pc() {
+    let opts = |frames, until_pc| Options { frames, until_pc, ..Options::default() };
+
+    let mut halted = machine("lda #$01\nsta $0200\nbrk");
+    assert!(run_plain(&mut halted, &opts(None, None)).unwrap());
+    assert_eq!(halted.cpu().peek(0x0200), 1);
+
+    let counter = "loop: inc $10\njmp loop";
+    let mut frames = machine(counter);
+    assert!(run_plain(&mut frames, &opts(Some(1), None)).unwrap());
+    // half the instructions of a frame are `inc`s
+    assert_eq!(frames.cpu().peek(0x10) as usize, easy6502::STEPS_PER_FRAME / 2 % 0x100);
+
+    let mut until = machine(counter);
+    assert!(run_plain(&mut until, &opts(None, Some(0x0602))).unwrap());
+    assert_eq!((until.cpu().program_counter, until.cpu().peek(0x10)), (0x0602, 1));
+}
+
+#[test]
+fn test_runs_that_never_stop_fail() {
+    let mut machine = machine("loop: jmp loop");
+    let error = run_plain(&mut machine, &Options::default()).unwrap_err();
+    assert!(error.starts_with("still running after 36000 frames"), "{}", error);
+}
+
+#[test]
+fn test_frame_hashes() {
+    let program = "lda #$01\nsta $0200\nbrk";
+    let mut frame = [0u8; easy6502::FRAME_SIZE];
+    let mut halted = machine(program);
+    run_plain(&mut halted, &Options::default()).unwrap();
+    halted.render(&mut frame);
+    let hash = fnv1a(&frame);
+
+    let expect = |hash| Options { expect_hash: Some(hash), ..Options::default() };
+    assert!(run_plain(&mut machine(program), &expect(hash)).unwrap());
+    assert!(!run_plain(&mut machine(program), &expect(hash ^ 1)).unwrap());
+}
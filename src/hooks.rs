@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use bitflags::bitflags;
+
+#[cfg(test)]
+mod hooks_test;
+
+bitflags! {
+    pub struct Access: u8 {
+        const READ = 0x01;
+        const WRITE = 0x02;
+        // the opcode fetch, operand bytes are plain reads
+        const EXECUTE = 0x04;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(usize);
+
+// Called with the kind of access, the address and the value read or about to
+// be written. Returning a value replaces it for the CPU and the later hooks.
+pub type Hook = dyn FnMut(Access, u16, u8) -> Option<u8>;
+
+#[derive(Clone)]
+struct Entry {
+    id: HookId,
+    access: Access,
+    range: RangeInclusive<u16>,
+    hook: Rc<RefCell<Hook>>,
+}
+
+// The hooks on a bus. They are shared, not copied, when the bus is cloned,
+// so a hook keeps seeing the machine after a rewind or a state load.
+#[derive(Clone, Default)]
+pub struct Hooks {
+    entries: Vec<Entry>,
+    next_id: usize,
+}
+
+impl Hooks {
+    pub fn add<F>(&mut self, access: Access, range: RangeInclusive<u16>, hook: F) -> HookId
+    where
+        F: FnMut(Access, u16, u8) -> Option<u8> + 'static,
+    {
+        let id = HookId(self.next_id);
+        self.next_id += 1;
+        self.entries.push(Entry {
+            id,
+            access,
+            range,
+            hook: Rc::new(RefCell::new(hook)),
+        });
+        id
+    }
+
+    // returns whether the hook was registered
+    pub fn remove(&mut self, id: HookId) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|entry| entry.id != id);
+        self.entries.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // runs the matching hooks in the order they were added, returns the final value
+    pub fn run(&self, access: Access, addr: u16, value: u8) -> u8 {
+        let mut value = value;
+        for entry in &self.entries {
+            if entry.access.contains(access) && entry.range.contains(&addr) {
+                if let Some(replaced) = (entry.hook.borrow_mut())(access, addr, value) {
+                    value = replaced;
+                }
+            }
+        }
+        value
+    }
+}
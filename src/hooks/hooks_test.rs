@@ -0,0 +1,73 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::*;
+use crate::testutil::cpu_with;
+use crate::cpu::CPU;
+
+#[test]
+fn test_read_hooks_can_override_the_value() {
+    let mut cpu = cpu_with("lda $10\nldx $11\nbrk");
+    cpu.mem_write(0x10, 0x01);
+    cpu.mem_write(0x11, 0x02);
+    cpu.bus.hooks.add(Access::READ, 0x10..=0x10, |_, _, value| Some(value + 0x40));
+    cpu.run();
+    assert_eq!(cpu.register_a, 0x41);
+    assert_eq!(cpu.register_x, 0x02);
+    // peek sees memory as it is
+    assert_eq!(cpu.peek(0x10), 0x01);
+}
+
+#[test]
+fn test_write_hooks_observe_and_replace() {
+    let mut cpu = cpu_with("lda #$05\nsta $0200\nsta $0300\nbrk");
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let seen = writes.clone();
+    cpu.bus.hooks.add(Access::WRITE, 0x0200..=0x02ff, move |_, addr, value| {
+        seen.borrow_mut().push((addr, value));
+        None
+    });
+    // a later hook sees what the earlier ones left
+    cpu.bus.hooks.add(Access::WRITE, 0x0000..=0xffff, |_, _, value| Some(value * 2));
+    cpu.run();
+    assert_eq!(*writes.borrow(), [(0x0200, 0x05)]);
+    assert_eq!(cpu.peek(0x0200), 0x0a);
+    assert_eq!(cpu.peek(0x0300), 0x0a);
+}
+
+#[test]
+fn test_execute_hooks_see_opcode_fetches_only() {
+    let mut cpu = cpu_with("lda #$01\nldx $0600\nbrk");
+    let fetches = Rc::new(RefCell::new(Vec::new()));
+    let seen = fetches.clone();
+    cpu.bus.hooks.add(Access::EXECUTE | Access::READ, 0x0600..=0x0606, move |access, addr, _| {
+        seen.borrow_mut().push((access, addr));
+        None
+    });
+    cpu.run();
+    assert_eq!(fetches.borrow()[0], (Access::EXECUTE, 0x0600));
+    assert!(fetches.borrow().contains(&(Access::READ, 0x0600)));
+    assert!(fetches.borrow().contains(&(Access::EXECUTE, 0x0602)));
+    assert!(!fetches.borrow().contains(&(Access::EXECUTE, 0x0601)));
+}
+
+#[test]
+fn test_remove_and_shared_clones() {
+    let mut cpu = CPU::new();
+    let count = Rc::new(RefCell::new(0));
+    let counter = count.clone();
+    let id = cpu.bus.hooks.add(Access::READ, 0x00..=0xff, move |_, _, _| {
+        *counter.borrow_mut() += 1;
+        None
+    });
+    let copy = cpu.clone();
+    cpu.mem_read(0x10);
+    copy.mem_read(0x20);
+    assert_eq!(*count.borrow(), 2);
+
+    assert!(cpu.bus.hooks.remove(id));
+    assert!(!cpu.bus.hooks.remove(id));
+    cpu.mem_read(0x10);
+    assert_eq!(*count.borrow(), 2);
+    assert!(cpu.bus.hooks.is_empty());
+}
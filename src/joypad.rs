@@ -0,0 +1,86 @@
+use std::cell::Cell;
+
+use bitflags::bitflags;
+
+#[cfg(test)]
+mod joypad_test;
+
+bitflags! {
+    // in the order a read of $4016/$4017 shifts them out
+    #[derive(Default)]
+    pub struct Buttons: u8 {
+        const A = 0x01;
+        const B = 0x02;
+        const SELECT = 0x04;
+        const START = 0x08;
+        const UP = 0x10;
+        const DOWN = 0x20;
+        const LEFT = 0x40;
+        const RIGHT = 0x80;
+    }
+}
+
+// A standard controller. `buttons` is what's held for the current frame;
+// writing 1 then 0 to $4016 latches it into the shift register, and each read
+// returns the next bit, then 1s once all eight are out.
+#[derive(Debug, Clone, Default)]
+pub struct Joypad {
+    pub buttons: Buttons,
+    strobe: bool,
+    // reads don't need a mutable bus
+    shift: Cell<u8>,
+}
+
+impl Joypad {
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            self.shift.set(self.buttons.bits);
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        let value = self.peek();
+        if !self.strobe {
+            self.shift.set(self.shift.get() >> 1 | 0x80);
+        }
+        value
+    }
+
+    // buttons, strobe and shift register, for save states
+    pub fn save(&self) -> [u8; 3] {
+        [self.buttons.bits, self.strobe as u8, self.shift.get()]
+    }
+
+    pub fn restore(&mut self, state: &[u8; 3]) {
+        self.buttons = Buttons::from_bits_truncate(state[0]);
+        self.strobe = state[1] != 0;
+        self.shift.set(state[2]);
+    }
+
+    // the next bit without shifting; the upper bits are open bus, usually
+    // the $40 of the address
+    pub fn peek(&self) -> u8 {
+        let bit = if self.strobe { self.buttons.bits & 1 } else { self.shift.get() & 1 };
+        0x40 | bit
+    }
+}
+
+// Auto-fire: buttons held on turbo keys are pressed every other frame, the
+// first frame they're latched included.
+#[derive(Debug, Default)]
+pub struct Turbo {
+    frame: bool,
+}
+
+impl Turbo {
+    // the buttons to latch for the next frame
+    pub fn apply(&mut self, held: Buttons, turbo: Buttons) -> Buttons {
+        if turbo.is_empty() {
+            self.frame = false;
+            return held;
+        }
+        self.frame = !self.frame;
+        if self.frame { held | turbo } else { held }
+    }
+}
@@ -0,0 +1,64 @@
+use super::*;
+use crate::bus::Bus;
+use crate::cpu::CPU;
+use crate::testutil::Ines;
+
+fn bus() -> Bus {
+    Bus::with_rom(Ines::new(&[]).rom())
+}
+
+#[test]
+fn test_buttons_shift_out_after_the_strobe() {
+    let mut bus = bus();
+    bus.joypads[0].buttons = Buttons::A | Buttons::START | Buttons::RIGHT;
+    bus.joypads[1].buttons = Buttons::B;
+    bus.mem_write(0x4016, 1);
+    // held high, every read is A
+    assert_eq!(bus.mem_read(0x4016), 0x41);
+    assert_eq!(bus.mem_read(0x4016), 0x41);
+    bus.mem_write(0x4016, 0);
+
+    let bits: Vec<u8> = (0..10).map(|_| bus.mem_read(0x4016) & 1).collect();
+    assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    assert_eq!(bus.mem_read(0x4017) & 1, 0);
+    assert_eq!(bus.peek(0x4017) & 1, 1);
+    assert_eq!(bus.mem_read(0x4017) & 1, 1);
+}
+
+#[test]
+fn test_buttons_held_after_the_latch_wait_for_the_next_one() {
+    let mut bus = bus();
+    bus.mem_write(0x4016, 1);
+    bus.mem_write(0x4016, 0);
+    bus.joypads[0].buttons = Buttons::A;
+    assert_eq!(bus.mem_read(0x4016) & 1, 0);
+    bus.mem_write(0x4016, 1);
+    bus.mem_write(0x4016, 0);
+    assert_eq!(bus.mem_read(0x4016) & 1, 1);
+}
+
+#[test]
+fn test_save_states_keep_the_shift_register() {
+    let mut cpu = CPU::with_bus(bus());
+    cpu.bus.joypads[0].buttons = Buttons::B | Buttons::UP;
+    cpu.mem_write(0x4016, 1);
+    cpu.mem_write(0x4016, 0);
+    cpu.mem_read(0x4016);
+    let state = cpu.save_state();
+
+    let mut restored = CPU::with_bus(bus());
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.bus.joypads[0].buttons, Buttons::B | Buttons::UP);
+    let bits: Vec<u8> = (0..4).map(|_| restored.mem_read(0x4016) & 1).collect();
+    assert_eq!(bits, [1, 0, 0, 1]);
+}
+
+#[test]
+fn test_turbo_buttons_toggle_each_frame() {
+    let mut turbo = Turbo::default();
+    let frames: Vec<Buttons> = (0..4).map(|_| turbo.apply(Buttons::UP, Buttons::A)).collect();
+    assert_eq!(frames, [Buttons::UP | Buttons::A, Buttons::UP, Buttons::UP | Buttons::A, Buttons::UP]);
+    // let go and pressed again, it fires straight away
+    assert_eq!(turbo.apply(Buttons::empty(), Buttons::empty()), Buttons::empty());
+    assert_eq!(turbo.apply(Buttons::empty(), Buttons::B), Buttons::B);
+}
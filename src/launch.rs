@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use crate::battery::BatterySave;
+use crate::cli::Args;
+use crate::gdb;
+use crate::headless;
+use crate::machine::Machine;
+use crate::savestate::Slots;
+
+#[cfg(test)]
+mod launch_test;
+
+// What every frontend does with the command line before running: the
+// machine, its battery save and save state slots, then the --load-slot
+// state. Headless runs always start from a clean save RAM.
+pub struct Launch {
+    pub machine: Machine,
+    pub slots: Slots,
+    pub battery: Option<BatterySave>,
+}
+
+impl Launch {
+    pub fn from_args(args: &Args) -> Result<Launch, String> {
+        let mut machine = args.load()?;
+        // the built-in snake keeps its slots in the working directory
+        let slots = Slots {
+            program: PathBuf::from(args.program.as_deref().unwrap_or("snake")),
+            current: args.load_slot.unwrap_or(0),
+        };
+        let battery = match (&args.program, &args.headless) {
+            (Some(program), None) => BatterySave::open(Path::new(program), machine.cpu_mut())?,
+            _ => None,
+        };
+        if args.load_slot.is_some() {
+            slots.load(&mut machine)?;
+        }
+        Ok(Launch { machine, slots, battery })
+    }
+
+    // Runs a --gdb session or a --headless run to the end and returns the
+    // process exit status; None leaves the machine to a window.
+    pub fn run_windowless(&mut self, args: &Args) -> Option<i32> {
+        if let Some(port) = args.gdb {
+            let status = match gdb::serve(&mut self.machine, port) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    2
+                }
+            };
+            if let Some(battery) = self.battery.as_mut() {
+                battery.flush(self.machine.cpu_mut()).unwrap_or_else(|e| eprintln!("{}", e));
+            }
+            return Some(status);
+        }
+        let opts = args.headless.as_ref()?;
+        Some(headless::run_args(args, opts, &mut self.machine))
+    }
+}
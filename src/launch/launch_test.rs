@@ -0,0 +1,37 @@
+use std::fs;
+
+use super::*;
+
+fn args(list: &[&str]) -> Args {
+    Args::parse(&list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>()).unwrap()
+}
+
+#[test]
+fn test_slots_are_kept_next_to_the_program() {
+    let dir = std::env::temp_dir().join(format!("launch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let program = dir.join("count.asm");
+    fs::write(&program, "loop: inc $10\njmp loop\n").unwrap();
+    let program = program.to_str().unwrap();
+
+    let mut launch = Launch::from_args(&args(&["--headless", program])).unwrap();
+    launch.machine.cpu_mut().register_a = 0x42;
+    launch.slots.current = 2;
+    launch.slots.save(&launch.machine).unwrap();
+    assert!(dir.join("count.ss2").exists());
+
+    let launch = Launch::from_args(&args(&["--headless", "--load-slot", "2", program])).unwrap();
+    assert_eq!(launch.machine.cpu().register_a, 0x42);
+    assert!(Launch::from_args(&args(&["--headless", "--load-slot", "3", program])).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_runs_without_a_program_get_the_snake() {
+    let headless = args(&["--headless", "--frames", "1"]);
+    let mut launch = Launch::from_args(&headless).unwrap();
+    assert_eq!(launch.slots.program, PathBuf::from("snake"));
+    assert_eq!(launch.run_windowless(&headless), Some(0));
+    // the rest is up to the window
+    assert_eq!(Launch::from_args(&Args::default()).unwrap().run_windowless(&Args::default()), None);
+}
@@ -0,0 +1,47 @@
+// The emulator core, usable without any native libraries. The SDL frontend
+// lives in `main.rs` behind the `sdl` feature, `nes-headless` runs without it.
+//
+// Only the CPU, bus and cartridge exist so far; the PPU and APU will be
+// added to the bus as they get written.
+
+pub mod asm;
+pub mod battery;
+pub mod blargg;
+pub mod bus;
+pub mod callstack;
+pub mod cartridge;
+pub mod cdl;
+pub mod cheats;
+pub mod cli;
+pub mod coverage;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod dormann;
+pub mod easy6502;
+pub mod gdb;
+pub mod hash;
+pub mod headless;
+pub mod hooks;
+pub mod joypad;
+pub mod launch;
+pub mod loader;
+pub mod machine;
+pub mod movie;
+pub mod opcodes;
+pub mod profiler;
+pub mod ramsearch;
+pub mod region;
+pub mod rewind;
+pub mod savestate;
+pub mod scheduler;
+pub mod session;
+pub mod symbols;
+pub mod trace;
+
+#[cfg(test)]
+mod testutil;
+
+pub use crate::bus::Bus;
+pub use crate::cartridge::Rom;
+pub use crate::cpu::CPU;
@@ -0,0 +1,252 @@
+use std::path::Path;
+
+use crate::cpu::{CPU, IRQ_VECTOR, NMI_VECTOR, RESET_VECTOR};
+use crate::hash::fnv1a;
+
+#[cfg(test)]
+mod loader_test;
+
+// A block of bytes placed at a fixed address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub address: u16,
+    pub data: Vec<u8>,
+}
+
+// A memory image to load into the CPU: any number of segments plus the
+// vectors to point at them. Vectors left at None keep whatever the segments
+// put there, so a full 64K image can bring its own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub segments: Vec<Segment>,
+    pub reset: Option<u16>,
+    pub nmi: Option<u16>,
+    pub irq: Option<u16>,
+}
+
+impl Program {
+    // a single segment that also is where execution starts
+    pub fn at(address: u16, data: Vec<u8>) -> Self {
+        Program {
+            segments: vec![Segment { address, data }],
+            reset: Some(address),
+            ..Program::default()
+        }
+    }
+
+    pub fn add_segment(&mut self, address: u16, data: Vec<u8>) {
+        self.segments.push(Segment { address, data });
+    }
+
+    // identifies the image, for save states of the machine it's loaded in
+    pub fn hash(&self) -> u64 {
+        let mut data = Vec::new();
+        for segment in &self.segments {
+            data.extend_from_slice(&segment.address.to_le_bytes());
+            data.extend_from_slice(&(segment.data.len() as u32).to_le_bytes());
+            data.extend_from_slice(&segment.data);
+        }
+        for vector in [self.reset, self.nmi, self.irq] {
+            data.extend_from_slice(&vector.map_or([0xff; 3], |addr| [0, addr as u8, (addr >> 8) as u8]));
+        }
+        fnv1a(&data)
+    }
+
+    // Segments are written in order, later ones win where they overlap.
+    // Loading isn't a CPU access, so the bus hooks don't see it.
+    pub fn load_into(&self, cpu: &mut CPU) -> Result<(), String> {
+        for segment in &self.segments {
+            if segment.address as usize + segment.data.len() > 0x10000 {
+                return Err(format!(
+                    "segment at ${:04x} of {} bytes runs past $ffff",
+                    segment.address,
+                    segment.data.len()
+                ));
+            }
+        }
+        for segment in &self.segments {
+            for (i, byte) in segment.data.iter().enumerate() {
+                cpu.bus.poke(segment.address + i as u16, *byte);
+            }
+        }
+        for (vector, address) in [(NMI_VECTOR, self.nmi), (RESET_VECTOR, self.reset), (IRQ_VECTOR, self.irq)] {
+            if let Some(address) = address {
+                let [lo, hi] = address.to_le_bytes();
+                cpu.bus.poke(vector, lo);
+                cpu.bus.poke(vector + 1, hi);
+            }
+        }
+        Ok(())
+    }
+}
+
+// File formats for bare programs, picked by extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    // bytes loaded as is at a given origin
+    Raw,
+    // `.hex`, `:LLAAAATT...CC` records
+    IntelHex,
+    // `.srec` / `.s19` / `.s28` / `.s37`, `S1`..`S9` records
+    SRecord,
+    // `.prg`, a little endian load address followed by the bytes
+    Prg,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Format {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match ext.as_str() {
+            "hex" | "ihx" | "ihex" => Format::IntelHex,
+            "srec" | "s19" | "s28" | "s37" | "mot" => Format::SRecord,
+            "prg" => Format::Prg,
+            _ => Format::Raw,
+        }
+    }
+
+    // `origin` only matters for raw binaries, the others carry their addresses
+    pub fn parse(&self, data: &[u8], origin: u16) -> Result<Program, String> {
+        let mut program = match self {
+            Format::Raw => return Ok(Program::at(origin, data.to_vec())),
+            Format::IntelHex => parse_intel_hex(&String::from_utf8_lossy(data))?,
+            Format::SRecord => parse_srecord(&String::from_utf8_lossy(data))?,
+            Format::Prg => {
+                if data.len() < 2 {
+                    return Err("prg file has no load address".to_string());
+                }
+                let address = u16::from_le_bytes([data[0], data[1]]);
+                Program::at(address, data[2..].to_vec())
+            }
+        };
+        program.default_reset();
+        Ok(program)
+    }
+}
+
+impl Program {
+    // start at the first segment, unless the image has its own reset vector
+    fn default_reset(&mut self) {
+        let has_vector = self.segments.iter().any(|segment| {
+            let end = segment.address as usize + segment.data.len();
+            (segment.address as usize) <= RESET_VECTOR as usize && RESET_VECTOR as usize + 2 <= end
+        });
+        if self.reset.is_none() && !has_vector {
+            self.reset = self.segments.first().map(|segment| segment.address);
+        }
+    }
+
+    // consecutive records usually continue each other, keep them in one segment
+    fn append(&mut self, address: u32, data: &[u8]) -> Result<(), String> {
+        if address as usize + data.len() > 0x10000 {
+            return Err(format!("data at ${:x} is outside the 64K address space", address));
+        }
+        match self.segments.last_mut() {
+            Some(last) if last.address as usize + last.data.len() == address as usize => {
+                last.data.extend_from_slice(data);
+            }
+            _ => self.add_segment(address as u16, data.to_vec()),
+        }
+        Ok(())
+    }
+}
+
+fn parse_hex_bytes(text: &str, line: usize) -> Result<Vec<u8>, String> {
+    if !text.is_ascii() || !text.len().is_multiple_of(2) {
+        return Err(format!("line {}: expected pairs of hex digits", line));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).map_err(|_| format!("line {}: invalid hex", line)))
+        .collect()
+}
+
+pub fn parse_intel_hex(text: &str) -> Result<Program, String> {
+    let mut program = Program::default();
+    let mut base = 0u32;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let record = line
+            .strip_prefix(':')
+            .ok_or_else(|| format!("line {}: record doesn't start with `:`", number))?;
+        let bytes = parse_hex_bytes(record, number)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(format!("line {}: record length doesn't match", number));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0 {
+            return Err(format!("line {}: bad checksum", number));
+        }
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        match bytes[3] {
+            0x00 => program.append(base + address, data)?,
+            0x01 => break,
+            0x02 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4,
+            0x04 if data.len() == 2 => base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16,
+            // start segment (CS:IP) and start linear address
+            0x03 if data.len() == 4 => {
+                let segment = u16::from_be_bytes([data[0], data[1]]) as u32;
+                let offset = u16::from_be_bytes([data[2], data[3]]) as u32;
+                program.reset = Some(((segment << 4) + offset) as u16);
+            }
+            0x05 if data.len() == 4 => {
+                program.reset = Some(u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as u16);
+            }
+            kind => return Err(format!("line {}: unsupported record type {:02x}", number, kind)),
+        }
+    }
+    Ok(program)
+}
+
+pub fn parse_srecord(text: &str) -> Result<Program, String> {
+    let mut program = Program::default();
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // the type and data are sliced by byte
+        if !line.is_ascii() {
+            return Err(format!("line {}: record isn't ASCII", number));
+        }
+        let kind = match line.strip_prefix('S').or_else(|| line.strip_prefix('s')) {
+            Some(rest) if !rest.is_empty() => rest.as_bytes()[0],
+            _ => return Err(format!("line {}: record doesn't start with `S`", number)),
+        };
+        let bytes = parse_hex_bytes(&line[2..], number)?;
+        if bytes.is_empty() || bytes.len() != bytes[0] as usize + 1 {
+            return Err(format!("line {}: record length doesn't match", number));
+        }
+        if bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)) != 0xff {
+            return Err(format!("line {}: bad checksum", number));
+        }
+        let address_size = match kind {
+            b'0' | b'1' | b'5' | b'9' => 2,
+            b'2' | b'6' | b'8' => 3,
+            b'3' | b'7' => 4,
+            _ => return Err(format!("line {}: unknown record type S{}", number, kind as char)),
+        };
+        if bytes.len() < address_size + 2 {
+            return Err(format!("line {}: record is too short", number));
+        }
+        let address = bytes[1..=address_size]
+            .iter()
+            .fold(0u32, |address, byte| address << 8 | *byte as u32);
+        let data = &bytes[address_size + 1..bytes.len() - 1];
+        match kind {
+            b'1' | b'2' | b'3' => program.append(address, data)?,
+            // plenty of tools write a zero start address to mean "none given"
+            b'7' | b'8' | b'9' if address != 0 => program.reset = Some(address as u16),
+            _ => {}
+        }
+    }
+    Ok(program)
+}
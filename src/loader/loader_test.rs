@@ -0,0 +1,117 @@
+use std::path::Path;
+
+use super::*;
+
+#[test]
+fn test_segments_and_vectors() {
+    let mut program = Program::at(0x8000, vec![0xa9, 0x01, 0x00]);
+    program.add_segment(0x0300, vec![0x40]);
+    program.nmi = Some(0x0300);
+    program.irq = Some(0x0301);
+
+    let mut cpu = CPU::new();
+    cpu.load_program(&program).unwrap();
+    assert_eq!(cpu.mem_read(0x8000), 0xa9);
+    assert_eq!(cpu.mem_read(0x0300), 0x40);
+    assert_eq!(cpu.mem_read_u16(NMI_VECTOR), 0x0300);
+    assert_eq!(cpu.mem_read_u16(RESET_VECTOR), 0x8000);
+    assert_eq!(cpu.mem_read_u16(IRQ_VECTOR), 0x0301);
+
+    cpu.reset();
+    assert_eq!(cpu.program_counter, 0x8000);
+}
+
+#[test]
+fn test_image_vectors_are_kept() {
+    let mut program = Program::default();
+    program.add_segment(0xfffc, vec![0x34, 0x12]);
+
+    let mut cpu = CPU::new();
+    cpu.load_program(&program).unwrap();
+    cpu.reset();
+    assert_eq!(cpu.program_counter, 0x1234);
+}
+
+#[test]
+fn test_runs_code_below_the_reset_vector() {
+    // jsr $0010, the routine in zero page loads A and returns
+    let mut program = Program::at(0x8000, vec![0x20, 0x10, 0x00, 0x00]);
+    program.add_segment(0x0010, vec![0xa9, 0x42, 0x60]);
+
+    let mut cpu = CPU::new();
+    cpu.load_program(&program).unwrap();
+    cpu.reset();
+    cpu.run();
+    assert_eq!(cpu.register_a, 0x42);
+}
+
+#[test]
+fn test_segment_past_the_end_is_rejected() {
+    let program = Program::at(0xfffe, vec![0; 3]);
+    let mut cpu = CPU::new();
+    assert!(cpu.load_program(&program).is_err());
+}
+
+#[test]
+fn test_format_from_path() {
+    assert_eq!(Format::from_path(Path::new("a.HEX")), Format::IntelHex);
+    assert_eq!(Format::from_path(Path::new("a.s19")), Format::SRecord);
+    assert_eq!(Format::from_path(Path::new("a.prg")), Format::Prg);
+    assert_eq!(Format::from_path(Path::new("a.bin")), Format::Raw);
+    assert_eq!(Format::from_path(Path::new("a")), Format::Raw);
+}
+
+#[test]
+fn test_raw_binary_at_origin() {
+    let program = Format::Raw.parse(&[0xea, 0x00], 0xc000).unwrap();
+    assert_eq!(program, Program::at(0xc000, vec![0xea, 0x00]));
+}
+
+#[test]
+fn test_prg_load_address() {
+    let program = Format::Prg.parse(&[0x01, 0x08, 0xea, 0x00], 0x0600).unwrap();
+    assert_eq!(program, Program::at(0x0801, vec![0xea, 0x00]));
+    assert!(Format::Prg.parse(&[0x01], 0x0600).is_err());
+}
+
+#[test]
+fn test_intel_hex() {
+    let text = ":04800000A9018D0045\n:02800400020078\n:0103000040BC\n:040000050000800077\n:00000001FF\n";
+    let program = Format::IntelHex.parse(text.as_bytes(), 0).unwrap();
+    assert_eq!(
+        program.segments,
+        vec![
+            Segment { address: 0x8000, data: vec![0xa9, 0x01, 0x8d, 0x00, 0x02, 0x00] },
+            Segment { address: 0x0300, data: vec![0x40] },
+        ]
+    );
+    assert_eq!(program.reset, Some(0x8000));
+
+    let mut cpu = CPU::new();
+    cpu.load_program(&program).unwrap();
+    cpu.reset();
+    cpu.run();
+    assert_eq!(cpu.mem_read(0x0200), 0x01);
+}
+
+#[test]
+fn test_intel_hex_errors() {
+    assert!(parse_intel_hex(":0103000040BD").unwrap_err().contains("checksum"));
+    assert!(parse_intel_hex("0103000040BC").is_err());
+    assert!(parse_intel_hex(":0203000040BC").is_err());
+}
+
+#[test]
+fn test_srecord() {
+    let text = "S0050000686929\nS1061000A901003F\nS9031000EC\n";
+    let program = Format::SRecord.parse(text.as_bytes(), 0).unwrap();
+    assert_eq!(program, Program::at(0x1000, vec![0xa9, 0x01, 0x00]));
+
+    // a zero start address falls back to the first segment
+    let text = "S1061000A901003F\nS9030000FC\n";
+    let program = Format::SRecord.parse(text.as_bytes(), 0).unwrap();
+    assert_eq!(program.reset, Some(0x1000));
+
+    assert!(parse_srecord("S1061000A901003E").unwrap_err().contains("checksum"));
+    assert_eq!(parse_srecord("S1061000A901003F\nSé1000").unwrap_err(), "line 2: record isn't ASCII");
+}
@@ -0,0 +1,332 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use crate::bus::Bus;
+use crate::cartridge::Rom;
+use crate::cli::MachineKind;
+use crate::cpu::{CpuError, CPU};
+use crate::easy6502::{self, Easy6502Machine};
+use crate::joypad::Buttons;
+use crate::loader::Program;
+use crate::region::Region;
+use crate::savestate::{StateReader, StateWriter, RNG_CHUNK};
+
+#[cfg(test)]
+mod machine_test;
+
+// the NES work RAM, mirrored up to $1fff
+const WORK_RAM: u16 = 0x0800;
+
+// What RAM and the A, X and Y registers hold at power on.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum RamInit {
+    #[default]
+    Zeros,
+    Ff,
+    Random,
+}
+
+impl RamInit {
+    pub fn from_name(name: &str) -> Option<RamInit> {
+        match name {
+            "zeros" => Some(RamInit::Zeros),
+            "ff" => Some(RamInit::Ff),
+            "random" => Some(RamInit::Random),
+            _ => None,
+        }
+    }
+
+    // FCEUX's RAMInitOption, whose 0 is its own default pattern
+    pub fn from_fm2(option: u8) -> Option<RamInit> {
+        match option {
+            0 | 2 => Some(RamInit::Zeros),
+            1 => Some(RamInit::Ff),
+            3 => Some(RamInit::Random),
+            _ => None,
+        }
+    }
+
+    pub fn fm2(self) -> u8 {
+        match self {
+            RamInit::Zeros => 2,
+            RamInit::Ff => 1,
+            RamInit::Random => 3,
+        }
+    }
+
+    fn byte(self, random: &mut Random) -> u8 {
+        match self {
+            RamInit::Zeros => 0x00,
+            RamInit::Ff => 0xff,
+            RamInit::Random => random.byte(),
+        }
+    }
+}
+
+// The generator rand's StdRng wraps, seeded, and how many numbers it gave:
+// all a save state needs to go on with the same stream.
+#[derive(Clone)]
+struct Random {
+    rng: ChaCha20Rng,
+    draws: u64,
+}
+
+impl Random {
+    fn new(seed: u64) -> Random {
+        Random::resume(seed, 0)
+    }
+
+    // where `new(seed)` is after `draws` bytes
+    fn resume(seed: u64, draws: u64) -> Random {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        // each byte takes one 32 bit word of the stream
+        rng.set_word_pos(draws as u128);
+        Random { rng, draws }
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.draws += 1;
+        self.rng.gen()
+    }
+}
+
+// how a machine comes up; the same power on gives the same run
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PowerOn {
+    pub seed: u64,
+    pub ram: RamInit,
+    // how long an NES frame runs
+    pub region: Region,
+}
+
+// the most cycles an instruction can run past the end of a frame
+const MAX_OVERSHOOT: u64 = 8;
+
+#[derive(Clone)]
+enum System {
+    Easy6502(Box<Easy6502Machine>),
+    Nes(Box<CPU>),
+}
+
+// What the frontends drive: a CPU plus whatever the machine wires around it,
+// and the one random number generator everything random in it draws from.
+// The NES side is only the CPU on the cartridge until the PPU exists.
+#[derive(Clone)]
+pub struct Machine {
+    system: System,
+    random: Random,
+    seed: u64,
+    ram: RamInit,
+    region: Region,
+    // the cycle count an NES frame ends at, and whether the next one is half
+    // a cycle longer
+    frame_end: u64,
+    half_cycle: bool,
+    // instructions run so far in an Easy6502 frame
+    frame_steps: usize,
+}
+
+impl Machine {
+    pub fn easy6502(program: &Program) -> Result<Machine, String> {
+        Machine::easy6502_with(program, PowerOn::default())
+    }
+
+    // the whole flat memory starts out as `power.ram`, then the program is loaded
+    pub fn easy6502_with(program: &Program, power: PowerOn) -> Result<Machine, String> {
+        let machine = Easy6502Machine::with_program(program)?;
+        Ok(Machine::power_on(System::Easy6502(Box::new(machine)), power))
+    }
+
+    pub fn nes(rom: &[u8]) -> Result<Machine, String> {
+        Machine::nes_with(rom, PowerOn::default())
+    }
+
+    // work RAM starts out as `power.ram`, PRG-RAM is left to the battery save
+    pub fn nes_with(rom: &[u8], power: PowerOn) -> Result<Machine, String> {
+        let mut cpu = CPU::with_bus(Bus::with_rom(Rom::new(rom)?));
+        cpu.halt_on_brk = false;
+        Ok(Machine::power_on(System::Nes(Box::new(cpu)), power))
+    }
+
+    fn power_on(system: System, power: PowerOn) -> Machine {
+        let mut machine = Machine {
+            system,
+            random: Random::new(power.seed),
+            seed: power.seed,
+            ram: power.ram,
+            region: power.region,
+            frame_end: 0,
+            half_cycle: false,
+            frame_steps: 0,
+        };
+        machine.power_cycle();
+        machine
+    }
+
+    // Switches the machine off and on again: RAM filled afresh from the seed
+    // and RAM pattern, the joypads let go, and the program or cartridge
+    // started over. Bus hooks stay attached.
+    pub fn power_cycle(&mut self) {
+        self.random = Random::new(self.seed);
+        let (ram, random) = (self.ram, &mut self.random);
+        match &mut self.system {
+            System::Easy6502(machine) => {
+                for addr in 0..=0xffff {
+                    machine.cpu.bus.poke(addr, ram.byte(random));
+                }
+                machine.cpu.bus.joypads = Default::default();
+                machine.reload();
+            }
+            System::Nes(cpu) => {
+                for addr in 0..WORK_RAM {
+                    cpu.bus.poke(addr, ram.byte(random));
+                }
+                cpu.bus.joypads = Default::default();
+                cpu.reset();
+            }
+        }
+        // the registers come last, the reset clears some of them
+        let [a, x, y] = [(); 3].map(|_| ram.byte(&mut self.random));
+        let cpu = self.cpu_mut();
+        cpu.register_a = a;
+        cpu.register_x = x;
+        cpu.register_y = y;
+    }
+
+    pub fn kind(&self) -> MachineKind {
+        match self.system {
+            System::Easy6502(_) => MachineKind::Easy6502,
+            System::Nes(_) => MachineKind::Nes,
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        match &self.system {
+            System::Easy6502(machine) => &machine.cpu,
+            System::Nes(cpu) => cpu,
+        }
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        match &mut self.system {
+            System::Easy6502(machine) => &mut machine.cpu,
+            System::Nes(cpu) => cpu,
+        }
+    }
+
+    pub fn step(&mut self) -> Result<bool, CpuError> {
+        self.frame_steps += 1;
+        match &mut self.system {
+            System::Easy6502(machine) => machine.step(self.random.byte()),
+            System::Nes(cpu) => cpu.step(),
+        }
+    }
+
+    // Starts a frame: STEPS_PER_FRAME instructions on the Easy6502 machine,
+    // a video frame's worth of CPU cycles on the NES.
+    pub fn begin_frame(&mut self) {
+        self.frame_steps = 0;
+        if let System::Nes(cpu) = &self.system {
+            // an instruction that ran past the last frame's end takes its
+            // cycles from this one; after a state load the count starts over
+            if !(self.frame_end..self.frame_end + MAX_OVERSHOOT).contains(&cpu.cycles) {
+                self.frame_end = cpu.cycles;
+            }
+            let half_cycles = self.region.frame_half_cycles() + self.half_cycle as u64;
+            self.frame_end += half_cycles / 2;
+            self.half_cycle = half_cycles % 2 == 1;
+        }
+    }
+
+    // whether the frame begun last has run
+    pub fn frame_done(&self) -> bool {
+        match &self.system {
+            System::Easy6502(_) => self.frame_steps >= easy6502::STEPS_PER_FRAME,
+            System::Nes(cpu) => cpu.cycles >= self.frame_end,
+        }
+    }
+
+    // keys only go to the Easy6502 machine, the NES one has joypads instead
+    pub fn press_key(&mut self, key: u8) {
+        if let System::Easy6502(machine) = &mut self.system {
+            machine.press_key(key);
+        }
+    }
+
+    // The buttons held on both joypads for the frame about to run. Frontends
+    // call this once per frame, so input lands at the same step however it
+    // was produced.
+    pub fn latch(&mut self, buttons: [Buttons; 2]) {
+        match &mut self.system {
+            System::Easy6502(machine) => machine.latch(buttons[0]),
+            System::Nes(cpu) => {
+                for (joypad, buttons) in cpu.bus.joypads.iter_mut().zip(buttons) {
+                    joypad.buttons = buttons;
+                }
+            }
+        }
+    }
+
+    // same seed, same random numbers from here on
+    pub fn reseed(&mut self, seed: u64) {
+        self.random = Random::new(seed);
+        self.seed = seed;
+    }
+
+    // the seed of the last power on or `reseed`
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn ram_init(&self) -> RamInit {
+        self.ram
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    // of the cartridge or the program image loaded
+    fn hash(&self) -> u64 {
+        match &self.system {
+            System::Easy6502(machine) => machine.program_hash(),
+            System::Nes(cpu) => cpu.bus.rom_hash(),
+        }
+    }
+
+    // The CPU's state, and the seed and how far the random numbers got, so a
+    // loaded state draws the same numbers the saved run went on to draw.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.hash());
+        self.cpu().write_state(&mut state);
+        let mut random = self.seed.to_le_bytes().to_vec();
+        random.extend_from_slice(&self.random.draws.to_le_bytes());
+        state.chunk(RNG_CHUNK, &random);
+        state.finish()
+    }
+
+    // states without the random numbers leave them where they are
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let state = StateReader::new(data)?;
+        if state.rom_hash != self.hash() {
+            return Err(match self.kind() {
+                MachineKind::Nes => "save state was made with a different ROM".to_string(),
+                MachineKind::Easy6502 => "save state was made with a different program".to_string(),
+            });
+        }
+        self.cpu_mut().read_state(&state)?;
+        if let Some(random) = state.chunk(RNG_CHUNK).filter(|random| random.len() >= 16) {
+            self.seed = u64::from_le_bytes(random[..8].try_into().unwrap());
+            self.random = Random::resume(self.seed, u64::from_le_bytes(random[8..16].try_into().unwrap()));
+        }
+        Ok(())
+    }
+
+    // returns whether the frame changed, always false without a screen
+    pub fn render(&self, frame: &mut [u8; easy6502::FRAME_SIZE]) -> bool {
+        match &self.system {
+            System::Easy6502(machine) => machine.render(frame),
+            System::Nes(_) => false,
+        }
+    }
+}
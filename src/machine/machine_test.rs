@@ -0,0 +1,133 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use super::*;
+use crate::asm::assemble;
+use crate::hooks::Access;
+use crate::testutil::Ines;
+
+const READ_RANDOM: &str = "lda $fe\nsta $10\nlda $fe\nsta $11\nbrk";
+
+fn easy6502(source: &str, power: PowerOn) -> Machine {
+    Machine::easy6502_with(&Program::at(0x0600, assemble(source, 0x0600).unwrap()), power).unwrap()
+}
+
+fn nes(power: PowerOn) -> Machine {
+    Machine::nes_with(&Ines::new(&[]).image(), power).unwrap()
+}
+
+fn run(machine: &mut Machine) {
+    while machine.step().unwrap() {}
+}
+
+#[test]
+fn test_random_bytes_follow_the_seed() {
+    let read_random = |seed| {
+        let mut machine = easy6502(READ_RANDOM, PowerOn { seed, ram: RamInit::Zeros, ..PowerOn::default() });
+        run(&mut machine);
+        (machine.cpu().mem_read(0x10), machine.cpu().mem_read(0x11))
+    };
+    assert_eq!(read_random(1), read_random(1));
+    assert_ne!(read_random(1), read_random(2));
+
+    let mut machine = easy6502(READ_RANDOM, PowerOn::default());
+    machine.reseed(1);
+    run(&mut machine);
+    assert_eq!((machine.cpu().mem_read(0x10), machine.cpu().mem_read(0x11)), read_random(1));
+    assert_eq!(machine.seed(), 1);
+}
+
+#[test]
+fn test_ram_init() {
+    let machine = easy6502("brk", PowerOn { seed: 0, ram: RamInit::Ff, ..PowerOn::default() });
+    assert_eq!(machine.cpu().peek(0x0200), 0xff);
+    assert_eq!(machine.cpu().peek(0x0600), 0x00);
+    assert_eq!(machine.cpu().peek(0x0601), 0xff);
+    assert_eq!((machine.cpu().register_a, machine.cpu().register_y), (0xff, 0xff));
+
+    let machine = nes(PowerOn { seed: 0, ram: RamInit::Ff, ..PowerOn::default() });
+    assert_eq!(machine.cpu().peek(0x07ff), 0xff);
+    assert_eq!(machine.cpu().peek(0x6000), 0x00);
+
+    let zeros = nes(PowerOn::default());
+    assert!((0..0x800).all(|addr| zeros.cpu().peek(addr) == 0));
+    assert_eq!(zeros.cpu().register_x, 0);
+}
+
+#[test]
+fn test_random_ram_follows_the_seed() {
+    let ram = |seed| {
+        let machine = nes(PowerOn { seed, ram: RamInit::Random, ..PowerOn::default() });
+        let cpu = machine.cpu();
+        let ram: Vec<u8> = (0..0x800).map(|addr| cpu.peek(addr)).collect();
+        (ram, [cpu.register_a, cpu.register_x, cpu.register_y])
+    };
+    assert_eq!(ram(5), ram(5));
+    assert_ne!(ram(5).0, ram(6).0);
+    assert!(ram(5).0.iter().any(|&byte| byte != ram(5).0[0]));
+}
+
+#[test]
+fn test_frame_lengths() {
+    let run_frames = |machine: &mut Machine, frames| {
+        for _ in 0..frames {
+            machine.begin_frame();
+            while !machine.frame_done() {
+                machine.step().unwrap();
+            }
+        }
+    };
+    // NOPs take 2 cycles, so a frame ends up to a cycle late
+    for (region, two_frames) in [(Region::Ntsc, 59561), (Region::Pal, 66495), (Region::Dendy, 70928)] {
+        let mut machine = nes(PowerOn { region, ..PowerOn::default() });
+        run_frames(&mut machine, 2);
+        assert!((two_frames..two_frames + 2).contains(&machine.cpu().cycles), "{:?}", region);
+    }
+
+    let mut machine = easy6502("loop: jmp loop", PowerOn::default());
+    run_frames(&mut machine, 1);
+    assert_eq!(machine.cpu().cycles, 7 + 3 * easy6502::STEPS_PER_FRAME as u64);
+}
+
+#[test]
+fn test_states_carry_on_the_random_numbers() {
+    let power = PowerOn { seed: 5, ram: RamInit::Random, ..PowerOn::default() };
+    let mut machine = easy6502("loop: lda $fe\nsta $10\njmp loop", power);
+    for _ in 0..100 {
+        machine.step().unwrap();
+    }
+    let state = machine.save_state();
+
+    let mut loaded = easy6502("loop: lda $fe\nsta $10\njmp loop", PowerOn::default());
+    loaded.load_state(&state).unwrap();
+    assert_eq!(loaded.seed(), 5);
+    for _ in 0..100 {
+        machine.step().unwrap();
+        loaded.step().unwrap();
+        assert_eq!(loaded.cpu().peek(0x10), machine.cpu().peek(0x10));
+    }
+}
+
+#[test]
+fn test_states_only_load_into_the_same_program() {
+    let state = easy6502("lda #$01\nbrk", PowerOn::default()).save_state();
+    assert!(easy6502("lda #$01\nbrk", PowerOn::default()).load_state(&state).is_ok());
+    let error = easy6502("lda #$02\nbrk", PowerOn::default()).load_state(&state).unwrap_err();
+    assert_eq!(error, "save state was made with a different program");
+    assert!(nes(PowerOn::default()).load_state(&state).is_err());
+}
+
+#[test]
+fn test_power_cycles_load_past_the_hooks() {
+    let mut machine = easy6502("lda #$01\nsta $10\nbrk", PowerOn::default());
+    let writes = Rc::new(Cell::new(0));
+    let seen = writes.clone();
+    machine.cpu_mut().bus.hooks.add(Access::WRITE, 0x0000..=0xffff, move |_, _, _| {
+        seen.set(seen.get() + 1);
+        None
+    });
+    machine.power_cycle();
+    assert_eq!(writes.get(), 0);
+    while machine.step().unwrap() {}
+    assert_eq!(writes.get(), 1);
+}
@@ -1,134 +1,197 @@
-mod cpu;
 
-use crate::cpu::CPU;
+use std::time::Instant;
+
+use nes::cli::{self, Args, MachineKind};
+use nes::easy6502;
+use nes::joypad::{Buttons, Turbo};
+use nes::launch::Launch;
+use nes::machine::Machine;
+use nes::rewind::Rewind;
+use nes::savestate::Slots;
+use nes::scheduler::Scheduler;
+use nes::session::Session;
 
 use sdl2::event::Event;
 use sdl2::EventPump;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::keyboard::{KeyboardState, Keycode, Scancode};
 use sdl2::pixels::PixelFormatEnum;
-use rand::Rng;
 
-#[warn(unreachable_code)]
-fn handle_user_input(cpu: &mut CPU, event_pump: &mut EventPump) {
+// returns false once the user asked to quit
+fn handle_user_input(machine: &mut Machine, event_pump: &mut EventPump, scheduler: &mut Scheduler, slots: &mut Slots, rewind: &mut Rewind, session: &mut Session) -> bool {
     for event in event_pump.poll_iter() {
         match event {
             Event::Quit {..} | Event::KeyDown {
-                keycode: Some(Keycode::Escape), ..} => std::process::exit(0),
-            Event::KeyDown { keycode: Some(Keycode::W), .. } | Event::KeyDown { keycode: Some(Keycode::Up), ..} => {
-                cpu.mem_write(0xff, 0x77);
-                // println!("{:?}", event);
+                keycode: Some(Keycode::Escape), ..} => return false,
+            Event::KeyDown { keycode: Some(Keycode::F1), .. } | Event::KeyDown { keycode: Some(Keycode::Pause), .. } => {
+                scheduler.toggle_pause();
+            }
+            Event::KeyDown { keycode: Some(Keycode::F2), .. } => scheduler.advance(),
+            Event::KeyDown { keycode: Some(Keycode::F3), .. } => {
+                scheduler.speed = scheduler.speed.slower();
+                println!("speed {}", scheduler.speed);
+            }
+            Event::KeyDown { keycode: Some(Keycode::F4), .. } => {
+                scheduler.speed = scheduler.speed.faster();
+                println!("speed {}", scheduler.speed);
+            }
+            Event::KeyDown { keycode: Some(Keycode::F5), .. } => {
+                match slots.save(machine) {
+                    Ok(()) => println!("saved state to slot {}", slots.current),
+                    Err(e) => eprintln!("{}", e),
+                }
             }
-            Event::KeyDown { keycode: Some(Keycode::S), .. } | Event::KeyDown { keycode: Some(Keycode::Down), ..} => {
-                cpu.mem_write(0xff, 0x73);
-                // println!("{:?}", event);
+            Event::KeyDown { keycode: Some(Keycode::F6), .. } => {
+                slots.current = (slots.current + 1) % 10;
+                println!("selected slot {}", slots.current);
             }
-            Event::KeyDown { keycode: Some(Keycode::A), .. } | Event::KeyDown { keycode: Some(Keycode::Left), ..} => {
-                cpu.mem_write(0xff, 0x61);
-                // println!("{:?}", event);
+            Event::KeyDown { keycode: Some(Keycode::F7), .. } if session.movie_active() => {
+                eprintln!("can't load a state while a movie is playing or recording");
             }
-            Event::KeyDown { keycode: Some(Keycode::D), .. } | Event::KeyDown { keycode: Some(Keycode::Right), ..} => {
-                cpu.mem_write(0xff, 0x64);
-                // println!("{:?}", event);
+            Event::KeyDown { keycode: Some(Keycode::Backspace), repeat: false, .. } if session.movie_active() => {
+                eprintln!("can't rewind while a movie is playing or recording");
             }
-            _ => { /* do nothing */ }
+            Event::KeyDown { keycode: Some(Keycode::F7), .. } => {
+                match slots.load(machine) {
+                    Ok(()) => {
+                        rewind.clear();
+                        println!("loaded state from slot {}", slots.current);
+                    }
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::F8), .. } if session.tracer.has_log() => {
+                let tracer = &mut session.tracer;
+                tracer.enabled = !tracer.enabled;
+                println!("tracing {}", if tracer.enabled { "on" } else { "off" });
+            }
+            // Easy6502 stores the character typed, so shift and the keyboard layout count
+            Event::TextInput { text, .. } => {
+                for key in text.bytes().filter(u8::is_ascii) {
+                    machine.press_key(key);
+                }
+            }
+            Event::KeyDown { keycode: Some(Keycode::Return), .. } => machine.press_key(0x0d),
+            _ => {}
         }
     }
+    true
 }
 
-fn color(byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
-    }
+// the first joypad, the second has no keys
+fn held_buttons(keyboard: &KeyboardState, turbo: &mut Turbo) -> [Buttons; 2] {
+    let keys = [
+        (Scancode::Up, Buttons::UP),
+        (Scancode::Down, Buttons::DOWN),
+        (Scancode::Left, Buttons::LEFT),
+        (Scancode::Right, Buttons::RIGHT),
+        (Scancode::X, Buttons::A),
+        (Scancode::Z, Buttons::B),
+        (Scancode::Return, Buttons::START),
+        (Scancode::RShift, Buttons::SELECT),
+    ];
+    let turbo_keys = [(Scancode::S, Buttons::A), (Scancode::A, Buttons::B)];
+    let pressed = |keys: &[(Scancode, Buttons)]| {
+        keys.iter().filter(|(key, _)| keyboard.is_scancode_pressed(*key)).fold(Buttons::empty(), |held, (_, button)| held | *button)
+    };
+    [turbo.apply(pressed(&keys), pressed(&turbo_keys)), Buttons::empty()]
 }
 
-fn read_screen_state(cpu: &CPU, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        // transform the CPU screen map into 3 bytes
-        let color_idx = cpu.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
-        }
-        frame_idx += 3;
-    }
-    update
+fn exit_with_usage(error: String) -> ! {
+    eprintln!("{}\n\n{}", error, cli::USAGE);
+    std::process::exit(2);
 }
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args = Args::parse(&args).unwrap_or_else(|e| exit_with_usage(e));
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    let mut launch = Launch::from_args(&args).unwrap_or_else(|e| exit_with_usage(e));
+    if let Some(status) = launch.run_windowless(&args) {
+        std::process::exit(status);
+    }
+    let Launch { mut machine, mut slots, mut battery } = launch;
+    let mut session = Session::from_args(&args, &mut machine).unwrap_or_else(|e| exit_with_usage(e));
+    if machine.kind() == MachineKind::Nes {
+        eprintln!("the NES machine has no PPU yet, the window will stay blank");
+    }
+
     // init sdl2
+    let scale = args.scale;
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
-        .position_centered()
-        .build().unwrap();
+    let mut window = video_subsystem.window("Snake game", 32 * scale, 32 * scale);
+    window.position_centered();
+    if args.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = window.build().unwrap();
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
+    canvas.set_scale(scale as f32, scale as f32).unwrap();
 
     // create a texture
     let creator = canvas.texture_creator();
     let mut texture = creator
         .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
 
+    let mut screen_state = [0u8; easy6502::FRAME_SIZE];
+    let mut rewind = Rewind::new(args.rewind_interval, args.rewind_budget);
 
-    // let game_code: Vec<u8> = vec![0xa9, 0x01, 0x8d, 0x00, 0x02, 0xa9, 0x05, 0x8d, 0x01, 0x02, 0xa9, 0x08, 0x8d, 0x02, 0x02];
-    let game_code: Vec<u8> = vec![
-        0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
-        0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
-        0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
-        0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
-        0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
-        0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
-        0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
-        0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
-        0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
-        0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
-        0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
-        0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
-        0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
-        0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
-        0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
-        0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
-        0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
-        0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
-        0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
-        0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
-        0x60, 0xa2, 0x00, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
-    ];
-
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
-
-    // load the game
-    let mut cpu = CPU::new();
-    cpu.load(game_code);
-    cpu.reset();
-    cpu.run_with_callbacks(move |cpu| {
-        handle_user_input(cpu, &mut event_pump);
-        cpu.mem_write(0xfe, rng.gen_range(1, 16));
-        if read_screen_state(cpu, &mut screen_state) {
+    let mut scheduler = Scheduler::new(args.region.frame_rate(), args.paused);
+    let mut turbo = Turbo::default();
+    'running: loop {
+        if !handle_user_input(&mut machine, &mut event_pump, &mut scheduler, &mut slots, &mut rewind, &mut session) {
+            break;
+        }
+        let keyboard = event_pump.keyboard_state();
+        scheduler.fast_forward = keyboard.is_scancode_pressed(Scancode::Tab);
+        let rewinding = args.rewind_budget > 0 && !session.movie_active() && keyboard.is_scancode_pressed(Scancode::Backspace);
+        scheduler.begin(Instant::now());
+        while scheduler.due(Instant::now()) {
+            // paced like running: one snapshot back per frame due
+            if rewinding {
+                rewind.step_back(&mut machine);
+                continue;
+            }
+            let held = held_buttons(&keyboard, &mut turbo);
+            session.latch(&mut machine, held);
+            machine.begin_frame();
+            while !machine.frame_done() {
+                match session.step(&mut machine) {
+                    Ok(true) => {}
+                    Ok(false) => break 'running,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        break 'running;
+                    }
+                }
+            }
+            if args.rewind_budget > 0 {
+                rewind.push_frame(&machine);
+            }
+            if let Some(battery) = battery.as_mut() {
+                battery.tick(machine.cpu_mut()).unwrap_or_else(|e| eprintln!("{}", e));
+            }
+        }
+        if machine.render(&mut screen_state) {
             texture.update(None, &screen_state, 32 * 3).unwrap();
             canvas.copy(&texture, None, None).unwrap();
             canvas.present();
         }
 
-        // ::std::thread::sleep(std::time::Duration::new(0, 500_000_000));
-        ::std::thread::sleep(std::time::Duration::new(0, 70_000));
-    });
-    println!("Hello, world!");
+        let now = Instant::now();
+        let wake = scheduler.wake_at(now);
+        if wake > now {
+            ::std::thread::sleep(wake - now);
+        }
+    }
+
+    if let Some(battery) = battery.as_mut() {
+        battery.flush(machine.cpu_mut()).unwrap_or_else(|e| eprintln!("{}", e));
+    }
+    session.finish(&args, &machine);
 }
@@ -0,0 +1,295 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use crate::cli::Args;
+use crate::joypad::Buttons;
+use crate::machine::{Machine, RamInit};
+use crate::region::Region;
+
+#[cfg(test)]
+mod movie_test;
+
+// the frame commands this emulator acts on
+pub const RESET: u8 = 1;
+pub const POWER: u8 = 2;
+
+// the button letters of an FM2 input field, highest bit first
+const BUTTON_LETTERS: &[u8; 8] = b"RLDUTSBA";
+
+// the FCEUX version written to exported movies; older ones refuse to load them
+const EMU_VERSION: u32 = 22020;
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Frame {
+    // RESET and POWER, run before the buttons are latched
+    pub commands: u8,
+    pub buttons: [Buttons; 2],
+}
+
+// The joypad input of a run, one entry per frame, and what it started from.
+// Read and written as FCEUX FM2 text.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Movie {
+    pub rom_filename: String,
+    pub pal: bool,
+    pub rerecords: u64,
+    // how the machine powered on, kept as FM2's RAMInitSeed and RAMInitOption
+    pub seed: u64,
+    pub ram: RamInit,
+    // the save state it starts from; without one it starts at power on
+    pub start_state: Option<Vec<u8>>,
+    // header lines this emulator doesn't use, written back on export
+    pub extra: Vec<(String, String)>,
+    pub frames: Vec<Frame>,
+}
+
+impl Movie {
+    pub fn new() -> Movie {
+        Movie::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Movie, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Movie::parse_fm2(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_fm2()).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+
+    // text FM2 with gamepads or nothing in the two ports
+    pub fn parse_fm2(text: &str) -> Result<Movie, String> {
+        let mut movie = Movie::new();
+        let mut ports = [true, true];
+        for (lineno, line) in text.lines().enumerate() {
+            let error = |e: String| format!("line {}: {}", lineno + 1, e);
+            let line = line.trim_end();
+            if line.starts_with('|') {
+                movie.frames.push(parse_frame(line, ports).map_err(error)?);
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match key {
+                "version" if value != "3" => return Err(error(format!("unknown FM2 version {}", value))),
+                "binary" if value != "0" => return Err(error("binary FM2 input isn't supported".to_string())),
+                "fourscore" if value != "0" => return Err(error("Four Score movies aren't supported".to_string())),
+                "savestate" => return Err(error("the movie starts from an FCEUX save state".to_string())),
+                "port0" | "port1" => {
+                    ports[(key == "port1") as usize] = match value {
+                        "0" => false,
+                        "1" => true,
+                        _ => return Err(error(format!("{} isn't a gamepad", key))),
+                    }
+                }
+                "port2" if value != "0" => return Err(error("expansion port devices aren't supported".to_string())),
+                "palFlag" => movie.pal = value == "1",
+                "romFilename" => movie.rom_filename = value.to_string(),
+                "rerecordCount" => movie.rerecords = value.parse().map_err(|_| error(format!("invalid {}", key)))?,
+                "RAMInitSeed" => movie.seed = value.parse().map_err(|_| error(format!("invalid {}", key)))?,
+                "RAMInitOption" => {
+                    let option = value.parse().ok().and_then(RamInit::from_fm2);
+                    movie.ram = option.ok_or_else(|| error(format!("invalid {}", key)))?;
+                }
+                "startState" => {
+                    let data = value.strip_prefix("base64:").ok_or_else(|| error(format!("invalid {}", key)))?;
+                    movie.start_state = Some(from_base64(data).map_err(error)?);
+                }
+                // written from what the movie holds
+                "version" | "binary" | "fourscore" | "port2" | "emuVersion" | "length" => {}
+                _ => movie.extra.push((key.to_string(), value.to_string())),
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_fm2(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "version 3\nemuVersion {}\nrerecordCount {}", EMU_VERSION, self.rerecords);
+        let _ = writeln!(out, "palFlag {}\nromFilename {}", self.pal as u8, self.rom_filename);
+        let _ = writeln!(out, "RAMInitOption {}\nRAMInitSeed {}", self.ram.fm2(), self.seed);
+        let _ = writeln!(out, "fourscore 0\nport0 1\nport1 1\nport2 0");
+        if let Some(state) = &self.start_state {
+            let _ = writeln!(out, "startState base64:{}", to_base64(state));
+        }
+        for (key, value) in &self.extra {
+            let _ = writeln!(out, "{} {}", key, value);
+        }
+        for frame in &self.frames {
+            let _ = writeln!(out, "|{}|{}|{}||", frame.commands, buttons_field(frame.buttons[0]), buttons_field(frame.buttons[1]));
+        }
+        out
+    }
+
+    // Puts the machine where the movie starts: the start state, or as
+    // loaded, which should have been powered on with the movie's seed and RAM
+    // pattern. A start state brings its own random numbers.
+    pub fn start(&self, machine: &mut Machine) -> Result<(), String> {
+        machine.reseed(self.seed);
+        if let Some(state) = &self.start_state {
+            machine.load_state(state)?;
+        }
+        Ok(())
+    }
+
+    // runs frame `index`; past the end nothing is held
+    pub fn play(&self, index: usize, machine: &mut Machine) {
+        apply(&self.frames.get(index).copied().unwrap_or_default(), machine);
+    }
+}
+
+fn apply(frame: &Frame, machine: &mut Machine) {
+    if frame.commands & POWER != 0 {
+        machine.power_cycle();
+    } else if frame.commands & RESET != 0 {
+        machine.cpu_mut().soft_reset();
+    }
+    machine.latch(frame.buttons);
+}
+
+// `|commands|port0|port1|port2|`, a port's field empty if nothing is plugged in
+fn parse_frame(line: &str, ports: [bool; 2]) -> Result<Frame, String> {
+    let mut fields = line.split('|').skip(1);
+    let commands = fields.next().unwrap_or("");
+    let commands = commands.trim().parse().map_err(|_| format!("invalid commands: {}", commands))?;
+    let mut frame = Frame { commands, buttons: [Buttons::empty(); 2] };
+    for (port, plugged) in ports.into_iter().enumerate() {
+        let field = fields.next().ok_or("missing input field")?;
+        if plugged {
+            frame.buttons[port] = parse_buttons(field)?;
+        }
+    }
+    Ok(frame)
+}
+
+// `RLDUTSBA`, a `.` or space for a button that isn't held
+fn parse_buttons(field: &str) -> Result<Buttons, String> {
+    if field.len() != BUTTON_LETTERS.len() {
+        return Err(format!("invalid gamepad input: {:?}", field));
+    }
+    let bits = field.bytes().enumerate().fold(0, |bits, (i, c)| match c {
+        b'.' | b' ' => bits,
+        _ => bits | 0x80 >> i,
+    });
+    Ok(Buttons::from_bits_truncate(bits))
+}
+
+fn buttons_field(buttons: Buttons) -> String {
+    BUTTON_LETTERS
+        .iter()
+        .enumerate()
+        .map(|(i, &letter)| if buttons.bits() & 0x80 >> i != 0 { letter as char } else { '.' })
+        .collect()
+}
+
+fn to_base64(data: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn from_base64(text: &str) -> Result<Vec<u8>, String> {
+    let error = || "invalid base64".to_string();
+    let digits: Vec<u32> = text
+        .trim_end_matches('=')
+        .bytes()
+        .map(|c| BASE64.iter().position(|&b| b == c).map(|n| n as u32).ok_or_else(error))
+        .collect::<Result<_, _>>()?;
+    if digits.len() % 4 == 1 {
+        return Err(error());
+    }
+    let mut out = Vec::new();
+    for chunk in digits.chunks(4) {
+        let bits = chunk.iter().enumerate().fold(0, |bits, (i, digit)| bits | digit << (18 - 6 * i));
+        out.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Ok(out)
+}
+
+// Plays a movie back from `--movie`, or records one for `--record`, a frame
+// at a time.
+pub struct MovieController {
+    pub movie: Movie,
+    // where a recording goes, None when playing back
+    recording: Option<String>,
+    frame: usize,
+}
+
+impl MovieController {
+    // A recording starts from the machine as it is, so from a save state if
+    // `--load-slot` loaded one, and keeps how it powered on.
+    pub fn from_args(args: &Args, machine: &mut Machine) -> Result<Option<MovieController>, String> {
+        match (&args.movie, &args.record) {
+            (Some(_), Some(_)) => Err("--movie and --record can't be used together".to_string()),
+            (Some(path), None) => {
+                let movie = Movie::load(Path::new(path))?;
+                if movie.start_state.is_none() && args.load_slot.is_some() {
+                    return Err(format!("{} starts at power on, not from --load-slot", path));
+                }
+                movie.start(machine)?;
+                Ok(Some(MovieController { movie, recording: None, frame: 0 }))
+            }
+            (None, Some(path)) => {
+                let movie = Movie {
+                    rom_filename: args.program.as_deref().map(file_stem).unwrap_or("snake").to_string(),
+                    pal: args.region == Region::Pal,
+                    seed: machine.seed(),
+                    ram: machine.ram_init(),
+                    start_state: args.load_slot.map(|_| machine.save_state()),
+                    ..Movie::new()
+                };
+                movie.start(machine)?;
+                Ok(Some(MovieController { movie, recording: Some(path.clone()), frame: 0 }))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    // At the start of each frame, with the buttons the player holds: a
+    // recording latches and keeps them, playback latches the movie's instead
+    // until it runs out.
+    pub fn frame(&mut self, machine: &mut Machine, held: [Buttons; 2]) {
+        if self.recording.is_some() {
+            let frame = Frame { commands: 0, buttons: held };
+            self.movie.frames.push(frame);
+            apply(&frame, machine);
+        } else if self.finished() {
+            machine.latch(held);
+        } else {
+            self.movie.play(self.frame, machine);
+        }
+        self.frame += 1;
+    }
+
+    // a movie playing back has no more frames
+    pub fn finished(&self) -> bool {
+        self.recording.is_none() && self.frame >= self.movie.frames.len()
+    }
+
+    // writes a recording
+    pub fn save(&self) -> Result<(), String> {
+        match &self.recording {
+            Some(path) => self.movie.save(Path::new(path)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn file_stem(path: &str) -> &str {
+    Path::new(path).file_stem().and_then(|stem| stem.to_str()).unwrap_or(path)
+}
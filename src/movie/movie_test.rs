@@ -0,0 +1,148 @@
+use super::*;
+use crate::asm::assemble;
+use crate::easy6502::KEY_PORT;
+use crate::loader::Program;
+use crate::machine::PowerOn;
+
+const FCEUX_MOVIE: &str = "version 3
+emuVersion 22020
+rerecordCount 12
+palFlag 0
+romFilename smb
+romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==
+guid 452DE2C3-EF43-2FA9-77AC-0677FC51543B
+fourscore 0
+microphone 0
+port0 1
+port1 0
+port2 0
+FDS 0
+NewPPU 0
+comment author someone
+|2|........|||
+|0|R...T..A|||
+|1|.L  ..B.|||
+";
+
+// mixes the random byte into $00 and copies the key to $01, forever
+const PROGRAM: &str = "
+    loop:
+        lda $fe
+        eor $00
+        sta $00
+        lda $ff
+        sta $01
+        jmp loop
+";
+
+fn machine() -> Machine {
+    machine_with(PowerOn::default())
+}
+
+fn machine_with(power: PowerOn) -> Machine {
+    Machine::easy6502_with(&Program::at(0x0600, assemble(PROGRAM, 0x0600).unwrap()), power).unwrap()
+}
+
+fn run_frame(machine: &mut Machine) {
+    machine.begin_frame();
+    while !machine.frame_done() {
+        assert!(machine.step().unwrap());
+    }
+}
+
+#[test]
+fn test_fceux_import_and_export() {
+    let movie = Movie::parse_fm2(FCEUX_MOVIE).unwrap();
+    assert_eq!(movie.rerecords, 12);
+    assert_eq!(movie.rom_filename, "smb");
+    let frames = [
+        Frame { commands: POWER, buttons: [Buttons::empty(); 2] },
+        Frame { commands: 0, buttons: [Buttons::RIGHT | Buttons::START | Buttons::A, Buttons::empty()] },
+        Frame { commands: RESET, buttons: [Buttons::LEFT | Buttons::B, Buttons::empty()] },
+    ];
+    assert_eq!(movie.frames, frames);
+    assert_eq!(movie.extra[0], ("romChecksum".to_string(), "base64:jjYwGG411HcjG/j9UOVM3Q==".to_string()));
+    assert_eq!(movie.extra.last().unwrap(), &("comment".to_string(), "author someone".to_string()));
+
+    let fm2 = movie.to_fm2();
+    assert!(fm2.contains("\nguid 452DE2C3-EF43-2FA9-77AC-0677FC51543B\n"));
+    assert!(fm2.ends_with("|2|........|........||\n|0|R...T..A|........||\n|1|.L....B.|........||\n"));
+    assert_eq!(Movie::parse_fm2(&fm2).unwrap(), movie);
+}
+
+#[test]
+fn test_unsupported_movies() {
+    let error = |header: &str| Movie::parse_fm2(&format!("version 3\n{}\n|0|........|||\n", header)).unwrap_err();
+    assert_eq!(error("binary 1"), "line 2: binary FM2 input isn't supported");
+    assert_eq!(error("port0 2"), "line 2: port0 isn't a gamepad");
+    assert_eq!(error("savestate base64:AAAA"), "line 2: the movie starts from an FCEUX save state");
+    assert_eq!(Movie::parse_fm2("|0|..x|\n").unwrap_err(), "line 1: invalid gamepad input: \"..x\"");
+}
+
+#[test]
+fn test_start_state_and_base64() {
+    for (data, text) in [(&b""[..], ""), (b"M", "TQ=="), (b"Ma", "TWE="), (b"Man", "TWFu"), (b"Many", "TWFueQ==")] {
+        assert_eq!(to_base64(data), text);
+        assert_eq!(from_base64(text).unwrap(), data);
+    }
+    assert!(from_base64("T").is_err());
+
+    let mut machine = machine();
+    machine.cpu_mut().mem_write(0x10, 0x42);
+    let movie = Movie { start_state: Some(machine.save_state()), ..Movie::new() };
+    let movie = Movie::parse_fm2(&movie.to_fm2()).unwrap();
+    let mut other = self::machine();
+    movie.start(&mut other).unwrap();
+    assert_eq!(other.cpu().mem_read(0x10), 0x42);
+}
+
+#[test]
+fn test_playback_matches_the_recording() {
+    let args = Args { record: Some("unused.fm2".to_string()), ..Args::default() };
+    let power = PowerOn { seed: 7, ram: RamInit::Random, ..PowerOn::default() };
+    let mut machine = machine_with(power);
+    let mut recorder = MovieController::from_args(&args, &mut machine).unwrap().unwrap();
+    let input = [Buttons::empty(), Buttons::RIGHT, Buttons::RIGHT | Buttons::UP, Buttons::empty(), Buttons::A];
+    for buttons in input {
+        recorder.frame(&mut machine, [buttons, Buttons::empty()]);
+        run_frame(&mut machine);
+    }
+    assert_eq!(machine.cpu().mem_read(0x01), b'x');
+    let movie = Movie::parse_fm2(&recorder.movie.to_fm2()).unwrap();
+    assert_eq!((movie.seed, movie.ram), (7, RamInit::Random));
+    assert_eq!(movie.frames.len(), input.len());
+
+    let mut replay = machine_with(PowerOn { seed: movie.seed, ram: movie.ram, ..PowerOn::default() });
+    movie.start(&mut replay).unwrap();
+    for frame in 0..input.len() {
+        movie.play(frame, &mut replay);
+        run_frame(&mut replay);
+        // the key stays where the frame's button put it
+        if frame == 1 {
+            assert_eq!(replay.cpu().peek(KEY_PORT), b'd');
+        }
+    }
+    for addr in 0..0x600 {
+        assert_eq!(replay.cpu().peek(addr), machine.cpu().peek(addr));
+    }
+    assert_eq!(replay.cpu().mem_read(0x01), b'x');
+}
+
+#[test]
+fn test_power_frames_power_cycle() {
+    let power = PowerOn { seed: 3, ram: RamInit::Random, ..PowerOn::default() };
+    let mut machine = machine_with(power);
+    let fresh = machine_with(power);
+    for _ in 0..3 {
+        run_frame(&mut machine);
+    }
+    machine.latch([Buttons::A, Buttons::empty()]);
+    let movie = Movie::parse_fm2("|2|........|........||\n").unwrap();
+    movie.play(0, &mut machine);
+    for addr in 0..=0xffff {
+        assert_eq!(machine.cpu().peek(addr), fresh.cpu().peek(addr));
+    }
+    assert_eq!(machine.cpu().program_counter, 0x0600);
+    assert_eq!(machine.cpu().register_a, fresh.cpu().register_a);
+    assert_eq!(machine.cpu().cycles, fresh.cpu().cycles);
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use lazy_static::lazy_static;
+
+// Addressing modes as written in assembly, finer grained than the CPU's
+// `AddressingMode` which folds branches into Immediate and has no Implied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Implied,
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+}
+
+impl Mode {
+    // instruction length in bytes, opcode included
+    pub fn size(&self) -> u8 {
+        match self {
+            Mode::Implied | Mode::Accumulator => 1,
+            Mode::Immediate
+            | Mode::ZeroPage
+            | Mode::ZeroPageX
+            | Mode::ZeroPageY
+            | Mode::IndirectX
+            | Mode::IndirectY
+            | Mode::Relative => 2,
+            Mode::Absolute | Mode::AbsoluteX | Mode::AbsoluteY | Mode::Indirect => 3,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OpInfo {
+    pub code: u8,
+    pub mnemonic: &'static str,
+    pub mode: Mode,
+    // base cycles, without page crossing and taken branch penalties
+    pub cycles: u8,
+}
+
+impl OpInfo {
+    const fn new(code: u8, mnemonic: &'static str, mode: Mode, cycles: u8) -> Self {
+        OpInfo {
+            code,
+            mnemonic,
+            mode,
+            cycles,
+        }
+    }
+
+    pub fn size(&self) -> u8 {
+        self.mode.size()
+    }
+}
+
+// the 151 documented opcodes
+pub static OPCODES: &[OpInfo] = &[
+    // ADC
+    OpInfo::new(0x69, "ADC", Mode::Immediate, 2),
+    OpInfo::new(0x65, "ADC", Mode::ZeroPage, 3),
+    OpInfo::new(0x75, "ADC", Mode::ZeroPageX, 4),
+    OpInfo::new(0x6d, "ADC", Mode::Absolute, 4),
+    OpInfo::new(0x7d, "ADC", Mode::AbsoluteX, 4),
+    OpInfo::new(0x79, "ADC", Mode::AbsoluteY, 4),
+    OpInfo::new(0x61, "ADC", Mode::IndirectX, 6),
+    OpInfo::new(0x71, "ADC", Mode::IndirectY, 5),
+    // AND
+    OpInfo::new(0x29, "AND", Mode::Immediate, 2),
+    OpInfo::new(0x25, "AND", Mode::ZeroPage, 3),
+    OpInfo::new(0x35, "AND", Mode::ZeroPageX, 4),
+    OpInfo::new(0x2d, "AND", Mode::Absolute, 4),
+    OpInfo::new(0x3d, "AND", Mode::AbsoluteX, 4),
+    OpInfo::new(0x39, "AND", Mode::AbsoluteY, 4),
+    OpInfo::new(0x21, "AND", Mode::IndirectX, 6),
+    OpInfo::new(0x31, "AND", Mode::IndirectY, 5),
+    // ASL
+    OpInfo::new(0x0a, "ASL", Mode::Accumulator, 2),
+    OpInfo::new(0x06, "ASL", Mode::ZeroPage, 5),
+    OpInfo::new(0x16, "ASL", Mode::ZeroPageX, 6),
+    OpInfo::new(0x0e, "ASL", Mode::Absolute, 6),
+    OpInfo::new(0x1e, "ASL", Mode::AbsoluteX, 7),
+    // BCC
+    OpInfo::new(0x90, "BCC", Mode::Relative, 2),
+    // BCS
+    OpInfo::new(0xb0, "BCS", Mode::Relative, 2),
+    // BEQ
+    OpInfo::new(0xf0, "BEQ", Mode::Relative, 2),
+    // BIT
+    OpInfo::new(0x24, "BIT", Mode::ZeroPage, 3),
+    OpInfo::new(0x2c, "BIT", Mode::Absolute, 4),
+    // BMI
+    OpInfo::new(0x30, "BMI", Mode::Relative, 2),
+    // BNE
+    OpInfo::new(0xd0, "BNE", Mode::Relative, 2),
+    // BPL
+    OpInfo::new(0x10, "BPL", Mode::Relative, 2),
+    // BRK
+    OpInfo::new(0x00, "BRK", Mode::Implied, 7),
+    // BVC
+    OpInfo::new(0x50, "BVC", Mode::Relative, 2),
+    // BVS
+    OpInfo::new(0x70, "BVS", Mode::Relative, 2),
+    // CLC
+    OpInfo::new(0x18, "CLC", Mode::Implied, 2),
+    // CLD
+    OpInfo::new(0xd8, "CLD", Mode::Implied, 2),
+    // CLI
+    OpInfo::new(0x58, "CLI", Mode::Implied, 2),
+    // CLV
+    OpInfo::new(0xb8, "CLV", Mode::Implied, 2),
+    // CMP
+    OpInfo::new(0xc9, "CMP", Mode::Immediate, 2),
+    OpInfo::new(0xc5, "CMP", Mode::ZeroPage, 3),
+    OpInfo::new(0xd5, "CMP", Mode::ZeroPageX, 4),
+    OpInfo::new(0xcd, "CMP", Mode::Absolute, 4),
+    OpInfo::new(0xdd, "CMP", Mode::AbsoluteX, 4),
+    OpInfo::new(0xd9, "CMP", Mode::AbsoluteY, 4),
+    OpInfo::new(0xc1, "CMP", Mode::IndirectX, 6),
+    OpInfo::new(0xd1, "CMP", Mode::IndirectY, 5),
+    // CPX
+    OpInfo::new(0xe0, "CPX", Mode::Immediate, 2),
+    OpInfo::new(0xe4, "CPX", Mode::ZeroPage, 3),
+    OpInfo::new(0xec, "CPX", Mode::Absolute, 4),
+    // CPY
+    OpInfo::new(0xc0, "CPY", Mode::Immediate, 2),
+    OpInfo::new(0xc4, "CPY", Mode::ZeroPage, 3),
+    OpInfo::new(0xcc, "CPY", Mode::Absolute, 4),
+    // DEC
+    OpInfo::new(0xc6, "DEC", Mode::ZeroPage, 5),
+    OpInfo::new(0xd6, "DEC", Mode::ZeroPageX, 6),
+    OpInfo::new(0xce, "DEC", Mode::Absolute, 6),
+    OpInfo::new(0xde, "DEC", Mode::AbsoluteX, 7),
+    // DEX
+    OpInfo::new(0xca, "DEX", Mode::Implied, 2),
+    // DEY
+    OpInfo::new(0x88, "DEY", Mode::Implied, 2),
+    // EOR
+    OpInfo::new(0x49, "EOR", Mode::Immediate, 2),
+    OpInfo::new(0x45, "EOR", Mode::ZeroPage, 3),
+    OpInfo::new(0x55, "EOR", Mode::ZeroPageX, 4),
+    OpInfo::new(0x4d, "EOR", Mode::Absolute, 4),
+    OpInfo::new(0x5d, "EOR", Mode::AbsoluteX, 4),
+    OpInfo::new(0x59, "EOR", Mode::AbsoluteY, 4),
+    OpInfo::new(0x41, "EOR", Mode::IndirectX, 6),
+    OpInfo::new(0x51, "EOR", Mode::IndirectY, 5),
+    // INC
+    OpInfo::new(0xe6, "INC", Mode::ZeroPage, 5),
+    OpInfo::new(0xf6, "INC", Mode::ZeroPageX, 6),
+    OpInfo::new(0xee, "INC", Mode::Absolute, 6),
+    OpInfo::new(0xfe, "INC", Mode::AbsoluteX, 7),
+    // INX
+    OpInfo::new(0xe8, "INX", Mode::Implied, 2),
+    // INY
+    OpInfo::new(0xc8, "INY", Mode::Implied, 2),
+    // JMP
+    OpInfo::new(0x4c, "JMP", Mode::Absolute, 3),
+    OpInfo::new(0x6c, "JMP", Mode::Indirect, 5),
+    // JSR
+    OpInfo::new(0x20, "JSR", Mode::Absolute, 6),
+    // LDA
+    OpInfo::new(0xa9, "LDA", Mode::Immediate, 2),
+    OpInfo::new(0xa5, "LDA", Mode::ZeroPage, 3),
+    OpInfo::new(0xb5, "LDA", Mode::ZeroPageX, 4),
+    OpInfo::new(0xad, "LDA", Mode::Absolute, 4),
+    OpInfo::new(0xbd, "LDA", Mode::AbsoluteX, 4),
+    OpInfo::new(0xb9, "LDA", Mode::AbsoluteY, 4),
+    OpInfo::new(0xa1, "LDA", Mode::IndirectX, 6),
+    OpInfo::new(0xb1, "LDA", Mode::IndirectY, 5),
+    // LDX
+    OpInfo::new(0xa2, "LDX", Mode::Immediate, 2),
+    OpInfo::new(0xa6, "LDX", Mode::ZeroPage, 3),
+    OpInfo::new(0xb6, "LDX", Mode::ZeroPageY, 4),
+    OpInfo::new(0xae, "LDX", Mode::Absolute, 4),
+    OpInfo::new(0xbe, "LDX", Mode::AbsoluteY, 4),
+    // LDY
+    OpInfo::new(0xa0, "LDY", Mode::Immediate, 2),
+    OpInfo::new(0xa4, "LDY", Mode::ZeroPage, 3),
+    OpInfo::new(0xb4, "LDY", Mode::ZeroPageX, 4),
+    OpInfo::new(0xac, "LDY", Mode::Absolute, 4),
+    OpInfo::new(0xbc, "LDY", Mode::AbsoluteX, 4),
+    // LSR
+    OpInfo::new(0x4a, "LSR", Mode::Accumulator, 2),
+    OpInfo::new(0x46, "LSR", Mode::ZeroPage, 5),
+    OpInfo::new(0x56, "LSR", Mode::ZeroPageX, 6),
+    OpInfo::new(0x4e, "LSR", Mode::Absolute, 6),
+    OpInfo::new(0x5e, "LSR", Mode::AbsoluteX, 7),
+    // NOP
+    OpInfo::new(0xea, "NOP", Mode::Implied, 2),
+    // ORA
+    OpInfo::new(0x09, "ORA", Mode::Immediate, 2),
+    OpInfo::new(0x05, "ORA", Mode::ZeroPage, 3),
+    OpInfo::new(0x15, "ORA", Mode::ZeroPageX, 4),
+    OpInfo::new(0x0d, "ORA", Mode::Absolute, 4),
+    OpInfo::new(0x1d, "ORA", Mode::AbsoluteX, 4),
+    OpInfo::new(0x19, "ORA", Mode::AbsoluteY, 4),
+    OpInfo::new(0x01, "ORA", Mode::IndirectX, 6),
+    OpInfo::new(0x11, "ORA", Mode::IndirectY, 5),
+    // PHA
+    OpInfo::new(0x48, "PHA", Mode::Implied, 3),
+    // PHP
+    OpInfo::new(0x08, "PHP", Mode::Implied, 3),
+    // PLA
+    OpInfo::new(0x68, "PLA", Mode::Implied, 4),
+    // PLP
+    OpInfo::new(0x28, "PLP", Mode::Implied, 4),
+    // ROL
+    OpInfo::new(0x2a, "ROL", Mode::Accumulator, 2),
+    OpInfo::new(0x26, "ROL", Mode::ZeroPage, 5),
+    OpInfo::new(0x36, "ROL", Mode::ZeroPageX, 6),
+    OpInfo::new(0x2e, "ROL", Mode::Absolute, 6),
+    OpInfo::new(0x3e, "ROL", Mode::AbsoluteX, 7),
+    // ROR
+    OpInfo::new(0x6a, "ROR", Mode::Accumulator, 2),
+    OpInfo::new(0x66, "ROR", Mode::ZeroPage, 5),
+    OpInfo::new(0x76, "ROR", Mode::ZeroPageX, 6),
+    OpInfo::new(0x6e, "ROR", Mode::Absolute, 6),
+    OpInfo::new(0x7e, "ROR", Mode::AbsoluteX, 7),
+    // RTI
+    OpInfo::new(0x40, "RTI", Mode::Implied, 6),
+    // RTS
+    OpInfo::new(0x60, "RTS", Mode::Implied, 6),
+    // SBC
+    OpInfo::new(0xe9, "SBC", Mode::Immediate, 2),
+    OpInfo::new(0xe5, "SBC", Mode::ZeroPage, 3),
+    OpInfo::new(0xf5, "SBC", Mode::ZeroPageX, 4),
+    OpInfo::new(0xed, "SBC", Mode::Absolute, 4),
+    OpInfo::new(0xfd, "SBC", Mode::AbsoluteX, 4),
+    OpInfo::new(0xf9, "SBC", Mode::AbsoluteY, 4),
+    OpInfo::new(0xe1, "SBC", Mode::IndirectX, 6),
+    OpInfo::new(0xf1, "SBC", Mode::IndirectY, 5),
+    // SEC
+    OpInfo::new(0x38, "SEC", Mode::Implied, 2),
+    // SED
+    OpInfo::new(0xf8, "SED", Mode::Implied, 2),
+    // SEI
+    OpInfo::new(0x78, "SEI", Mode::Implied, 2),
+    // STA
+    OpInfo::new(0x85, "STA", Mode::ZeroPage, 3),
+    OpInfo::new(0x95, "STA", Mode::ZeroPageX, 4),
+    OpInfo::new(0x8d, "STA", Mode::Absolute, 4),
+    OpInfo::new(0x9d, "STA", Mode::AbsoluteX, 5),
+    OpInfo::new(0x99, "STA", Mode::AbsoluteY, 5),
+    OpInfo::new(0x81, "STA", Mode::IndirectX, 6),
+    OpInfo::new(0x91, "STA", Mode::IndirectY, 6),
+    // STX
+    OpInfo::new(0x86, "STX", Mode::ZeroPage, 3),
+    OpInfo::new(0x96, "STX", Mode::ZeroPageY, 4),
+    OpInfo::new(0x8e, "STX", Mode::Absolute, 4),
+    // STY
+    OpInfo::new(0x84, "STY", Mode::ZeroPage, 3),
+    OpInfo::new(0x94, "STY", Mode::ZeroPageX, 4),
+    OpInfo::new(0x8c, "STY", Mode::Absolute, 4),
+    // TAX
+    OpInfo::new(0xaa, "TAX", Mode::Implied, 2),
+    // TAY
+    OpInfo::new(0xa8, "TAY", Mode::Implied, 2),
+    // TSX
+    OpInfo::new(0xba, "TSX", Mode::Implied, 2),
+    // TXA
+    OpInfo::new(0x8a, "TXA", Mode::Implied, 2),
+    // TXS
+    OpInfo::new(0x9a, "TXS", Mode::Implied, 2),
+    // TYA
+    OpInfo::new(0x98, "TYA", Mode::Implied, 2),
+];
+
+lazy_static! {
+    pub static ref OPCODES_MAP: HashMap<u8, &'static OpInfo> = {
+        let mut map = HashMap::new();
+        for op in OPCODES {
+            map.insert(op.code, op);
+        }
+        map
+    };
+}
+
+pub fn find(mnemonic: &str, mode: Mode) -> Option<&'static OpInfo> {
+    OPCODES
+        .iter()
+        .find(|op| op.mode == mode && op.mnemonic.eq_ignore_ascii_case(mnemonic))
+}
+
+pub fn is_mnemonic(name: &str) -> bool {
+    OPCODES.iter().any(|op| op.mnemonic.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+
+use crate::cli::Args;
+use crate::cpu::{op, CpuError};
+use crate::machine::Machine;
+
+#[cfg(test)]
+mod profiler_test;
+
+// instructions listed in the report, the routines are all there
+const HOT_INSTRUCTIONS: usize = 50;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counts {
+    pub cycles: u64,
+    pub executed: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Routine {
+    pub calls: u64,
+    // cycles in the routine itself
+    pub exclusive: u64,
+    // cycles until it returned, callees and interrupts included; a recursive
+    // call counts once
+    pub inclusive: u64,
+}
+
+struct Frame {
+    routine: u16,
+    // the stack pointer before the call, back at this value it has returned
+    sp: u8,
+    start: u64,
+}
+
+// Cycles per instruction and per subroutine. Frontends call `step` instead
+// of `Machine::step`. JSR, BRK and interrupts enter a routine named by where
+// they land, RTS and RTI leave every routine the stack pointer has unwound
+// past, so code that pushes its own return addresses doesn't confuse it.
+#[derive(Default)]
+pub struct Profiler {
+    instructions: HashMap<u16, Counts>,
+    routines: HashMap<u16, Routine>,
+    // flame graph samples, keyed by the routines on the stack
+    stacks: HashMap<Vec<u16>, u64>,
+    frames: Vec<Frame>,
+    // cycles profiled, the clock the frames are timed with
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // a profiler if `--profile` or `--profile-folded` asked for one
+    pub fn from_args(args: &Args) -> Option<Profiler> {
+        (args.profile.is_some() || args.profile_folded.is_some()).then(Profiler::new)
+    }
+
+    pub fn step(&mut self, machine: &mut Machine) -> Result<bool, CpuError> {
+        let cpu = machine.cpu();
+        let (pc, sp, cycles) = (cpu.program_counter, cpu.stack_counter, cpu.cycles);
+        let interrupt = cpu.pending_interrupt();
+        let code = cpu.peek(pc);
+        if self.frames.is_empty() {
+            self.frames.push(Frame { routine: pc, sp, start: 0 });
+            self.routines.entry(pc).or_default().calls += 1;
+        }
+
+        let running = machine.step()?;
+        let cpu = machine.cpu();
+        let spent = cpu.cycles.wrapping_sub(cycles);
+        let target = cpu.program_counter;
+        if interrupt.is_some() {
+            // the entry sequence belongs to the handler
+            self.enter(target, sp);
+            self.account(spent);
+            return Ok(running);
+        }
+        let counts = self.instructions.entry(pc).or_default();
+        counts.cycles += spent;
+        counts.executed += 1;
+        self.account(spent);
+        match code {
+            op::JSR => self.enter(target, sp),
+            op::BRK if running => self.enter(target, sp),
+            op::RTS | op::RTI => self.leave(cpu.stack_counter),
+            _ => {}
+        }
+        Ok(running)
+    }
+
+    fn account(&mut self, cycles: u64) {
+        self.total += cycles;
+        let path: Vec<u16> = self.frames.iter().map(|frame| frame.routine).collect();
+        self.routines.entry(*path.last().unwrap()).or_default().exclusive += cycles;
+        *self.stacks.entry(path).or_default() += cycles;
+    }
+
+    fn enter(&mut self, routine: u16, sp: u8) {
+        self.frames.push(Frame {
+            routine,
+            sp,
+            start: self.total,
+        });
+        self.routines.entry(routine).or_default().calls += 1;
+    }
+
+    // the bottom frame is whatever ran first, it never returns
+    fn leave(&mut self, sp: u8) {
+        while self.frames.len() > 1 && self.frames.last().unwrap().sp <= sp {
+            let frame = self.frames.pop().unwrap();
+            if self.frames.iter().all(|outer| outer.routine != frame.routine) {
+                self.routines.get_mut(&frame.routine).unwrap().inclusive += self.total - frame.start;
+            }
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn instruction(&self, addr: u16) -> Counts {
+        self.instructions.get(&addr).copied().unwrap_or_default()
+    }
+
+    // routines still running count up to now
+    pub fn routine(&self, addr: u16) -> Routine {
+        let mut routine = self.routines.get(&addr).copied().unwrap_or_default();
+        if let Some(frame) = self.frames.iter().find(|frame| frame.routine == addr) {
+            routine.inclusive += self.total - frame.start;
+        }
+        routine
+    }
+
+    // routines by the cycles spent in them, then the hottest instructions;
+    // `name` turns an address into a label
+    pub fn report(&self, name: &dyn Fn(u16) -> String) -> String {
+        let percent = |cycles: u64| cycles as f64 * 100.0 / self.total.max(1) as f64;
+        let mut out = format!("{} cycles profiled\n\n", self.total);
+
+        let mut routines: Vec<(u16, Routine)> = self.routines.keys().map(|addr| (*addr, self.routine(*addr))).collect();
+        routines.sort_by_key(|(addr, routine)| (std::cmp::Reverse(routine.exclusive), *addr));
+        let _ = writeln!(out, "{:>10} {:>6} {:>10} {:>6} {:>8}  routine", "self", "", "inclusive", "", "calls");
+        for (addr, routine) in routines {
+            let _ = writeln!(
+                out,
+                "{:>10} {:5.1}% {:>10} {:5.1}% {:>8}  {}",
+                routine.exclusive,
+                percent(routine.exclusive),
+                routine.inclusive,
+                percent(routine.inclusive),
+                routine.calls,
+                name(addr),
+            );
+        }
+
+        let mut instructions: Vec<(&u16, &Counts)> = self.instructions.iter().collect();
+        instructions.sort_by_key(|(addr, counts)| (std::cmp::Reverse(counts.cycles), **addr));
+        let _ = writeln!(out, "\n{:>10} {:>6} {:>10}  address", "cycles", "", "executed");
+        for (addr, counts) in instructions.into_iter().take(HOT_INSTRUCTIONS) {
+            let _ = writeln!(
+                out,
+                "{:>10} {:5.1}% {:>10}  {:04X} {}",
+                counts.cycles,
+                percent(counts.cycles),
+                counts.executed,
+                addr,
+                name(*addr),
+            );
+        }
+        out
+    }
+
+    // `outer;inner cycles` lines, the input of flamegraph.pl and inferno
+    pub fn folded(&self, name: &dyn Fn(u16) -> String) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(path, cycles)| {
+                let names: Vec<String> = path.iter().map(|addr| name(*addr)).collect();
+                format!("{} {}", names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    // writes what `--profile` and `--profile-folded` asked for
+    pub fn save(&self, args: &Args, name: &dyn Fn(u16) -> String) -> Result<(), String> {
+        if let Some(path) = &args.profile {
+            fs::write(path, self.report(name)).map_err(|e| format!("can't write {}: {}", path, e))?;
+        }
+        if let Some(path) = &args.profile_folded {
+            fs::write(path, self.folded(name)).map_err(|e| format!("can't write {}: {}", path, e))?;
+        }
+        Ok(())
+    }
+}
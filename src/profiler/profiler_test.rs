@@ -0,0 +1,96 @@
+use super::*;
+use crate::testutil::machine;
+use crate::cpu::NMI_VECTOR;
+
+fn run(profiler: &mut Profiler, machine: &mut Machine) {
+    for _ in 0..1000 {
+        if !profiler.step(machine).unwrap() {
+            return;
+        }
+    }
+    panic!("the program didn't halt");
+}
+
+fn hex(addr: u16) -> String {
+    format!("{:04X}", addr)
+}
+
+const NESTED: &str = "
+        jsr outer
+        jsr outer
+        brk
+    outer:
+        jsr inner
+        rts
+    inner:
+        ldx #$02
+    loop:
+        dex
+        bne loop
+        rts
+";
+
+#[test]
+fn test_subroutines() {
+    let mut machine = machine(NESTED);
+    let mut profiler = Profiler::new();
+    run(&mut profiler, &mut machine);
+
+    // JSR costs the caller, RTS the routine returning
+    let inner = Routine { calls: 2, exclusive: 34, inclusive: 34 };
+    assert_eq!(profiler.routine(0x060b), inner);
+    assert_eq!(profiler.routine(0x0607), Routine { calls: 2, exclusive: 24, inclusive: 58 });
+    // JSR, JSR and the BRK that halts
+    assert_eq!(profiler.routine(0x0600), Routine { calls: 1, exclusive: 19, inclusive: 77 });
+    assert_eq!(profiler.total(), 77);
+    assert_eq!(profiler.instruction(0x060e), Counts { cycles: 10, executed: 4 });
+
+    assert_eq!(profiler.folded(&hex), "0600 19\n0600;0607 24\n0600;0607;060B 34\n");
+    let report = profiler.report(&hex);
+    assert!(report.starts_with("77 cycles profiled\n"));
+    let routines: Vec<&str> = report.lines().skip(3).take(3).map(|line| line.rsplit(' ').next().unwrap()).collect();
+    assert_eq!(routines, ["060B", "0607", "0600"]);
+}
+
+#[test]
+fn test_interrupts() {
+    let mut machine = machine("nop\nnop\nbrk\ninx\nrti");
+    machine.cpu_mut().mem_write_u16(NMI_VECTOR, 0x0603);
+    let mut profiler = Profiler::new();
+    profiler.step(&mut machine).unwrap();
+    machine.cpu_mut().nmi_pending = true;
+    run(&mut profiler, &mut machine);
+
+    // the entry sequence, INX and RTI
+    assert_eq!(profiler.routine(0x0603), Routine { calls: 1, exclusive: 15, inclusive: 15 });
+    assert_eq!(profiler.routine(0x0600).exclusive, 11);
+    assert_eq!(profiler.instruction(0x0601).executed, 1);
+}
+
+#[test]
+fn test_pushed_return_addresses() {
+    // `sub` jumps to its second half with RTS, then returns for real
+    let source = "
+        jsr sub
+        brk
+    sub:
+        lda #$06
+        pha
+        lda #$0b
+        pha
+        rts
+        nop
+        rts
+    ";
+    let mut machine = machine(source);
+    let mut profiler = Profiler::new();
+    run(&mut profiler, &mut machine);
+
+    assert_eq!(profiler.instruction(0x060c).executed, 1);
+    assert_eq!(profiler.instruction(0x060b).executed, 0);
+    let sub = profiler.routine(0x0604);
+    assert_eq!(sub.calls, 1);
+    assert_eq!(sub.exclusive, 2 + 3 + 2 + 3 + 6 + 6);
+    assert_eq!(sub.inclusive, sub.exclusive);
+    assert_eq!(profiler.frames.len(), 1);
+}
@@ -0,0 +1,194 @@
+use std::ops::{Range, RangeInclusive};
+
+use crate::cheats::Cheat;
+use crate::cpu::CPU;
+
+#[cfg(test)]
+mod ramsearch_test;
+
+// the 2KiB of work RAM, and the cart's PRG RAM if there's a cart
+const WORK_RAM: Range<u32> = 0x0000..0x0800;
+const PRG_RAM: Range<u32> = 0x6000..0x8000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compare {
+    Eq,
+    Ne,
+    Gt,
+    Lt,
+}
+
+impl Compare {
+    pub fn parse(text: &str) -> Result<Compare, String> {
+        match text {
+            "eq" => Ok(Compare::Eq),
+            "ne" => Ok(Compare::Ne),
+            "gt" => Ok(Compare::Gt),
+            "lt" => Ok(Compare::Lt),
+            _ => Err(format!("unknown comparison: {}", text)),
+        }
+    }
+
+    fn holds(self, current: i32, other: i32) -> bool {
+        match self {
+            Compare::Eq => current == other,
+            Compare::Ne => current != other,
+            Compare::Gt => current > other,
+            Compare::Lt => current < other,
+        }
+    }
+}
+
+// what the current value is compared with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operand {
+    Previous,
+    Value(i32),
+}
+
+// How the bytes at a candidate address are read: one byte or a little
+// endian word, either of them signed or not.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct View {
+    pub word: bool,
+    pub signed: bool,
+}
+
+impl View {
+    fn value(self, lo: u8, hi: u8) -> i32 {
+        match (self.word, self.signed) {
+            (false, false) => lo as i32,
+            (false, true) => lo as i8 as i32,
+            (true, false) => u16::from_le_bytes([lo, hi]) as i32,
+            (true, true) => i16::from_le_bytes([lo, hi]) as i32,
+        }
+    }
+
+    // what `read` can give, signed or not
+    fn range(self) -> RangeInclusive<i32> {
+        let bits = if self.word { 16 } else { 8 };
+        match self.signed {
+            false => 0..=(1 << bits) - 1,
+            true => -(1 << (bits - 1))..=(1 << (bits - 1)) - 1,
+        }
+    }
+
+    pub fn read(self, cpu: &CPU, addr: u16) -> i32 {
+        self.value(cpu.peek(addr), cpu.peek(addr.wrapping_add(1)))
+    }
+
+    // Cheats that hold `addr` at `value`, or at what it holds now; one per
+    // byte of the view.
+    pub fn pin(self, cpu: &CPU, addr: u16, value: Option<i32>) -> Result<Vec<Cheat>, String> {
+        let value = value.unwrap_or_else(|| self.read(cpu, addr));
+        let bytes = self.bytes(value)?;
+        Ok(bytes.into_iter().enumerate().map(|(i, byte)| Cheat::new(addr.wrapping_add(i as u16), byte, None)).collect())
+    }
+
+    // the bytes of `value`, which may be given signed or unsigned
+    fn bytes(self, value: i32) -> Result<Vec<u8>, String> {
+        let bits = if self.word { 16 } else { 8 };
+        if value < -(1 << (bits - 1)) || value >= 1 << bits {
+            return Err(format!("{} doesn't fit in {} bits", value, bits));
+        }
+        let bytes = (value as u16).to_le_bytes();
+        Ok(bytes[..bits / 8].to_vec())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    pub addr: u16,
+    pub previous: i32,
+    pub current: i32,
+}
+
+// A cheat finder. Every RAM address starts out a candidate; each filter
+// keeps those whose value now compares as asked with the value at the
+// previous filter, or with a constant, and then takes a new snapshot.
+pub struct RamSearch {
+    pub view: View,
+    candidates: Vec<u16>,
+    // the values at the last snapshot, indexed by address
+    snapshot: Vec<u8>,
+}
+
+impl RamSearch {
+    pub fn new(cpu: &CPU, view: View) -> RamSearch {
+        let mut ranges = vec![WORK_RAM];
+        if cpu.bus.rom().is_some() {
+            ranges.push(PRG_RAM);
+        }
+        // a word needs its high byte inside the same range
+        let shorten = view.word as u32;
+        let candidates = ranges.into_iter().flat_map(|range| range.start..range.end - shorten).map(|addr| addr as u16).collect();
+        let mut search = RamSearch { view, candidates, snapshot: vec![0; 0x10000] };
+        search.snapshot(cpu);
+        search
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // Returns how many candidates are left. A value the view can't read is
+    // refused rather than matching nothing.
+    pub fn filter(&mut self, cpu: &CPU, compare: Compare, operand: Operand) -> Result<usize, String> {
+        let view = self.view;
+        if let Operand::Value(value) = operand {
+            let range = view.range();
+            if !range.contains(&value) {
+                return Err(format!("{} is outside the search's {}..={}", value, range.start(), range.end()));
+            }
+        }
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&addr| {
+            let current = view.read(cpu, addr);
+            let other = match operand {
+                Operand::Previous => view.value(snapshot[addr as usize], snapshot[addr.wrapping_add(1) as usize]),
+                Operand::Value(value) => value,
+            };
+            compare.holds(current, other)
+        });
+        self.snapshot(cpu);
+        Ok(self.candidates.len())
+    }
+
+    pub fn results(&self, cpu: &CPU) -> Vec<Candidate> {
+        self.candidates
+            .iter()
+            .map(|&addr| Candidate {
+                addr,
+                previous: self.previous(addr),
+                current: self.view.read(cpu, addr),
+            })
+            .collect()
+    }
+
+    fn previous(&self, addr: u16) -> i32 {
+        self.view.value(self.snapshot[addr as usize], self.snapshot[addr.wrapping_add(1) as usize])
+    }
+
+    // the candidates and the byte after each, which a word view reads
+    fn snapshot(&mut self, cpu: &CPU) {
+        for &addr in &self.candidates {
+            for addr in [addr, addr.wrapping_add(1)] {
+                self.snapshot[addr as usize] = cpu.peek(addr);
+            }
+        }
+    }
+}
+
+// decimal, or hex after `$` or `0x`; either may be negative
+pub fn parse_value(text: &str) -> Result<i32, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix('$').or_else(|| digits.strip_prefix("0x")) {
+        Some(hex) => i32::from_str_radix(hex, 16),
+        None => digits.parse(),
+    };
+    let value = value.map_err(|_| format!("invalid value: {}", text))?;
+    Ok(if negative { -value } else { value })
+}
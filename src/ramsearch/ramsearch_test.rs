@@ -0,0 +1,81 @@
+use super::*;
+
+#[test]
+fn test_filters_against_previous_and_values() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x10, 3);
+    cpu.mem_write(0x20, 3);
+    let mut search = RamSearch::new(&cpu, View::default());
+    assert_eq!(search.candidates().len(), 0x800);
+
+    // a life lost
+    cpu.mem_write(0x10, 2);
+    assert_eq!(search.filter(&cpu, Compare::Lt, Operand::Previous).unwrap(), 1);
+    assert_eq!(search.candidates(), [0x10]);
+    cpu.mem_write(0x10, 1);
+    assert_eq!(search.results(&cpu), [Candidate { addr: 0x10, previous: 2, current: 1 }]);
+    assert_eq!(search.filter(&cpu, Compare::Eq, Operand::Value(1)).unwrap(), 1);
+    assert_eq!(search.filter(&cpu, Compare::Ne, Operand::Previous).unwrap(), 0);
+}
+
+#[test]
+fn test_values_the_view_cant_read_are_refused() {
+    let cpu = CPU::new();
+    let mut search = RamSearch::new(&cpu, View::default());
+    let error = search.filter(&cpu, Compare::Eq, Operand::Value(300)).unwrap_err();
+    assert_eq!(error, "300 is outside the search's 0..=255");
+    assert!(search.filter(&cpu, Compare::Eq, Operand::Value(-1)).is_err());
+    assert_eq!(search.candidates().len(), 0x800);
+
+    let mut search = RamSearch::new(&cpu, View { word: true, signed: true });
+    assert!(search.filter(&cpu, Compare::Gt, Operand::Value(-32768)).is_ok());
+    assert!(search.filter(&cpu, Compare::Lt, Operand::Value(32768)).is_err());
+}
+
+#[test]
+fn test_word_and_signed_views() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x30, 0xff);
+    cpu.mem_write(0x31, 0xff);
+    let view = View { word: true, signed: true };
+    let mut search = RamSearch::new(&cpu, view);
+    assert_eq!(search.candidates().len(), 0x7ff);
+    assert_eq!(search.filter(&cpu, Compare::Eq, Operand::Value(-1)).unwrap(), 1);
+    assert_eq!(search.candidates(), [0x30]);
+
+    let mut search = RamSearch::new(&cpu, View { word: true, signed: false });
+    search.filter(&cpu, Compare::Eq, Operand::Value(0xffff)).unwrap();
+    assert_eq!(search.candidates(), [0x30]);
+    assert_eq!(search.results(&cpu)[0].current, 0xffff);
+
+    // $31 is $ff alone and with $00 after it
+    let mut search = RamSearch::new(&cpu, View { word: false, signed: true });
+    search.filter(&cpu, Compare::Lt, Operand::Value(0)).unwrap();
+    assert_eq!(search.candidates(), [0x30, 0x31]);
+}
+
+#[test]
+fn test_pin() {
+    let mut cpu = CPU::new();
+    cpu.mem_write(0x10, 0x42);
+    let byte = View::default();
+    assert_eq!(byte.pin(&cpu, 0x10, None).unwrap(), [Cheat::new(0x10, 0x42, None)]);
+    assert_eq!(byte.pin(&cpu, 0x10, Some(-1)).unwrap(), [Cheat::new(0x10, 0xff, None)]);
+    assert!(byte.pin(&cpu, 0x10, Some(256)).is_err());
+    assert!(byte.pin(&cpu, 0x10, Some(-129)).is_err());
+
+    let word = View { word: true, signed: false };
+    let cheats = word.pin(&cpu, 0x30, Some(1000)).unwrap();
+    assert_eq!(cheats, [Cheat::new(0x30, 0xe8, None), Cheat::new(0x31, 0x03, None)]);
+    assert_eq!(cheats[0].code, "0030:E8");
+}
+
+#[test]
+fn test_parse_value() {
+    assert_eq!(parse_value("12"), Ok(12));
+    assert_eq!(parse_value("$1f"), Ok(0x1f));
+    assert_eq!(parse_value("0x1F"), Ok(0x1f));
+    assert_eq!(parse_value("-5"), Ok(-5));
+    assert_eq!(parse_value("-$10"), Ok(-16));
+    assert!(parse_value("1f").is_err());
+}
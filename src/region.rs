@@ -0,0 +1,53 @@
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Region {
+    #[default]
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+impl Region {
+    pub fn from_name(name: &str) -> Option<Region> {
+        match name.to_ascii_lowercase().as_str() {
+            "ntsc" => Some(Region::Ntsc),
+            "pal" => Some(Region::Pal),
+            "dendy" => Some(Region::Dendy),
+            _ => None,
+        }
+    }
+
+    // frames per second of the video signal
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Region::Ntsc => 60.0988,
+            // Dendy uses PAL's 312 line frame with a faster CPU clock
+            Region::Pal | Region::Dendy => 50.0070,
+        }
+    }
+
+    // CPU cycles per frame, doubled as NTSC and PAL frames end half way
+    // through a cycle: 341 dots on each scanline, less NTSC's dot skipped
+    // every other frame, at 3 or 3.2 dots per cycle
+    pub fn frame_half_cycles(&self) -> u64 {
+        match self {
+            Region::Ntsc => 59561,
+            Region::Pal => 66495,
+            Region::Dendy => 70928,
+        }
+    }
+
+    pub fn scanlines(&self) -> u64 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    // PPU dots elapsed after `cycles` CPU cycles, PAL's PPU does 3.2 per cycle
+    pub fn ppu_dots(&self, cycles: u64) -> u64 {
+        match self {
+            Region::Pal => cycles * 16 / 5,
+            Region::Ntsc | Region::Dendy => cycles * 3,
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::VecDeque;
+
+use crate::machine::Machine;
+
+#[cfg(test)]
+mod rewind_test;
+
+// Snapshots are kept as a chain of deltas ending at the newest full state:
+// every delta is the XOR of two consecutive save states, run-length encoded
+// so that the untouched parts of memory cost next to nothing. Walking
+// backwards XORs the newest delta into the full state, and the oldest
+// deltas can be dropped whenever the memory budget runs out.
+pub struct Rewind {
+    interval: usize,
+    budget: usize,
+    frames: usize,
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    // the deltas and the full state
+    used: usize,
+}
+
+impl Rewind {
+    // `interval` frames between snapshots, `budget` bytes of compressed deltas
+    // plus the newest full state
+    pub fn new(interval: usize, budget: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            latest: None,
+            deltas: VecDeque::new(),
+            used: 0,
+        }
+    }
+
+    // number of snapshots that can be stepped back to
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    // call once per emulated frame
+    pub fn push_frame(&mut self, machine: &Machine) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.capture(machine.save_state());
+        }
+    }
+
+    pub fn capture(&mut self, state: Vec<u8>) {
+        if let Some(prev) = self.latest.take() {
+            self.used -= prev.len();
+            if prev.len() == state.len() {
+                let delta = encode_delta(&prev, &state);
+                self.used += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                // a different machine was loaded, the chain can't go past it
+                self.deltas.clear();
+                self.used = 0;
+            }
+        }
+        self.used += state.len();
+        self.latest = Some(state);
+
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    // Forgets every snapshot, for when the machine jumps elsewhere, like to a
+    // loaded state: stepping back would undo the jump.
+    pub fn clear(&mut self) {
+        self.frames = 0;
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+
+    // restores the previous snapshot, returns false once there's nothing left
+    pub fn step_back(&mut self, machine: &mut Machine) -> bool {
+        let latest = match self.latest.as_mut() {
+            Some(latest) => latest,
+            None => return false,
+        };
+        // frames ran since the last capture, go back to it first
+        if self.frames > 0 {
+            self.frames = 0;
+            return machine.load_state(latest).is_ok();
+        }
+        let delta = match self.deltas.pop_back() {
+            Some(delta) => delta,
+            None => return false,
+        };
+        self.used -= delta.len();
+        apply_delta(&delta, latest);
+        machine.load_state(latest).is_ok()
+    }
+}
+
+// pairs of (zero run length, literal length, literal bytes) over `prev ^ next`
+pub fn encode_delta(prev: &[u8], next: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < prev.len() {
+        let zeros_start = i;
+        while i < prev.len() && prev[i] == next[i] {
+            i += 1;
+        }
+        let literal_start = i;
+        // runs of less than 3 equal bytes are cheaper to keep inside the literal
+        loop {
+            while i < prev.len() && prev[i] != next[i] {
+                i += 1;
+            }
+            let run = (i..prev.len()).take_while(|&j| prev[j] == next[j]).take(3).count();
+            if run == 0 || run == 3 || i + run == prev.len() {
+                break;
+            }
+            i += run;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(|j| prev[j] ^ next[j]));
+    }
+    out
+}
+
+// XORs the delta into `state`, turning either side of it into the other
+pub fn apply_delta(delta: &[u8], state: &mut [u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        pos += read_varint(delta, &mut i);
+        let literal = read_varint(delta, &mut i);
+        for byte in &delta[i..i + literal] {
+            state[pos] ^= byte;
+            pos += 1;
+        }
+        i += literal;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
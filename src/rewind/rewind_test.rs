@@ -0,0 +1,106 @@
+use super::*;
+use crate::testutil;
+
+fn machine_with_a(value: u8) -> Machine {
+    let mut machine = testutil::machine("brk");
+    let cpu = machine.cpu_mut();
+    cpu.register_a = value;
+    cpu.mem_write(0x0200 + value as u16, value);
+    machine
+}
+
+#[test]
+fn test_delta_roundtrip() {
+    let prev: Vec<u8> = (0..200u8).collect();
+    let mut next = prev.clone();
+    next[0] = 0xff;
+    next[10] = 0xee;
+    next[12] = 0xdd;
+    next[199] = 0;
+
+    let delta = encode_delta(&prev, &next);
+    assert!(delta.len() < 20);
+
+    let mut state = next.clone();
+    apply_delta(&delta, &mut state);
+    assert_eq!(state, prev);
+    apply_delta(&delta, &mut state);
+    assert_eq!(state, next);
+}
+
+#[test]
+fn test_identical_states_are_cheap() {
+    let state = testutil::machine("brk").save_state();
+    assert!(encode_delta(&state, &state).len() <= 4);
+}
+
+#[test]
+fn test_step_back() {
+    let mut rewind = Rewind::new(1, 1 << 20);
+    let mut machine = machine_with_a(0);
+    for value in 1..=5 {
+        machine = machine_with_a(value);
+        rewind.push_frame(&machine);
+    }
+    assert_eq!(rewind.len(), 4);
+
+    for value in (1..=4).rev() {
+        assert!(rewind.step_back(&mut machine));
+        assert_eq!(machine.cpu().register_a, value);
+        assert_eq!(machine.cpu().peek(0x0200 + value as u16), value);
+        assert_eq!(machine.cpu().peek(0x0200 + value as u16 + 1), 0);
+    }
+    assert!(!rewind.step_back(&mut machine));
+}
+
+#[test]
+fn test_step_back_returns_to_last_capture_first() {
+    let mut rewind = Rewind::new(4, 1 << 20);
+    let mut machine = machine_with_a(0);
+    for value in 1..=6 {
+        machine = machine_with_a(value);
+        rewind.push_frame(&machine);
+    }
+    // captured on frame 4, two frames ran since then
+    assert!(rewind.step_back(&mut machine));
+    assert_eq!(machine.cpu().register_a, 4);
+    assert!(!rewind.step_back(&mut machine));
+}
+
+#[test]
+fn test_budget_drops_oldest_snapshots() {
+    // the full state counts too
+    let budget = machine_with_a(0).save_state().len() + 64;
+    let mut rewind = Rewind::new(1, budget);
+    let mut machine = machine_with_a(0);
+    for value in 1..=100 {
+        machine = machine_with_a(value);
+        rewind.push_frame(&machine);
+    }
+    assert!(rewind.memory_used() <= budget);
+    assert!(rewind.len() < 99);
+
+    let mut steps = 0;
+    while rewind.step_back(&mut machine) {
+        steps += 1;
+    }
+    assert_eq!(machine.cpu().register_a, 100 - steps);
+}
+
+#[test]
+fn test_clear_forgets_the_old_timeline() {
+    let mut rewind = Rewind::new(1, 1 << 20);
+    let mut machine = machine_with_a(1);
+    rewind.push_frame(&machine);
+    rewind.push_frame(&machine);
+    rewind.clear();
+    assert_eq!((rewind.len(), rewind.memory_used()), (0, 0));
+    assert!(!rewind.step_back(&mut machine));
+
+    machine = machine_with_a(2);
+    rewind.push_frame(&machine);
+    rewind.push_frame(&machine_with_a(3));
+    assert!(rewind.step_back(&mut machine));
+    assert_eq!(machine.cpu().register_a, 2);
+    assert!(!rewind.step_back(&mut machine));
+}
@@ -0,0 +1,161 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::machine::Machine;
+
+#[cfg(test)]
+mod savestate_test;
+
+// Layout, all numbers little endian:
+//
+//   "NESS"                 magic
+//   u16                    format version that wrote the state
+//   u16                    oldest format version able to read it
+//   u8 + bytes             emulator version string
+//   u64                    hash of the loaded ROM or program, 0 for a bare CPU
+//   chunks until the end:  4 byte tag, u32 length, data
+//
+// Readers skip chunks they don't know and ignore trailing bytes inside a
+// chunk, so new state can be added without bumping the compatible version.
+const MAGIC: &[u8; 4] = b"NESS";
+pub const FORMAT_VERSION: u16 = 1;
+const COMPATIBLE_VERSION: u16 = 1;
+
+pub const CPU_CHUNK: &[u8; 4] = b"CPU ";
+pub const RAM_CHUNK: &[u8; 4] = b"RAM ";
+pub const PRG_RAM_CHUNK: &[u8; 4] = b"PRAM";
+pub const JOYPAD_CHUNK: &[u8; 4] = b"JOYP";
+pub const RNG_CHUNK: &[u8; 4] = b"RNG ";
+
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new(rom_hash: u64) -> Self {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        buf.extend_from_slice(&COMPATIBLE_VERSION.to_le_bytes());
+        let version = env!("CARGO_PKG_VERSION").as_bytes();
+        buf.push(version.len() as u8);
+        buf.extend_from_slice(version);
+        buf.extend_from_slice(&rom_hash.to_le_bytes());
+        StateWriter { buf }
+    }
+
+    pub fn chunk(&mut self, tag: &[u8; 4], data: &[u8]) {
+        self.buf.extend_from_slice(tag);
+        self.buf.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct StateReader<'a> {
+    pub version: u16,
+    pub emulator_version: String,
+    pub rom_hash: u64,
+    chunks: Vec<(&'a [u8], &'a [u8])>,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, String> {
+        let mut input = Input { data, pos: 0 };
+        if input.take(4)? != MAGIC {
+            return Err("not a save state".to_string());
+        }
+        let version = input.u16()?;
+        let compatible = input.u16()?;
+        if compatible > FORMAT_VERSION {
+            return Err(format!(
+                "save state needs format version {}, this build reads up to {}",
+                compatible, FORMAT_VERSION
+            ));
+        }
+        let len = input.take(1)?[0] as usize;
+        let emulator_version = String::from_utf8_lossy(input.take(len)?).into_owned();
+        let mut hash = [0u8; 8];
+        hash.copy_from_slice(input.take(8)?);
+        let rom_hash = u64::from_le_bytes(hash);
+
+        let mut chunks = Vec::new();
+        while input.pos < data.len() {
+            let tag = input.take(4)?;
+            let mut len = [0u8; 4];
+            len.copy_from_slice(input.take(4)?);
+            let body = input.take(u32::from_le_bytes(len) as usize)?;
+            chunks.push((tag, body));
+        }
+
+        Ok(StateReader {
+            version,
+            emulator_version,
+            rom_hash,
+            chunks,
+        })
+    }
+
+    pub fn chunk(&self, tag: &[u8; 4]) -> Option<&'a [u8]> {
+        self.chunks
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, body)| *body)
+    }
+
+    // like `chunk`, for chunks that must be there and hold at least `len` bytes
+    pub fn require(&self, tag: &[u8; 4], len: usize) -> Result<&'a [u8], String> {
+        let name = String::from_utf8_lossy(tag).trim().to_string();
+        match self.chunk(tag) {
+            Some(body) if body.len() >= len => Ok(body),
+            Some(_) => Err(format!("save state chunk {} is too short", name)),
+            None => Err(format!("save state has no {} chunk", name)),
+        }
+    }
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.data.len() - self.pos < len {
+            return Err("save state is truncated".to_string());
+        }
+        self.pos += len;
+        Ok(&self.data[self.pos - len..self.pos])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+// slot files live next to the program: `game.nes` -> `game.ss3`
+pub fn slot_path(program: &Path, slot: u8) -> PathBuf {
+    program.with_extension(format!("ss{}", slot))
+}
+
+pub struct Slots {
+    pub program: PathBuf,
+    pub current: u8,
+}
+
+impl Slots {
+    pub fn save(&self, machine: &Machine) -> Result<(), String> {
+        let path = slot_path(&self.program, self.current);
+        fs::write(&path, machine.save_state()).map_err(|e| format!("can't write {}: {}", path.display(), e))
+    }
+
+    pub fn load(&self, machine: &mut Machine) -> Result<(), String> {
+        let path = slot_path(&self.program, self.current);
+        let state = fs::read(&path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        machine.load_state(&state)
+    }
+}
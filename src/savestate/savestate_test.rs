@@ -0,0 +1,104 @@
+use super::*;
+use crate::bus::Bus;
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::testutil::Ines;
+
+fn test_rom(fill: u8) -> Rom {
+    Ines::new(&[fill; 0x4000]).battery().rom()
+}
+
+#[test]
+fn test_save_and_load_roundtrip() {
+    let mut cpu = CPU::new();
+    cpu.load_and_run(vec![0xa9, 0x42, 0xa2, 0x10, 0xa0, 0x20, 0x8d, 0x00, 0x02, 0x00]);
+    let state = cpu.save_state();
+
+    let mut restored = CPU::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.register_a, 0x42);
+    assert_eq!(restored.register_x, 0x10);
+    assert_eq!(restored.register_y, 0x20);
+    assert_eq!(restored.status, cpu.status);
+    assert_eq!(restored.program_counter, cpu.program_counter);
+    assert_eq!(restored.stack_counter, cpu.stack_counter);
+    assert_eq!(restored.cycles, cpu.cycles);
+    assert_eq!(restored.mem_read(0x0200), 0x42);
+}
+
+#[test]
+fn test_prg_ram_is_saved() {
+    let mut cpu = CPU::with_bus(Bus::with_rom(test_rom(0xea)));
+    cpu.mem_write(0x6123, 0x99);
+    let state = cpu.save_state();
+
+    let mut restored = CPU::with_bus(Bus::with_rom(test_rom(0xea)));
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.mem_read(0x6123), 0x99);
+}
+
+#[test]
+fn test_state_from_other_rom_is_rejected() {
+    let cpu = CPU::with_bus(Bus::with_rom(test_rom(0xea)));
+    let state = cpu.save_state();
+
+    let mut other = CPU::with_bus(Bus::with_rom(test_rom(0x00)));
+    assert!(other.load_state(&state).is_err());
+    assert!(CPU::new().load_state(&state).is_err());
+}
+
+#[test]
+fn test_interrupt_lines_are_saved() {
+    let mut cpu = CPU::new();
+    cpu.nmi_pending = true;
+    cpu.irq_line = true;
+    let state = cpu.save_state();
+
+    let mut restored = CPU::new();
+    restored.load_state(&state).unwrap();
+    assert!(restored.nmi_pending);
+    assert!(restored.irq_line);
+}
+
+#[test]
+fn test_header() {
+    let state = CPU::new().save_state();
+    let reader = StateReader::new(&state).unwrap();
+    assert_eq!(reader.version, FORMAT_VERSION);
+    assert_eq!(reader.emulator_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(reader.rom_hash, 0);
+}
+
+#[test]
+fn test_unknown_chunks_are_skipped() {
+    let mut cpu = CPU::new();
+    cpu.register_a = 0x12;
+    let mut state = cpu.save_state();
+    state.extend_from_slice(b"PPU ");
+    state.extend_from_slice(&3u32.to_le_bytes());
+    state.extend_from_slice(&[1, 2, 3]);
+
+    let mut restored = CPU::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.register_a, 0x12);
+}
+
+#[test]
+fn test_newer_incompatible_format_is_rejected() {
+    let mut state = CPU::new().save_state();
+    // bump the compatible version past what this build reads
+    state[6..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    assert!(CPU::new().load_state(&state).is_err());
+}
+
+#[test]
+fn test_truncated_state_is_rejected() {
+    let state = CPU::new().save_state();
+    assert!(CPU::new().load_state(&state[..state.len() - 1]).is_err());
+    assert!(CPU::new().load_state(b"NES\x1a").is_err());
+}
+
+#[test]
+fn test_slot_path() {
+    assert_eq!(slot_path(Path::new("roms/zelda.nes"), 3), PathBuf::from("roms/zelda.ss3"));
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod scheduler_test;
+
+// frames run back to back to catch up; further behind than this, the time
+// is dropped instead
+const MAX_BATCH: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Quarter,
+    Half,
+    Normal,
+    Double,
+    Quadruple,
+    Uncapped,
+}
+
+const SPEEDS: [Speed; 6] = [Speed::Quarter, Speed::Half, Speed::Normal, Speed::Double, Speed::Quadruple, Speed::Uncapped];
+
+impl Speed {
+    pub fn slower(self) -> Speed {
+        let i = SPEEDS.iter().position(|speed| *speed == self).unwrap();
+        SPEEDS[i.saturating_sub(1)]
+    }
+
+    pub fn faster(self) -> Speed {
+        let i = SPEEDS.iter().position(|speed| *speed == self).unwrap();
+        SPEEDS[(i + 1).min(SPEEDS.len() - 1)]
+    }
+
+    // emulated frames per real one, None when uncapped
+    pub fn factor(self) -> Option<f64> {
+        match self {
+            Speed::Quarter => Some(0.25),
+            Speed::Half => Some(0.5),
+            Speed::Normal => Some(1.0),
+            Speed::Double => Some(2.0),
+            Speed::Quadruple => Some(4.0),
+            Speed::Uncapped => None,
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.factor() {
+            Some(factor) => write!(f, "{}%", factor * 100.0),
+            None => write!(f, "uncapped"),
+        }
+    }
+}
+
+// Decides when the driver runs emulated frames. Each pass of the driver's
+// loop is a batch: `begin` it, run frames while `due` says so, draw once,
+// then sleep until `wake_at`. Speeds change how often frames fall due rather
+// than how long the loop sleeps, so a slow host drops time instead of
+// drifting.
+pub struct Scheduler {
+    // at 100%
+    frame_time: Duration,
+    pub speed: Speed,
+    pub paused: bool,
+    // held down: uncapped whatever the speed
+    pub fast_forward: bool,
+    // a frame to run while paused
+    advance: bool,
+    next_frame: Instant,
+    // when an uncapped batch stops for a redraw, and frames run so far
+    batch_end: Instant,
+    batch: u32,
+}
+
+impl Scheduler {
+    pub fn new(frame_rate: f64, paused: bool) -> Scheduler {
+        let now = Instant::now();
+        Scheduler {
+            frame_time: Duration::from_secs_f64(1.0 / frame_rate),
+            speed: Speed::Normal,
+            paused,
+            fast_forward: false,
+            advance: false,
+            next_frame: now,
+            batch_end: now,
+            batch: 0,
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.advance = false;
+    }
+
+    // pauses, then runs one frame
+    pub fn advance(&mut self) {
+        self.paused = true;
+        self.advance = true;
+    }
+
+    fn uncapped(&self) -> bool {
+        self.fast_forward || self.speed == Speed::Uncapped
+    }
+
+    fn period(&self) -> Duration {
+        self.speed.factor().map_or(Duration::ZERO, |factor| self.frame_time.div_f64(factor))
+    }
+
+    pub fn begin(&mut self, now: Instant) {
+        self.batch = 0;
+        self.batch_end = now + self.frame_time;
+        // back from a pause, fast forward or a stall
+        if self.paused || self.uncapped() || now > self.next_frame + self.period() * MAX_BATCH {
+            self.next_frame = now;
+        }
+    }
+
+    // whether to run another frame before drawing
+    pub fn due(&mut self, now: Instant) -> bool {
+        if self.paused {
+            return std::mem::take(&mut self.advance);
+        }
+        let due = if self.uncapped() {
+            // at least one frame a batch, however slow they are
+            self.batch == 0 || now < self.batch_end
+        } else if now >= self.next_frame && self.batch < MAX_BATCH {
+            self.next_frame += self.period();
+            true
+        } else {
+            false
+        };
+        self.batch += due as u32;
+        due
+    }
+
+    // when the driver should start its next batch; paused it still wakes
+    // every frame for input
+    pub fn wake_at(&self, now: Instant) -> Instant {
+        if self.paused {
+            now + self.frame_time
+        } else if self.uncapped() {
+            now
+        } else {
+            self.next_frame.max(now)
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use super::*;
+
+const FRAME: Duration = Duration::from_millis(20);
+
+// frames run by a driver loop from `now` for `length`, sleeping until each
+// `wake_at`
+fn run(scheduler: &mut Scheduler, now: Instant, length: Duration) -> u32 {
+    let mut frames = 0;
+    let mut at = now;
+    while at <= now + length {
+        scheduler.begin(at);
+        while scheduler.due(at) {
+            frames += 1;
+        }
+        at = scheduler.wake_at(at);
+    }
+    frames
+}
+
+#[test]
+fn test_speeds() {
+    // one second at each speed
+    let frames_per_second: Vec<u32> = [Speed::Quarter, Speed::Half, Speed::Normal, Speed::Double, Speed::Quadruple]
+        .into_iter()
+        .map(|speed| {
+            let mut scheduler = Scheduler::new(50.0, false);
+            scheduler.speed = speed;
+            run(&mut scheduler, Instant::now(), Duration::from_millis(999))
+        })
+        .collect();
+    assert_eq!(frames_per_second, [13, 25, 50, 100, 200]);
+}
+
+#[test]
+fn test_uncapped_runs_until_the_next_redraw() {
+    let mut scheduler = Scheduler::new(50.0, false);
+    let now = Instant::now();
+    scheduler.fast_forward = true;
+    scheduler.begin(now);
+    assert!(scheduler.due(now + FRAME * 2));
+    assert!(scheduler.due(now));
+    assert!(scheduler.due(now + FRAME - Duration::from_millis(1)));
+    assert!(!scheduler.due(now + FRAME));
+    assert_eq!(scheduler.wake_at(now), now);
+
+    // back to normal speed from where fast forward stopped
+    scheduler.fast_forward = false;
+    let later = now + FRAME * 10;
+    scheduler.begin(later);
+    assert!(scheduler.due(later));
+    assert!(!scheduler.due(later));
+    assert_eq!(scheduler.wake_at(later), later + FRAME);
+}
+
+#[test]
+fn test_stalls_drop_time() {
+    let mut scheduler = Scheduler::new(50.0, false);
+    let now = Instant::now();
+    scheduler.begin(now);
+    assert!(scheduler.due(now));
+    // a little behind: caught up
+    let behind = now + FRAME * 4;
+    scheduler.begin(behind);
+    assert_eq!((0..20).filter(|_| scheduler.due(behind)).count(), 4);
+    // far behind: one frame and the rest is dropped
+    let stalled = behind + Duration::from_secs(5);
+    scheduler.begin(stalled);
+    assert_eq!((0..20).filter(|_| scheduler.due(stalled)).count(), 1);
+}
+
+#[test]
+fn test_pause_and_frame_advance() {
+    let mut scheduler = Scheduler::new(50.0, true);
+    let now = Instant::now();
+    assert_eq!(run(&mut scheduler, now, FRAME * 5), 0);
+    assert_eq!(scheduler.wake_at(now), now + FRAME);
+
+    scheduler.advance();
+    assert_eq!(run(&mut scheduler, now, FRAME * 5), 1);
+    assert_eq!(run(&mut scheduler, now, FRAME * 5), 0);
+
+    // advancing while running pauses first
+    scheduler.toggle_pause();
+    assert!(!scheduler.paused);
+    scheduler.advance();
+    assert!(scheduler.paused);
+    assert_eq!(run(&mut scheduler, now, FRAME), 1);
+
+    // resuming doesn't run the frames the pause skipped
+    scheduler.toggle_pause();
+    let later = now + Duration::from_secs(1);
+    assert_eq!(run(&mut scheduler, later, Duration::ZERO), 1);
+}
+
+#[test]
+fn test_speed_steps() {
+    assert_eq!(Speed::Normal.slower(), Speed::Half);
+    assert_eq!(Speed::Quarter.slower(), Speed::Quarter);
+    assert_eq!(Speed::Quadruple.faster(), Speed::Uncapped);
+    assert_eq!(Speed::Uncapped.faster(), Speed::Uncapped);
+    assert_eq!(Speed::Quarter.to_string(), "25%");
+    assert_eq!(Speed::Double.to_string(), "200%");
+    assert_eq!(Speed::Uncapped.to_string(), "uncapped");
+}
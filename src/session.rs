@@ -0,0 +1,98 @@
+use std::io;
+use std::path::Path;
+
+use crate::cdl::CodeDataLogger;
+use crate::cheats::Cheats;
+use crate::cli::Args;
+use crate::coverage::CoverageRecorder;
+use crate::joypad::Buttons;
+use crate::machine::Machine;
+use crate::movie::MovieController;
+use crate::profiler::Profiler;
+use crate::trace::Tracer;
+
+#[cfg(test)]
+mod session_test;
+
+// What the command line wraps around a machine, whichever frontend runs it:
+// the trace, code/data log, cheats, coverage, profile and movie. Set up once
+// the machine is loaded and `finish`ed when the run is over.
+pub struct Session {
+    pub tracer: Tracer,
+    pub profiler: Option<Profiler>,
+    pub movie: Option<MovieController>,
+    cdl: Option<(CodeDataLogger, String)>,
+    cheats: Option<Cheats>,
+    coverage: Option<CoverageRecorder>,
+}
+
+impl Session {
+    pub fn from_args(args: &Args, machine: &mut Machine) -> Result<Session, String> {
+        let tracer = Tracer::from_args(args)?;
+        let cdl = match &args.cdl {
+            Some(path) => Some((CodeDataLogger::open(Path::new(path), machine.cpu_mut())?, path.clone())),
+            None => None,
+        };
+        let cheats = Cheats::from_args(args, machine.cpu_mut())?;
+        let coverage = CoverageRecorder::from_args(args, machine.cpu_mut(), &tracer.symbols)?;
+        let profiler = Profiler::from_args(args);
+        let movie = MovieController::from_args(args, machine)?;
+        Ok(Session {
+            tracer,
+            profiler,
+            movie,
+            cdl,
+            cheats,
+            coverage,
+        })
+    }
+
+    // at the start of each frame: the movie's buttons, or the ones held
+    pub fn latch(&mut self, machine: &mut Machine, held: [Buttons; 2]) {
+        match self.movie.as_mut() {
+            Some(movie) => movie.frame(machine, held),
+            None => machine.latch(held),
+        }
+    }
+
+    // Whether a movie is recording or still playing back. Loading a state
+    // or rewinding then would leave it out of step with the machine.
+    pub fn movie_active(&self) -> bool {
+        self.movie.as_ref().is_some_and(|movie| !movie.finished())
+    }
+
+    // one traced and profiled instruction; a CPU error prints the trace ring
+    pub fn step(&mut self, machine: &mut Machine) -> Result<bool, String> {
+        self.tracer.trace(machine.cpu())?;
+        let step = match self.profiler.as_mut() {
+            Some(profiler) => profiler.step(machine),
+            None => machine.step(),
+        };
+        step.or_else(|e| {
+            self.tracer.dump_ring(&mut io::stderr()).map_err(|e| e.to_string())?;
+            Err(e.to_string())
+        })
+    }
+
+    // Writes out everything the run recorded. Failures are printed, so one
+    // doesn't keep the rest from being saved.
+    pub fn finish(mut self, args: &Args, machine: &Machine) {
+        self.tracer.flush().unwrap_or_else(|e| eprintln!("{}", e));
+        if let Some(movie) = &self.movie {
+            movie.save().unwrap_or_else(|e| eprintln!("{}", e));
+        }
+        if let Some(cheats) = &self.cheats {
+            cheats.save_list().unwrap_or_else(|e| eprintln!("{}", e));
+        }
+        if let Some((cdl, path)) = &self.cdl {
+            cdl.save(Path::new(path)).unwrap_or_else(|e| eprintln!("{}", e));
+        }
+        if let Some(profiler) = &self.profiler {
+            let name = |addr| self.tracer.symbols.describe(&machine.cpu().bus, addr);
+            profiler.save(args, &name).unwrap_or_else(|e| eprintln!("{}", e));
+        }
+        if let Some(coverage) = &self.coverage {
+            coverage.save(args, machine.cpu(), &self.tracer.symbols).unwrap_or_else(|e| eprintln!("{}", e));
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use super::*;
+use std::fs;
+
+use crate::movie::Movie;
+use crate::testutil::machine;
+
+#[test]
+fn test_finish_saves_the_recording() {
+    let path = std::env::temp_dir().join(format!("session_test_{}.fm2", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let args = Args { record: Some(path.to_str().unwrap().to_string()), ..Args::default() };
+    let mut machine = machine("loop: jmp loop");
+    let mut session = Session::from_args(&args, &mut machine).unwrap();
+    for buttons in [Buttons::empty(), Buttons::A] {
+        session.latch(&mut machine, [buttons, Buttons::empty()]);
+        machine.begin_frame();
+        while !machine.frame_done() {
+            assert!(session.step(&mut machine).unwrap());
+        }
+    }
+    session.finish(&args, &machine);
+
+    let movie = Movie::parse_fm2(&fs::read_to_string(&path).unwrap()).unwrap();
+    assert_eq!(movie.frames.len(), 2);
+    assert_eq!(movie.frames[1].buttons[0], Buttons::A);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_movies_are_active_until_played_out() {
+    let mut machine = machine("loop: jmp loop");
+    assert!(!Session::from_args(&Args::default(), &mut machine).unwrap().movie_active());
+
+    let recording = Args { record: Some("unused.fm2".to_string()), ..Args::default() };
+    assert!(Session::from_args(&recording, &mut machine).unwrap().movie_active());
+
+    let path = std::env::temp_dir().join(format!("session_test_{}_active.fm2", std::process::id()));
+    Movie { frames: vec![Default::default()], ..Movie::new() }.save(&path).unwrap();
+    let playing = Args { movie: Some(path.to_str().unwrap().to_string()), ..Args::default() };
+    let mut session = Session::from_args(&playing, &mut machine).unwrap();
+    assert!(session.movie_active());
+    session.latch(&mut machine, [Buttons::empty(); 2]);
+    assert!(!session.movie_active());
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use crate::bus::Bus;
+
+#[cfg(test)]
+mod symbols_test;
+
+// FCEUX splits its name lists per 16KiB bank
+const NL_BANK_SIZE: u32 = 0x4000;
+const WORK_RAM: u32 = 0x6000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location {
+    // RAM and registers, the same whatever is mapped
+    Cpu(u16),
+    // an offset into PRG-ROM, found through the bus's current mapping
+    Prg(u32),
+}
+
+// a line of assembler source and the bytes it was assembled to
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32,
+    pub location: Location,
+    pub size: u32,
+}
+
+// Labels loaded from ca65 debug files, FCEUX name lists and Mesen label
+// files. Labels in ROM are kept by PRG offset, so a bank switch changes what
+// an address is called. Debug files also give the source lines.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    cpu: BTreeMap<u16, String>,
+    prg: BTreeMap<u32, String>,
+    names: HashMap<String, Location>,
+    lines: Vec<SourceLine>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    // picks the format from the extension: .dbg, .nl (`game.nes.2.nl` for
+    // bank 2, `game.nes.ram.nl` for RAM) or .mlb
+    pub fn load(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        let result = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("dbg") => self.parse_dbg(&text),
+            Some("mlb") => self.parse_mlb(&text),
+            Some("nl") => {
+                let bank = name.trim_end_matches(".nl").rsplit('.').next().and_then(|bank| bank.parse().ok());
+                self.parse_nl(&text, bank)
+            }
+            _ => Err("unknown symbol file format, expected .dbg, .nl or .mlb".to_string()),
+        };
+        result.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    // a name given again moves, its old location loses the label
+    pub fn insert(&mut self, location: Location, name: &str) {
+        match self.names.get(name).copied() {
+            Some(Location::Cpu(addr)) if self.cpu.get(&addr).is_some_and(|label| label == name) => {
+                self.cpu.remove(&addr);
+            }
+            Some(Location::Prg(offset)) if self.prg.get(&offset).is_some_and(|label| label == name) => {
+                self.prg.remove(&offset);
+            }
+            _ => {}
+        }
+        match location {
+            Location::Cpu(addr) => self.cpu.insert(addr, name.to_string()),
+            Location::Prg(offset) => self.prg.insert(offset, name.to_string()),
+        };
+        self.names.insert(name.to_string(), location);
+    }
+
+    // the label at exactly this address
+    pub fn label(&self, bus: &Bus, addr: u16) -> Option<&str> {
+        match bus.prg_offset(addr) {
+            Some(offset) => self.prg.get(&offset),
+            None => self.cpu.get(&addr),
+        }
+        .map(String::as_str)
+    }
+
+    // `label`, `label+3`, or the address in hex when nothing precedes it
+    pub fn describe(&self, bus: &Bus, addr: u16) -> String {
+        let nearest = match bus.prg_offset(addr) {
+            Some(offset) => self.prg.range(..=offset).next_back().map(|(at, name)| (offset - at, name)),
+            None => self.cpu.range(..=addr).next_back().map(|(at, name)| ((addr - at) as u32, name)),
+        };
+        match nearest {
+            Some((0, name)) => name.clone(),
+            Some((distance, name)) => format!("{}+{}", name, distance),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    // the CPU address of a label, ROM labels at the bank currently mapped
+    pub fn address(&self, bus: &Bus, name: &str) -> Option<u16> {
+        resolve(bus, *self.names.get(name)?)
+    }
+
+    // in the order the debug file lists them
+    pub fn source_lines(&self) -> &[SourceLine] {
+        &self.lines
+    }
+
+    // `$C000#reset#comment` lines, `$0300/10#buffer#` gives a size
+    pub fn parse_nl(&mut self, text: &str, bank: Option<u32>) -> Result<(), String> {
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.split('#');
+            let (addr, name) = match (fields.next(), fields.next()) {
+                (Some(addr), Some(name)) if !name.is_empty() => (addr, name),
+                _ => return Err(format!("line {}: expected `$addr#name#`", lineno + 1)),
+            };
+            let addr = addr.trim_start_matches('$').split('/').next().unwrap();
+            let addr = u16::from_str_radix(addr, 16).map_err(|_| format!("line {}: invalid address", lineno + 1))?;
+            let location = match bank {
+                Some(bank) if addr >= 0x8000 => Location::Prg(bank * NL_BANK_SIZE + (addr as u32 % NL_BANK_SIZE)),
+                _ => Location::Cpu(addr),
+            };
+            self.insert(location, name);
+        }
+        Ok(())
+    }
+
+    // `P:1F00:label:comment`, with Mesen 1 (`P`, `R`, ...) or Mesen 2
+    // (`NesPrgRom`, `NesInternalRam`, ...) memory types; ranges keep their start
+    pub fn parse_mlb(&mut self, text: &str) -> Result<(), String> {
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(4, ':');
+            let (kind, addr, name) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(kind), Some(addr), Some(name)) => (kind, addr, name),
+                _ => return Err(format!("line {}: expected `type:addr:label`", lineno + 1)),
+            };
+            // comment only entries
+            if name.is_empty() {
+                continue;
+            }
+            let addr = addr.split('-').next().unwrap();
+            let addr = u32::from_str_radix(addr, 16).map_err(|_| format!("line {}: invalid address", lineno + 1))?;
+            let location = match kind {
+                "P" | "NesPrgRom" => Location::Prg(addr),
+                "R" | "G" | "NesInternalRam" | "NesMemory" => Location::Cpu(addr as u16),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => Location::Cpu((WORK_RAM + addr) as u16),
+                // CHR, palette and the like aren't on the CPU bus
+                _ => continue,
+            };
+            self.insert(location, name);
+        }
+        Ok(())
+    }
+
+    // the `seg`, `sym`, `file`, `span` and `line` records of ld65's
+    // `--dbgfile` output; segments written to the ROM get their PRG offset
+    // from `ooffs`
+    pub fn parse_dbg(&mut self, text: &str) -> Result<(), String> {
+        let mut segments = HashMap::new();
+        let mut symbols = Vec::new();
+        let mut files = HashMap::new();
+        let mut spans = HashMap::new();
+        let mut lines = Vec::new();
+        for (lineno, line) in text.lines().enumerate() {
+            let (kind, rest) = match line.split_once(char::is_whitespace) {
+                Some(split) => split,
+                None => continue,
+            };
+            let fields = dbg_fields(rest);
+            let number = |key: &str| -> Result<Option<u32>, String> {
+                match fields.get(key) {
+                    Some(value) => parse_dbg_number(value)
+                        .map(Some)
+                        .ok_or_else(|| format!("line {}: invalid {}", lineno + 1, key)),
+                    None => Ok(None),
+                }
+            };
+            match kind {
+                "seg" => {
+                    let id = number("id")?.ok_or_else(|| format!("line {}: segment without id", lineno + 1))?;
+                    let start = number("start")?.unwrap_or(0);
+                    let rom = fields.get("type").map(String::as_str) == Some("ro") && start >= 0x8000;
+                    // ooffs counts the 16 byte iNES header
+                    let offset = number("ooffs")?.filter(|_| rom).map(|ooffs| ooffs.saturating_sub(16));
+                    segments.insert(id, (start, offset));
+                }
+                "sym" if fields.get("type").map(String::as_str) == Some("lab") => {
+                    let name = fields.get("name").cloned().unwrap_or_default();
+                    let value = number("val")?.ok_or_else(|| format!("line {}: symbol without val", lineno + 1))?;
+                    symbols.push((name, value, number("seg")?));
+                }
+                "file" => {
+                    let id = number("id")?.ok_or_else(|| format!("line {}: file without id", lineno + 1))?;
+                    files.insert(id, fields.get("name").cloned().unwrap_or_default());
+                }
+                "span" => {
+                    let id = number("id")?.ok_or_else(|| format!("line {}: span without id", lineno + 1))?;
+                    spans.insert(id, (number("seg")?, number("start")?.unwrap_or(0), number("size")?.unwrap_or(0)));
+                }
+                // type 2 lines are macro expansions, the invocation has the span too
+                "line" if fields.get("type").map(String::as_str) != Some("2") => {
+                    let (file, line) = (number("file")?, number("line")?);
+                    // a line may list several spans, `span=3+4`
+                    let span_ids: Vec<u32> = match fields.get("span") {
+                        Some(list) => list.split('+').filter_map(parse_dbg_number).collect(),
+                        None => Vec::new(),
+                    };
+                    if let (Some(file), Some(line)) = (file, line) {
+                        lines.extend(span_ids.into_iter().map(|span| (file, line, span)));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // a value below its segment's start isn't in the ROM image
+        let locate = |segment: Option<u32>, addr: u32| match segment.and_then(|id| segments.get(&id)) {
+            Some((start, Some(offset))) => match addr.checked_sub(*start) {
+                Some(delta) => Location::Prg(offset + delta),
+                None => Location::Cpu(addr as u16),
+            },
+            _ => Location::Cpu(addr as u16),
+        };
+        for (name, value, segment) in symbols {
+            self.insert(locate(segment, value), &name);
+        }
+        for (file, line, span) in lines {
+            let (file, (segment, start, size)) = match (files.get(&file), spans.get(&span)) {
+                (Some(file), Some(span)) => (file, *span),
+                _ => continue,
+            };
+            // spans start from their segment's start
+            let segment_start = segment.and_then(|id| segments.get(&id)).map_or(0, |(start, _)| *start);
+            self.lines.push(SourceLine {
+                file: file.clone(),
+                line,
+                location: locate(segment, segment_start + start),
+                size,
+            });
+        }
+        Ok(())
+    }
+}
+
+// the CPU address of a location, ROM at the bank currently mapped
+pub fn resolve(bus: &Bus, location: Location) -> Option<u16> {
+    match location {
+        Location::Cpu(addr) => Some(addr),
+        Location::Prg(offset) => bus.prg_address(offset),
+    }
+}
+
+// `id=0,name="CODE",start=0x008000`, the quotes stripped
+fn dbg_fields(text: &str) -> HashMap<&str, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (quoted[..end].to_string(), quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = after.find(',').unwrap_or(after.len());
+                (after[..end].to_string(), &after[end..])
+            }
+        };
+        fields.insert(key.trim(), value);
+        rest = next.trim_start_matches(',');
+    }
+    fields
+}
+
+fn parse_dbg_number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
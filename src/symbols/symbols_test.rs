@@ -0,0 +1,120 @@
+use super::*;
+use crate::asm::assemble;
+use crate::cpu::CPU;
+use crate::debugger::Debugger;
+use crate::region::Region;
+use crate::testutil::Ines;
+use crate::trace::{trace, Columns};
+
+// `banks` 16KiB banks of NOPs
+fn rom(banks: u8) -> Bus {
+    Bus::with_rom(Ines::new(&vec![0xea; banks as usize * 0x4000]).rom())
+}
+
+#[test]
+fn test_name_lists() {
+    let mut symbols = Symbols::new();
+    symbols.parse_nl("$0010#snake_length#\n$0300/10#buffer#the buffer\n", None).unwrap();
+    symbols.parse_nl("$C000#reset#\n$C010#nmi#\n", Some(1)).unwrap();
+    let bus = rom(2);
+
+    assert_eq!(symbols.label(&bus, 0x0010), Some("snake_length"));
+    assert_eq!(symbols.label(&bus, 0x0300), Some("buffer"));
+    assert_eq!(symbols.label(&bus, 0xc000), Some("reset"));
+    // bank 1 at $8000 would only be `reset` on a mapper that puts it there
+    assert_eq!(symbols.label(&bus, 0x8000), None);
+    assert_eq!(symbols.address(&bus, "nmi"), Some(0xc010));
+    assert_eq!(symbols.describe(&bus, 0xc012), "nmi+2");
+    assert_eq!(symbols.describe(&bus, 0x0305), "buffer+5");
+    assert_eq!(symbols.describe(&bus, 0x0005), "$0005");
+    assert!(symbols.parse_nl("C000 reset", None).is_err());
+}
+
+#[test]
+fn test_mirrored_banks() {
+    let mut symbols = Symbols::new();
+    symbols.parse_nl("$C004#main#\n", Some(0)).unwrap();
+    // a single bank shows at $8000 and $C000
+    let bus = rom(1);
+    assert_eq!(symbols.label(&bus, 0x8004), Some("main"));
+    assert_eq!(symbols.label(&bus, 0xc004), Some("main"));
+    assert_eq!(symbols.address(&bus, "main"), Some(0xc004));
+    // without a ROM there's no bank to look in
+    assert_eq!(symbols.label(&Bus::new(), 0xc004), None);
+}
+
+#[test]
+fn test_mesen_labels() {
+    let mut symbols = Symbols::new();
+    let text = "P:4010:nmi:vblank handler\nR:0010:snake_length\nS:0000-00FF:save\nP:4020::just a comment\nNesPrgRom:0000:irq\nG:2000:PPUCTRL\nC:0000:tiles\n";
+    symbols.parse_mlb(text).unwrap();
+    let bus = rom(2);
+
+    assert_eq!(symbols.label(&bus, 0xc010), Some("nmi"));
+    assert_eq!(symbols.label(&bus, 0x8000), Some("irq"));
+    assert_eq!(symbols.label(&bus, 0x0010), Some("snake_length"));
+    assert_eq!(symbols.label(&bus, 0x6000), Some("save"));
+    assert_eq!(symbols.label(&bus, 0x2000), Some("PPUCTRL"));
+    assert_eq!(symbols.address(&bus, "tiles"), None);
+    assert!(symbols.parse_mlb("P-4010").is_err());
+}
+
+#[test]
+fn test_ca65_debug_files() {
+    let mut symbols = Symbols::new();
+    let text = r#"version	major=2,minor=0
+info	csym=0,file=1,lib=0,line=20,mod=1,scope=1,seg=2,span=10,sym=3,type=2
+seg	id=0,name="CODE",start=0x00C000,size=0x0100,addrsize=absolute,type=ro,oname="game.nes",ooffs=16
+seg	id=1,name="ZEROPAGE",start=0x000000,size=0x0010,addrsize=zeropage,type=rw
+sym	id=0,name="update_snake",addrsize=absolute,scope=0,def=1,ref=3,val=0xC038,seg=0,type=lab
+sym	id=1,name="snake_length",addrsize=zeropage,scope=0,def=2,val=0x3,seg=1,type=lab
+sym	id=2,name="SPEED",addrsize=zeropage,scope=0,def=4,val=0x10,type=equ
+sym	id=3,name="io_base",addrsize=absolute,scope=0,def=5,val=0x4000,seg=0,type=lab
+"#;
+    symbols.parse_dbg(text).unwrap();
+    let bus = rom(1);
+
+    assert_eq!(symbols.label(&bus, 0xc038), Some("update_snake"));
+    assert_eq!(symbols.label(&bus, 0x8038), Some("update_snake"));
+    assert_eq!(symbols.label(&bus, 0x0003), Some("snake_length"));
+    assert_eq!(symbols.label(&bus, 0x4000), Some("io_base"));
+    // constants aren't addresses
+    assert_eq!(symbols.address(&bus, "SPEED"), None);
+}
+
+#[test]
+fn test_labels_in_listings() {
+    let mut symbols = Symbols::new();
+    symbols.parse_nl("$0010#snake_length#\n$0638#update_snake#\n$0600#start#\n", None).unwrap();
+    let mut cpu = CPU::new();
+    cpu.load(assemble("start:\njsr $0638\nlda $10\nbne start\nlda #$10", 0x0600).unwrap());
+    cpu.reset();
+    cpu.bus.poke(0x10, 0x10);
+
+    assert_eq!(trace(&cpu, Columns::DISASM, Region::Ntsc, &symbols), "JSR update_snake");
+    cpu.program_counter = 0x0603;
+    assert_eq!(trace(&cpu, Columns::DISASM, Region::Ntsc, &symbols), "LDA snake_length");
+
+    let mut debugger = Debugger::new();
+    debugger.symbols = symbols;
+    assert_eq!(debugger.disassemble(&cpu, 0x0605), "BNE start");
+    assert_eq!(debugger.disassemble(&cpu, 0x0607), "LDA #$10");
+    assert_eq!(debugger.describe(&cpu, 0x063a), "update_snake+2");
+    assert_eq!(debugger.expr(&cpu, "update_snake + 1").unwrap().eval(&cpu), 0x0639);
+    assert!(debugger.expr(&cpu, "[snake_length] == $10 && a == 0").unwrap().is_true(&cpu));
+    assert!(debugger.expr(&cpu, "missing == 1").is_err());
+}
+
+#[test]
+fn test_names_given_again_move() {
+    let mut symbols = Symbols::new();
+    symbols.parse_nl("$0010#lives#\n$0020#score#\n", None).unwrap();
+    symbols.parse_mlb("R:0030:lives\nP:0004:score\n").unwrap();
+    let bus = rom(1);
+    assert_eq!(symbols.label(&bus, 0x0010), None);
+    assert_eq!(symbols.label(&bus, 0x0020), None);
+    assert_eq!(symbols.describe(&bus, 0x0012), "$0012");
+    assert_eq!(symbols.label(&bus, 0x0030), Some("lives"));
+    assert_eq!(symbols.label(&bus, 0xc004), Some("score"));
+    assert_eq!(symbols.address(&bus, "lives"), Some(0x0030));
+}
@@ -0,0 +1,78 @@
+// Helpers shared by the unit tests.
+
+use crate::asm::assemble;
+use crate::bus::Bus;
+use crate::cartridge::Rom;
+use crate::cpu::CPU;
+use crate::loader::Program;
+use crate::machine::Machine;
+
+// a bare CPU running the assembled source from $0600
+pub fn cpu_with(source: &str) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.load(assemble(source, 0x0600).unwrap());
+    cpu.reset();
+    cpu
+}
+
+// an Easy6502 machine running the assembled source from $0600
+pub fn machine(source: &str) -> Machine {
+    Machine::easy6502(&Program::at(0x0600, assemble(source, 0x0600).unwrap())).unwrap()
+}
+
+// A mapper 0 cartridge image around a PRG image, which is padded with NOPs
+// to whole 16KiB banks.
+pub struct Ines {
+    prg: Vec<u8>,
+    chr_banks: u8,
+    battery: bool,
+}
+
+impl Ines {
+    pub fn new(prg: &[u8]) -> Ines {
+        let mut prg = prg.to_vec();
+        prg.resize(prg.len().div_ceil(0x4000).max(1) * 0x4000, 0xea);
+        Ines { prg, chr_banks: 0, battery: false }
+    }
+
+    // blank 8KiB CHR banks
+    pub fn chr(mut self, banks: u8) -> Ines {
+        self.chr_banks = banks;
+        self
+    }
+
+    pub fn battery(mut self) -> Ines {
+        self.battery = true;
+        self
+    }
+
+    pub fn image(&self) -> Vec<u8> {
+        let flags = if self.battery { 0b10 } else { 0 };
+        let mut raw = vec![b'N', b'E', b'S', 0x1a, (self.prg.len() / 0x4000) as u8, self.chr_banks, flags, 0];
+        raw.resize(16, 0);
+        raw.extend_from_slice(&self.prg);
+        raw.resize(raw.len() + self.chr_banks as usize * 0x2000, 0);
+        raw
+    }
+
+    pub fn rom(&self) -> Rom {
+        Rom::new(&self.image()).unwrap()
+    }
+
+    // a CPU on the cart, after the reset
+    pub fn cpu(&self) -> CPU {
+        let mut cpu = CPU::with_bus(Bus::with_rom(self.rom()));
+        cpu.reset();
+        cpu
+    }
+}
+
+// one PRG bank with the assembled source at $C000, which the reset vector
+// points at
+pub fn prg_at_c000(source: &str) -> Vec<u8> {
+    let mut prg = assemble(source, 0xc000).unwrap();
+    prg.resize(0x4000, 0xea);
+    prg[0x3ffc] = 0x00;
+    prg[0x3ffd] = 0xc0;
+    prg
+}
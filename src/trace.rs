@@ -0,0 +1,260 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use bitflags::bitflags;
+
+use crate::cli::Args;
+use crate::cpu::CPU;
+use crate::disasm::{self, Instruction};
+use crate::opcodes::Mode;
+use crate::region::Region;
+use crate::symbols::Symbols;
+
+#[cfg(test)]
+mod trace_test;
+
+// the most instructions the ring keeps, some hundred MiB of lines
+pub const MAX_RING: usize = 1 << 20;
+
+bitflags! {
+    // what goes on a trace line, always printed in this order:
+    //
+    //   060F  85 02     STA $02      @ 0002 = 00  A:02 X:00 Y:00 P:00 SP:FB  nv-bdizc  DEPTH:4  PPU:  0, 63  CYC:21
+    pub struct Columns: u16 {
+        const PC = 0x001;
+        const BYTES = 0x002;
+        const DISASM = 0x004;
+        const EFFECTIVE = 0x008;
+        const REGISTERS = 0x010;
+        const FLAGS = 0x020;
+        const STACK = 0x040;
+        const PPU = 0x080;
+        const CYCLES = 0x100;
+        const NESTEST = Self::PC.bits | Self::BYTES.bits | Self::DISASM.bits | Self::EFFECTIVE.bits
+            | Self::REGISTERS.bits | Self::PPU.bits | Self::CYCLES.bits;
+    }
+}
+
+const COLUMN_NAMES: &[(&str, Columns)] = &[
+    ("pc", Columns::PC),
+    ("bytes", Columns::BYTES),
+    ("disasm", Columns::DISASM),
+    ("effective", Columns::EFFECTIVE),
+    ("registers", Columns::REGISTERS),
+    ("flags", Columns::FLAGS),
+    ("stack", Columns::STACK),
+    ("ppu", Columns::PPU),
+    ("cycles", Columns::CYCLES),
+    ("nestest", Columns::NESTEST),
+    ("all", Columns::all()),
+];
+
+impl Columns {
+    // a comma separated list of column names, `nestest` and `all` included
+    pub fn parse(list: &str) -> Result<Columns, String> {
+        let mut columns = Columns::empty();
+        for name in list.split(',').map(str::trim) {
+            let (_, column) = COLUMN_NAMES
+                .iter()
+                .find(|(known, _)| known.eq_ignore_ascii_case(name))
+                .ok_or_else(|| format!("unknown trace column: {}", name))?;
+            columns |= *column;
+        }
+        Ok(columns)
+    }
+}
+
+// One line per instruction, taken before it executes; operands with a
+// symbol show the label. A step that takes an interrupt instead shows its
+// name in place of the instruction.
+pub fn trace(cpu: &CPU, columns: Columns, region: Region, symbols: &Symbols) -> String {
+    let mut fields = Vec::new();
+    if columns.contains(Columns::PC) {
+        fields.push(format!("{:04X}", cpu.program_counter));
+    }
+    match cpu.pending_interrupt() {
+        Some(interrupt) => fields.push(interrupt.name().to_string()),
+        None => instruction_fields(cpu, columns, symbols, &mut fields),
+    }
+    state_fields(cpu, columns, region, &mut fields);
+    fields.join("  ").trim_end().to_string()
+}
+
+fn instruction_fields(cpu: &CPU, columns: Columns, symbols: &Symbols, fields: &mut Vec<String>) {
+    let instruction = disasm::decode(|addr| cpu.peek(addr), cpu.program_counter);
+    if columns.contains(Columns::BYTES) {
+        let bytes: Vec<String> = instruction.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        fields.push(format!("{:<8}", bytes.join(" ")));
+    }
+    if columns.contains(Columns::DISASM) {
+        let text = instruction.format_with(|addr| symbols.label(&cpu.bus, addr).map(str::to_string));
+        fields.push(format!("{:<11}", text));
+    }
+    if columns.contains(Columns::EFFECTIVE) {
+        fields.push(format!("{:<11}", effective(cpu, &instruction)));
+    }
+}
+
+fn state_fields(cpu: &CPU, columns: Columns, region: Region, fields: &mut Vec<String>) {
+    if columns.contains(Columns::REGISTERS) {
+        fields.push(format!(
+            "A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            cpu.register_a, cpu.register_x, cpu.register_y, cpu.status, cpu.stack_counter,
+        ));
+    }
+    if columns.contains(Columns::FLAGS) {
+        let flags: String = "NV-BDIZC"
+            .chars()
+            .enumerate()
+            .map(|(i, letter)| match cpu.status & (0x80 >> i) {
+                _ if letter == '-' => letter,
+                0 => letter.to_ascii_lowercase(),
+                _ => letter,
+            })
+            .collect();
+        fields.push(flags);
+    }
+    if columns.contains(Columns::STACK) {
+        fields.push(format!("DEPTH:{}", 0xff - cpu.stack_counter));
+    }
+    // there is no PPU yet, the position is where it would be after this many cycles
+    if columns.contains(Columns::PPU) {
+        let dots = region.ppu_dots(cpu.cycles);
+        let scanline = dots / 341 % region.scanlines();
+        fields.push(format!("PPU:{:>3},{:>3}", scanline, dots % 341));
+    }
+    if columns.contains(Columns::CYCLES) {
+        fields.push(format!("CYC:{}", cpu.cycles));
+    }
+}
+
+// `@ 0203 = 5A`: the address the instruction will access and what it holds now
+fn effective(cpu: &CPU, instruction: &Instruction) -> String {
+    let op = match instruction.op {
+        Some(op) => op,
+        None => return String::new(),
+    };
+    let operand = instruction.operand();
+    let pointer = |at: u8| u16::from_le_bytes([cpu.peek(at as u16), cpu.peek(at.wrapping_add(1) as u16)]);
+    let address = match op.mode {
+        Mode::ZeroPage => operand,
+        Mode::ZeroPageX => (operand as u8).wrapping_add(cpu.register_x) as u16,
+        Mode::ZeroPageY => (operand as u8).wrapping_add(cpu.register_y) as u16,
+        Mode::Absolute if matches!(op.mnemonic, "JMP" | "JSR") => return String::new(),
+        Mode::Absolute => operand,
+        Mode::AbsoluteX => operand.wrapping_add(cpu.register_x as u16),
+        Mode::AbsoluteY => operand.wrapping_add(cpu.register_y as u16),
+        // the pointer's high byte never leaves its page
+        Mode::Indirect => {
+            let hi = (operand & 0xff00) | (operand.wrapping_add(1) & 0x00ff);
+            let target = u16::from_le_bytes([cpu.peek(operand), cpu.peek(hi)]);
+            return format!("@ {:04X}", target);
+        }
+        Mode::IndirectX => pointer((operand as u8).wrapping_add(cpu.register_x)),
+        Mode::IndirectY => pointer(operand as u8).wrapping_add(cpu.register_y as u16),
+        _ => return String::new(),
+    };
+    format!("@ {:04X} = {:02X}", address, cpu.peek(address))
+}
+
+// Writes trace lines to the log while enabled and keeps the last few in a
+// ring, dumped by the frontends when the CPU stops on an error.
+pub struct Tracer {
+    pub columns: Columns,
+    pub region: Region,
+    // runtime switch for the log, the ring always records
+    pub enabled: bool,
+    // only instructions starting in here are traced
+    pub range: Option<RangeInclusive<u16>>,
+    pub symbols: Symbols,
+    log: Option<Box<dyn Write>>,
+    ring: VecDeque<String>,
+    ring_size: usize,
+}
+
+impl Tracer {
+    pub fn new(columns: Columns, log: Option<Box<dyn Write>>, ring_size: usize) -> Tracer {
+        Tracer {
+            columns,
+            region: Region::Ntsc,
+            enabled: true,
+            range: None,
+            symbols: Symbols::new(),
+            log,
+            // grows as it fills, the size is only a bound
+            ring: VecDeque::new(),
+            ring_size,
+        }
+    }
+
+    pub fn from_args(args: &Args) -> Result<Tracer, String> {
+        let log = match &args.trace {
+            Some(path) => {
+                let file = File::create(path).map_err(|e| format!("can't create {}: {}", path, e))?;
+                Some(Box::new(BufWriter::new(file)) as Box<dyn Write>)
+            }
+            None => None,
+        };
+        let mut tracer = Tracer::new(args.trace_columns, log, args.trace_ring);
+        tracer.region = args.region;
+        tracer.range = args.trace_range.clone();
+        for path in &args.symbols {
+            tracer.symbols.load(Path::new(path))?;
+        }
+        Ok(tracer)
+    }
+
+    pub fn has_log(&self) -> bool {
+        self.log.is_some()
+    }
+
+    pub fn trace(&mut self, cpu: &CPU) -> Result<(), String> {
+        let logging = self.enabled && self.log.is_some();
+        if !logging && self.ring_size == 0 {
+            return Ok(());
+        }
+        if let Some(range) = &self.range {
+            if !range.contains(&cpu.program_counter) {
+                return Ok(());
+            }
+        }
+        let line = trace(cpu, self.columns, self.region, &self.symbols);
+        if logging {
+            let log = self.log.as_mut().unwrap();
+            writeln!(log, "{}", line).map_err(|e| format!("can't write trace: {}", e))?;
+        }
+        if self.ring_size > 0 {
+            if self.ring.len() == self.ring_size {
+                self.ring.pop_front();
+            }
+            self.ring.push_back(line);
+        }
+        Ok(())
+    }
+
+    // the last traced lines, oldest first
+    pub fn ring(&self) -> impl Iterator<Item = &str> {
+        self.ring.iter().map(String::as_str)
+    }
+
+    pub fn dump_ring(&self, out: &mut dyn Write) -> io::Result<()> {
+        if self.ring.is_empty() {
+            return Ok(());
+        }
+        writeln!(out, "last {} instructions:", self.ring.len())?;
+        for line in &self.ring {
+            writeln!(out, "{}", line)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), String> {
+        match self.log.as_mut() {
+            Some(log) => log.flush().map_err(|e| format!("can't write trace: {}", e)),
+            None => Ok(()),
+        }
+    }
+}
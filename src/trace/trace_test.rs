@@ -0,0 +1,70 @@
+use super::*;
+use crate::testutil::cpu_with;
+
+#[test]
+fn test_nestest_columns() {
+    let mut cpu = cpu_with("lda $0200,x");
+    cpu.register_x = 3;
+    cpu.status = 0x24;
+    cpu.stack_counter = 0xfd;
+    cpu.mem_write(0x0203, 0x5a);
+    assert_eq!(
+        trace(&cpu, Columns::NESTEST, Region::Ntsc, &Symbols::new()),
+        "0600  BD 00 02  LDA $0200,X  @ 0203 = 5A  A:00 X:03 Y:00 P:24 SP:FD  PPU:  0, 21  CYC:7"
+    );
+}
+
+#[test]
+fn test_flags_stack_and_effective_addresses() {
+    let mut cpu = cpu_with("sta ($10),y\njmp ($02ff)");
+    cpu.status = 0xc3;
+    cpu.stack_counter = 0xfb;
+    cpu.register_y = 0x10;
+    cpu.mem_write_u16(0x0010, 0x02f8);
+    cpu.mem_write(0x0308, 0x77);
+    let columns = Columns::EFFECTIVE | Columns::FLAGS | Columns::STACK;
+    assert_eq!(trace(&cpu, columns, Region::Ntsc, &Symbols::new()), "@ 0308 = 77  NV-bdiZC  DEPTH:4");
+
+    // JMP indirect shows where it goes, with the page wrap of the real chip
+    cpu.program_counter = 0x0602;
+    cpu.mem_write(0x02ff, 0x34);
+    cpu.mem_write(0x0200, 0x12);
+    assert_eq!(trace(&cpu, Columns::DISASM | Columns::EFFECTIVE, Region::Ntsc, &Symbols::new()), "JMP ($02FF)  @ 1234");
+}
+
+#[test]
+fn test_column_names() {
+    assert_eq!(Columns::parse("pc, cycles").unwrap(), Columns::PC | Columns::CYCLES);
+    assert_eq!(Columns::parse("ALL").unwrap(), Columns::all());
+    assert!(Columns::parse("pc,bogus").is_err());
+}
+
+#[test]
+fn test_ring_keeps_the_last_lines_in_range() {
+    let mut cpu = cpu_with("ldx #$05\nloop: dex\nbne loop\nbrk");
+    let mut tracer = Tracer::new(Columns::PC | Columns::DISASM, None, 3);
+    tracer.range = Some(0x0602..=0x0603);
+    tracer.enabled = false;
+    cpu.run_with_callbacks(|cpu| tracer.trace(cpu).unwrap());
+
+    let lines: Vec<&str> = tracer.ring().collect();
+    assert_eq!(lines, ["0603  BNE $0602", "0602  DEX", "0603  BNE $0602"]);
+    let mut dump = Vec::new();
+    tracer.dump_ring(&mut dump).unwrap();
+    assert!(String::from_utf8(dump).unwrap().starts_with("last 3 instructions:\n0603"));
+}
+
+#[test]
+fn test_interrupts_are_traced_by_name() {
+    let mut cpu = cpu_with("nop");
+    cpu.nmi_pending = true;
+    let mut tracer = Tracer::new(Columns::PC | Columns::DISASM | Columns::REGISTERS, None, 2);
+    tracer.trace(&cpu).unwrap();
+    cpu.step().unwrap();
+    cpu.irq_line = true;
+    cpu.status = 0;
+    tracer.trace(&cpu).unwrap();
+
+    let lines: Vec<&str> = tracer.ring().collect();
+    assert_eq!(lines, ["0600  NMI  A:00 X:00 Y:00 P:00 SP:FF", "0000  IRQ  A:00 X:00 Y:00 P:00 SP:FC"]);
+}